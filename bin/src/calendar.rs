@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use ambrogio_reminders::interface::AgendaEntry;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use itertools::Itertools;
+
+pub const MONTH_NAMES: [&str; 12] = [
+    "gennaio",
+    "febbraio",
+    "marzo",
+    "aprile",
+    "maggio",
+    "giugno",
+    "luglio",
+    "agosto",
+    "settembre",
+    "ottobre",
+    "novembre",
+    "dicembre",
+];
+
+const WEEKDAY_NAMES: [&str; 7] = ["Lu", "Ma", "Me", "Gi", "Ve", "Sa", "Do"];
+
+// keeps 31 legend lines well within the 4096 characters of a Telegram message
+const MAX_IDS_PER_DAY: usize = 5;
+
+pub fn month_range(year: i32, month: u32, timezone: &Tz) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let next = first.checked_add_months(chrono::Months::new(1))?;
+    let start_of = |date: NaiveDate| {
+        timezone
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|d| d.with_timezone(&Utc))
+    };
+    Some((start_of(first)?, start_of(next)?))
+}
+
+pub fn render_month(year: i32, month: u32, entries: &[AgendaEntry], timezone: &Tz) -> String {
+    let first = match NaiveDate::from_ymd_opt(year, month, 1) {
+        Some(first) => first,
+        None => return "".to_owned(),
+    };
+    let days = first
+        .checked_add_months(chrono::Months::new(1))
+        .map(|next| next.signed_duration_since(first).num_days() as u32)
+        .unwrap_or(31);
+
+    let mut marked: BTreeMap<u32, Vec<i32>> = BTreeMap::new();
+    for entry in entries {
+        let ids = marked
+            .entry(entry.when().with_timezone(timezone).day())
+            .or_default();
+        if !ids.contains(&entry.reminder_id()) {
+            ids.push(entry.reminder_id());
+        }
+    }
+
+    let header = WEEKDAY_NAMES
+        .iter()
+        .map(|name| format!("{name:>3} "))
+        .join("");
+    let offset = first.weekday().num_days_from_monday() as usize;
+    let cells = std::iter::repeat_n("    ".to_owned(), offset)
+        .chain((1..=days).map(|day| {
+            let marker = if marked.contains_key(&day) {
+                '•'
+            } else {
+                ' '
+            };
+            format!("{day:>3}{marker}")
+        }))
+        .collect::<Vec<_>>();
+    let grid = cells
+        .chunks(7)
+        .map(|week| week.join("").trim_end().to_owned())
+        .join("\n");
+
+    let legend = match marked.len() {
+        0 => "Nessun promemoria in questo mese".to_owned(),
+        _ => marked
+            .iter()
+            .map(|(day, ids)| {
+                let weekday = first
+                    .with_day(*day)
+                    .map(|date| WEEKDAY_NAMES[date.weekday().num_days_from_monday() as usize])
+                    .unwrap_or_default();
+                let others = match ids.len().saturating_sub(MAX_IDS_PER_DAY) {
+                    0 => "".to_owned(),
+                    others => format!(" e altri {others}"),
+                };
+                format!(
+                    "{day:>2} {weekday}: {}{others}",
+                    ids.iter()
+                        .take(MAX_IDS_PER_DAY)
+                        .map(|id| format!("ID {id}"))
+                        .join(", ")
+                )
+            })
+            .join("\n"),
+    };
+
+    let title = MONTH_NAMES[first.month0() as usize];
+    let mut title_chars = title.chars();
+    let title = title_chars
+        .next()
+        .map(|c| c.to_uppercase().chain(title_chars).collect::<String>())
+        .unwrap_or_default();
+
+    format!(
+        "<b>📅 {title} {year}</b>\n<pre>{}\n{grid}\n\n{legend}</pre>",
+        header.trim_end()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use ambrogio_reminders::interface::AgendaEntry;
+    use chrono::{DateTime, Duration, Utc};
+    use chrono_tz::Europe;
+
+    use super::{month_range, render_month};
+
+    fn at(date: &str) -> DateTime<Utc> {
+        date.parse::<DateTime<Utc>>().unwrap()
+    }
+
+    fn entry(when: &str, id: i32) -> AgendaEntry {
+        AgendaEntry::new(at(when), id, Arc::new("ciao".to_owned()))
+    }
+
+    #[test]
+    fn month_range_follows_the_timezone() {
+        assert_eq!(
+            Some((
+                at("2024-08-01T00:00:00+02:00"),
+                at("2024-09-01T00:00:00+02:00")
+            )),
+            month_range(2024, 8, &Europe::Rome)
+        );
+        assert_eq!(
+            Some((
+                at("2024-12-01T00:00:00+01:00"),
+                at("2025-01-01T00:00:00+01:00")
+            )),
+            month_range(2024, 12, &Europe::Rome)
+        );
+        assert_eq!(None, month_range(2024, 13, &Europe::Rome));
+    }
+
+    #[test]
+    fn month_range_across_dst_changes() {
+        let (from, to) = month_range(2024, 3, &Europe::Rome).unwrap();
+        assert_eq!(at("2024-03-01T00:00:00+01:00"), from);
+        assert_eq!(at("2024-04-01T00:00:00+02:00"), to);
+        assert_eq!(Duration::days(31) - Duration::hours(1), to - from);
+
+        let (from, to) = month_range(2024, 10, &Europe::Rome).unwrap();
+        assert_eq!(Duration::days(31) + Duration::hours(1), to - from);
+    }
+
+    #[test]
+    fn renders_the_grid_and_the_legend() {
+        let html = render_month(
+            2024,
+            8,
+            &[
+                entry("2024-08-01T09:00:00+02:00", 7),
+                entry("2024-08-01T18:00:00+02:00", 7),
+                entry("2024-08-31T23:30:00+02:00", 3),
+                entry("2024-08-31T12:00:00+02:00", 7),
+            ],
+            &Europe::Rome,
+        );
+        assert_eq!(
+            "<b>📅 Agosto 2024</b>\n<pre> Lu  Ma  Me  Gi  Ve  Sa  Do\n              1•  2   3   4\n  5   6   7   8   9  10  11\n 12  13  14  15  16  17  18\n 19  20  21  22  23  24  25\n 26  27  28  29  30  31•\n\n 1 Gi: ID 7\n31 Sa: ID 3, ID 7</pre>",
+            html
+        );
+    }
+
+    #[test]
+    fn renders_the_local_day_across_dst() {
+        let html = render_month(2024, 10, &[entry("2024-10-27T23:30:00Z", 1)], &Europe::Rome);
+        assert!(html.ends_with("28 Lu: ID 1</pre>"), "{html}");
+        assert!(html.contains("<b>📅 Ottobre 2024</b>"));
+    }
+
+    #[test]
+    fn renders_an_empty_month() {
+        let html = render_month(2025, 2, &[], &Europe::Rome);
+        assert!(html.ends_with("Nessun promemoria in questo mese</pre>"));
+        assert!(html.contains(" 28\n"));
+        assert!(!html.contains(" 29"));
+    }
+
+    #[test]
+    fn fits_a_telegram_message() {
+        let busy = (0..31)
+            .flat_map(|day| {
+                (0..50).map(move |id| {
+                    AgendaEntry::new(
+                        at("2024-08-01T09:00:00+02:00") + Duration::days(day),
+                        i32::MIN + id,
+                        Arc::new("ciao".to_owned()),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let html = render_month(2024, 8, &busy, &Europe::Rome);
+        assert!(html.chars().count() <= 4096, "{}", html.chars().count());
+        assert!(html.contains(" 1 Gi: ID -2147483648, ID -2147483647, ID -2147483646, ID -2147483645, ID -2147483644 e altri 45\n"));
+    }
+}
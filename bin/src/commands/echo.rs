@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use crate::telegram::TelegramProxy;
+use ambrogio_users::data::Capability;
 use async_trait::async_trait;
 
 use super::{InboundMessage, MessageHandler};
@@ -24,7 +25,14 @@ impl MessageHandler for EchoMessageHandler {
         true
     }
 
-    async fn handle(&self, InboundMessage { user, text }: InboundMessage) -> Result<(), String> {
+    fn capability(&self, _: &InboundMessage) -> Capability {
+        Capability::Chat
+    }
+
+    async fn handle(
+        &self,
+        InboundMessage { user, text, .. }: InboundMessage,
+    ) -> Result<(), String> {
         let (id, name) = (user.id(), user.name());
         let message = format!("{text} a Lei, {name}!");
         self.telegram.send_text_to_user(message, id).await
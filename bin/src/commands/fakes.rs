@@ -0,0 +1,83 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use ambrogio_users::{
+    data::{Permissions, User, UserId},
+    UserRepository,
+};
+use async_trait::async_trait;
+
+use crate::telegram::TelegramProxy;
+
+#[derive(Default)]
+pub struct FakeUserRepository {
+    users: Mutex<HashMap<u64, User>>,
+    permissions: Mutex<HashMap<u64, Permissions>>,
+}
+
+#[async_trait]
+impl UserRepository for FakeUserRepository {
+    async fn get(&self, id: UserId) -> Result<Option<User>, String> {
+        Ok(self.users.lock().unwrap().get(&id.0).cloned())
+    }
+    async fn set(&self, user: User) -> Result<bool, String> {
+        self.users.lock().unwrap().insert(user.id().0, user);
+        Ok(true)
+    }
+    async fn remove(&self, id: UserId) -> Result<bool, String> {
+        self.users.lock().unwrap().remove(&id.0);
+        self.permissions.lock().unwrap().remove(&id.0);
+        Ok(true)
+    }
+    async fn rename(&self, user: User) -> Result<bool, String> {
+        self.set(user).await
+    }
+    async fn find_by_name(&self, name: &str) -> Result<Option<User>, String> {
+        Ok(self
+            .users
+            .lock()
+            .unwrap()
+            .values()
+            .find(|user| matches!(user, User::NamedUser { name: n, .. } if n == name))
+            .cloned())
+    }
+    async fn get_permissions(&self, id: UserId) -> Result<Option<Permissions>, String> {
+        Ok(self.permissions.lock().unwrap().get(&id.0).cloned())
+    }
+    async fn set_permissions(&self, id: UserId, permissions: &Permissions) -> Result<bool, String> {
+        self.permissions
+            .lock()
+            .unwrap()
+            .insert(id.0, permissions.clone());
+        Ok(true)
+    }
+}
+
+pub struct SilentTelegram;
+
+#[async_trait]
+impl TelegramProxy for SilentTelegram {
+    async fn send_text_to_user(&self, _: String, _: UserId) -> Result<(), String> {
+        Ok(())
+    }
+    async fn send_html(&self, _: String, _: UserId) -> Result<(), String> {
+        Ok(())
+    }
+    async fn send_gif_from_url(&self, _: &str, _: UserId) -> Result<(), String> {
+        Ok(())
+    }
+    async fn send_local_video(&self, _: PathBuf, _: UserId) -> Result<(), String> {
+        Ok(())
+    }
+    async fn send_local_audio(&self, _: PathBuf, _: UserId) -> Result<(), String> {
+        Ok(())
+    }
+    async fn send_photo(&self, _: &str, _: String, _: UserId) -> Result<(), String> {
+        Ok(())
+    }
+    async fn send_document(&self, _: &str, _: String, _: UserId) -> Result<(), String> {
+        Ok(())
+    }
+    async fn send_voice(&self, _: &str, _: String, _: UserId) -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use std::{sync::Arc, time::Duration};
 
 use crate::telegram::TelegramProxy;
+use ambrogio_users::data::Capability;
 use async_trait::async_trait;
 
 use super::{InboundMessage, MessageHandler};
@@ -25,6 +26,10 @@ impl MessageHandler for FerreroHandler {
         text.contains("languorino")
     }
 
+    fn capability(&self, _: &InboundMessage) -> Capability {
+        Capability::Ferrero
+    }
+
     async fn handle(&self, InboundMessage { user, .. }: InboundMessage) -> Result<(), String> {
         let id = user.id();
 
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use crate::telegram::TelegramProxy;
+use ambrogio_users::data::Capability;
 use async_trait::async_trait;
 use chrono::NaiveDate;
 use itertools::Itertools;
@@ -22,7 +23,14 @@ impl MessageHandler for ForecastHandler {
         self.regex.is_match(&msg.text)
     }
 
-    async fn handle(&self, InboundMessage { user, text }: InboundMessage) -> Result<(), String> {
+    fn capability(&self, _: &InboundMessage) -> Capability {
+        Capability::Forecast
+    }
+
+    async fn handle(
+        &self,
+        InboundMessage { user, text, .. }: InboundMessage,
+    ) -> Result<(), String> {
         let (maybe_city, day_in_future) = Self::parse(text);
         let city = maybe_city.as_deref().unwrap_or("Roma");
         let req = day_in_future
@@ -59,7 +67,7 @@ impl ForecastHandler {
         }
     }
 
-    fn render_forecast(meteo: Meteo, city: &str, last_only: bool) -> Vec<String> {
+    pub fn render_forecast(meteo: Meteo, city: &str, last_only: bool) -> Vec<String> {
         let now = chrono::Utc::now();
         let header = vec![format!(
             "Meteo per \"{}\"\nLocalità: {} ({})",
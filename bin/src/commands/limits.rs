@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use ambrogio_users::data::{Capability, Permissions, Role, UserId};
+use async_trait::async_trait;
+use regex::Regex;
+
+use crate::{
+    quotas::{Limits, Quotas, LIMIT_NAMES},
+    telegram::TelegramProxy,
+};
+
+use super::{InboundMessage, MessageHandler};
+
+pub struct LimitsHandler {
+    telegram: Arc<dyn TelegramProxy + Send + Sync + 'static>,
+    quotas: Arc<Quotas>,
+    regex: Regex,
+}
+
+impl LimitsHandler {
+    pub fn new<Proxy>(telegram: Arc<Proxy>, quotas: Arc<Quotas>) -> Self
+    where
+        Proxy: TelegramProxy + Send + Sync + 'static,
+    {
+        Self {
+            telegram,
+            quotas,
+            regex: Regex::new(r"(?i)^limiti\s+\d+").unwrap(),
+        }
+    }
+
+    async fn execute(&self, target: UserId, arguments: &[String]) -> String {
+        let outcome = match arguments {
+            [] => Ok(self.quotas.limits_of(target).await),
+            [reset] if reset == "predefiniti" => self.quotas.reset_limits(target).await,
+            [name, value] => match value.parse::<u64>() {
+                Ok(value) => self.quotas.set_limit(target, name, value).await,
+                Err(_) => Err(format!("\"{value}\" non è un numero valido")),
+            },
+            _ => Err(help()),
+        };
+        match outcome {
+            Ok(limits) => render(target, &limits),
+            Err(e) => format!("❌ {e}"),
+        }
+    }
+}
+
+#[async_trait]
+impl MessageHandler for LimitsHandler {
+    fn can_accept(&self, msg: &InboundMessage) -> bool {
+        self.regex.is_match(&msg.text)
+    }
+
+    fn capability(&self, _: &InboundMessage) -> Capability {
+        Capability::Users
+    }
+
+    fn allows(&self, msg: &InboundMessage, permissions: &Permissions) -> bool {
+        permissions.allows(self.capability(msg)) && allows(msg, permissions)
+    }
+
+    async fn handle(
+        &self,
+        InboundMessage { user, text, .. }: InboundMessage,
+    ) -> Result<(), String> {
+        let tokens = text
+            .split_whitespace()
+            .map(|token| token.to_lowercase())
+            .collect::<Vec<_>>();
+        let target = tokens[1]
+            .parse::<u64>()
+            .map(UserId)
+            .map_err(|e| format!("{e}"))?;
+        let message = self.execute(target, &tokens[2..]).await;
+
+        self.telegram.send_text_to_user(message, user.id()).await
+    }
+}
+
+// quotas are for admins to hand out, and never to themselves
+fn allows(msg: &InboundMessage, permissions: &Permissions) -> bool {
+    let target = msg
+        .text
+        .split_whitespace()
+        .nth(1)
+        .and_then(|token| token.parse::<u64>().ok())
+        .map(UserId);
+    permissions.role == Role::Admin && target != Some(msg.user.id())
+}
+
+fn render(target: UserId, limits: &Limits) -> String {
+    format!("Limiti dell'utente {}:\n{}", target.0, limits.render())
+}
+
+fn help() -> String {
+    format!(
+        "Scriva `limiti <ID>` per vedere i limiti di un utente, `limiti <ID> <{}> <valore>` per cambiarne uno, `limiti <ID> predefiniti` per tornare ai valori predefiniti",
+        LIMIT_NAMES.join("|")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use ambrogio_users::data::{Capability, Permissions, Role, User, UserId};
+
+    use super::allows;
+    use crate::commands::InboundMessage;
+
+    fn message(id: u64, text: &str) -> InboundMessage {
+        InboundMessage {
+            user: User::SimpleUser { id: UserId(id) },
+            text: text.to_owned(),
+            attachment: None,
+        }
+    }
+
+    #[test]
+    fn only_admins_change_limits_of_others() {
+        let mut manager = Permissions::new(Role::Member);
+        manager.grant(Capability::Users);
+        let admin = Permissions::new(Role::Admin);
+
+        assert!(!allows(&message(1, "limiti 2 comandi 100"), &manager));
+        assert!(!allows(&message(1, "limiti 1 comandi 100"), &manager));
+        assert!(!allows(&message(1, "limiti 1 comandi 100"), &admin));
+        assert!(allows(&message(1, "limiti 2 comandi 100"), &admin));
+    }
+}
@@ -1,22 +1,141 @@
 pub mod echo;
+#[cfg(test)]
+pub mod fakes;
 pub mod ferrero;
 pub mod forecast;
+pub mod limits;
+pub mod permissions;
 pub mod reminders;
 pub mod shutdown;
 pub mod users;
 pub mod youtube;
 
-use ambrogio_users::data::User;
+use ambrogio_reminders::interface::Attachment;
+use ambrogio_users::{
+    data::{Capability, Permissions, Role, User},
+    UserRepository,
+};
 use async_trait::async_trait;
 
 #[derive(Debug)]
 pub struct InboundMessage {
     pub user: User,
     pub text: String,
+    pub attachment: Option<Attachment>,
 }
 
 #[async_trait]
 pub trait MessageHandler {
     fn can_accept(&self, msg: &InboundMessage) -> bool;
+    fn capability(&self, msg: &InboundMessage) -> Capability;
+    fn allows(&self, msg: &InboundMessage, permissions: &Permissions) -> bool {
+        permissions.allows(self.capability(msg))
+    }
     async fn handle(&self, msg: InboundMessage) -> Result<(), String>;
 }
+
+pub async fn authenticate_user(
+    message: InboundMessage,
+    repo: &(dyn UserRepository + Send + Sync),
+) -> Result<InboundMessage, String> {
+    let user_id = message.user.id();
+
+    if let User::SuperUser { .. } = message.user {
+        return Ok(message);
+    }
+
+    let user = repo
+        .get(user_id)
+        .await?
+        .ok_or(format!("Utente sconosciuto {}", user_id.0))?;
+
+    let user = match (user, message.user) {
+        (User::SimpleUser { .. }, named @ User::NamedUser { .. }) => {
+            repo.set(named.clone()).await?;
+            named
+        }
+        (User::NamedUser { name, .. }, User::NamedUser { id, name: new_name })
+            if name != new_name =>
+        {
+            let renamed = User::NamedUser { id, name: new_name };
+            repo.rename(renamed.clone()).await?;
+            renamed
+        }
+        (user, _) => user,
+    };
+
+    Ok(InboundMessage {
+        user,
+        text: message.text,
+        attachment: message.attachment,
+    })
+}
+
+pub async fn is_authorized(
+    message: &InboundMessage,
+    handler: &(dyn MessageHandler + Send + Sync),
+    repo: &(dyn UserRepository + Send + Sync),
+) -> bool {
+    let user_id = message.user.id();
+    let permissions = match message.user {
+        User::SuperUser { .. } => Permissions::new(Role::Admin),
+        _ => repo
+            .get_permissions(user_id)
+            .await
+            .inspect_err(|e| tracing::error!("Unable to read permissions of {}: {e}", user_id.0))
+            .ok()
+            .flatten()
+            .unwrap_or_default(),
+    };
+    let allowed = handler.allows(message, &permissions);
+    if !allowed {
+        tracing::info!(
+            user = user_id.0,
+            capability = handler.capability(message).name(),
+            "Command is not authorized"
+        );
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod test {
+    use ambrogio_users::{
+        data::{Permissions, Role, User, UserId},
+        UserRepository,
+    };
+
+    use super::{authenticate_user, fakes::FakeUserRepository, InboundMessage};
+
+    #[tokio::test]
+    async fn renaming_keeps_permissions() {
+        let repo = FakeUserRepository::default();
+        let id = UserId(1);
+        repo.set(User::NamedUser {
+            id,
+            name: "vecchio".to_owned(),
+        })
+        .await
+        .unwrap();
+        repo.set_permissions(id, &Permissions::new(Role::Admin))
+            .await
+            .unwrap();
+
+        let message = InboundMessage {
+            user: User::NamedUser {
+                id,
+                name: "nuovo".to_owned(),
+            },
+            text: "ciao".to_owned(),
+            attachment: None,
+        };
+        authenticate_user(message, &repo).await.unwrap();
+
+        assert!(repo.find_by_name("nuovo").await.unwrap().is_some());
+        assert!(repo.find_by_name("vecchio").await.unwrap().is_none());
+        assert_eq!(
+            repo.get_permissions(id).await.unwrap().map(|p| p.role),
+            Some(Role::Admin)
+        );
+    }
+}
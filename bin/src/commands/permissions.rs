@@ -0,0 +1,289 @@
+use std::sync::Arc;
+
+use ambrogio_users::{
+    data::{Capability, Permissions, Role, User, UserId},
+    UserRepository,
+};
+use async_trait::async_trait;
+use itertools::Itertools;
+use regex::Regex;
+
+use crate::telegram::TelegramProxy;
+
+use super::{InboundMessage, MessageHandler};
+
+pub struct PermissionsHandler {
+    telegram: Arc<dyn TelegramProxy + Send + Sync + 'static>,
+    repo: Arc<dyn UserRepository + Send + Sync + 'static>,
+    regex: Regex,
+}
+
+impl PermissionsHandler {
+    pub fn new<Proxy, Repository>(telegram: Arc<Proxy>, repo: Arc<Repository>) -> Self
+    where
+        Proxy: TelegramProxy + Send + Sync + 'static,
+        Repository: UserRepository + Send + Sync + 'static,
+    {
+        Self {
+            telegram,
+            repo,
+            regex: Regex::new(r"(?i)^permessi\s+\d+").unwrap(),
+        }
+    }
+
+    async fn is_admin(&self, user: &User) -> Result<bool, String> {
+        Ok(match user {
+            User::SuperUser { .. } => true,
+            _ => self
+                .repo
+                .get_permissions(user.id())
+                .await?
+                .is_some_and(|permissions| permissions.role == Role::Admin),
+        })
+    }
+
+    async fn execute(
+        &self,
+        actor: &User,
+        target: UserId,
+        arguments: &[String],
+    ) -> Result<Permissions, String> {
+        let mut permissions = self.repo.get_permissions(target).await?.unwrap_or_default();
+        if arguments.is_empty() {
+            return Ok(permissions);
+        }
+        if permissions.role == Role::Admin && !self.is_admin(actor).await? {
+            return Err("Solo un admin può cambiare i permessi di un altro admin".to_owned());
+        }
+        match arguments {
+            [action, role] if action == "ruolo" => {
+                let role = Role::from_name(role)
+                    .ok_or_else(|| format!("Non conosco il ruolo \"{role}\""))?;
+                permissions = Permissions::new(role);
+            }
+            [action, capability] if action == "concedi" || action == "revoca" => {
+                let capability = Capability::from_name(capability)
+                    .ok_or_else(|| format!("Non conosco il permesso \"{capability}\""))?;
+                match action.as_str() {
+                    "concedi" => permissions.grant(capability),
+                    _ => permissions.revoke(capability),
+                }
+            }
+            _ => return Err(help()),
+        }
+        self.repo.set_permissions(target, &permissions).await?;
+        Ok(permissions)
+    }
+}
+
+#[async_trait]
+impl MessageHandler for PermissionsHandler {
+    fn can_accept(&self, msg: &InboundMessage) -> bool {
+        self.regex.is_match(&msg.text)
+    }
+
+    fn capability(&self, _: &InboundMessage) -> Capability {
+        Capability::Users
+    }
+
+    // nobody changes their own permissions, and only admins hand out roles or what members lack
+    fn allows(&self, msg: &InboundMessage, permissions: &Permissions) -> bool {
+        if !permissions.allows(self.capability(msg)) {
+            return false;
+        }
+        let tokens = tokenize(&msg.text);
+        let target = tokens[1].parse::<u64>().ok().map(UserId);
+        match &tokens[2..] {
+            [] => true,
+            _ if target == Some(msg.user.id()) => false,
+            [action, _] if action == "ruolo" => permissions.role == Role::Admin,
+            [_, capability]
+                if Capability::from_name(capability).is_some_and(|capability| {
+                    !Role::Member.capabilities().contains(&capability)
+                }) =>
+            {
+                permissions.role == Role::Admin
+            }
+            _ => true,
+        }
+    }
+
+    async fn handle(
+        &self,
+        InboundMessage { user, text, .. }: InboundMessage,
+    ) -> Result<(), String> {
+        let tokens = tokenize(&text);
+        let target = tokens[1]
+            .parse::<u64>()
+            .map(UserId)
+            .map_err(|e| format!("{e}"))?;
+        let message = match self.execute(&user, target, &tokens[2..]).await {
+            Ok(permissions) => render(target, &permissions),
+            Err(e) => format!("❌ {e}"),
+        };
+
+        self.telegram.send_text_to_user(message, user.id()).await
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+fn render(target: UserId, permissions: &Permissions) -> String {
+    let capabilities = permissions
+        .capabilities()
+        .iter()
+        .map(|capability| format!("• {}", capability.name()))
+        .join("\n");
+    format!(
+        "Permessi dell'utente {} (ruolo {}):\n{capabilities}",
+        target.0,
+        permissions.role.name()
+    )
+}
+
+fn help() -> String {
+    format!(
+        "Scriva `permessi <ID>` per vedere i permessi di un utente, `permessi <ID> ruolo <admin|member|guest>` per assegnargli un ruolo, `permessi <ID> <concedi|revoca> <{}>` per cambiare un singolo permesso",
+        Capability::ALL.iter().map(|capability| capability.name()).join("|")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use ambrogio_users::{
+        data::{Capability, Permissions, Role, User, UserId},
+        UserRepository,
+    };
+
+    use super::PermissionsHandler;
+    use crate::commands::{
+        fakes::{FakeUserRepository, SilentTelegram},
+        is_authorized, InboundMessage, MessageHandler,
+    };
+
+    async fn setup() -> (PermissionsHandler, Arc<FakeUserRepository>) {
+        let repo = Arc::new(FakeUserRepository::default());
+        let mut manager = Permissions::new(Role::Member);
+        manager.grant(Capability::Users);
+        repo.set_permissions(UserId(1), &manager).await.unwrap();
+        repo.set_permissions(UserId(2), &Permissions::new(Role::Admin))
+            .await
+            .unwrap();
+        let handler = PermissionsHandler::new(Arc::new(SilentTelegram), repo.clone());
+        (handler, repo)
+    }
+
+    fn message(user: User, text: &str) -> InboundMessage {
+        InboundMessage {
+            user,
+            text: text.to_owned(),
+            attachment: None,
+        }
+    }
+
+    fn simple(id: u64) -> User {
+        User::SimpleUser { id: UserId(id) }
+    }
+
+    #[tokio::test]
+    async fn nobody_changes_their_own_permissions() {
+        let (handler, repo) = setup().await;
+        for (user, text) in [
+            (simple(1), "permessi 1 ruolo admin"),
+            (simple(1), "permessi 1 concedi spegnimento"),
+            (simple(2), "permessi 2 revoca utenti"),
+            (
+                User::SuperUser {
+                    id: UserId(9),
+                    powers: (),
+                },
+                "permessi 9 ruolo guest",
+            ),
+        ] {
+            assert!(
+                !is_authorized(&message(user, text), &handler, repo.as_ref()).await,
+                "{text}"
+            );
+        }
+        assert!(is_authorized(&message(simple(1), "permessi 1"), &handler, repo.as_ref()).await);
+    }
+
+    #[tokio::test]
+    async fn only_admins_assign_roles_and_user_management() {
+        let (handler, repo) = setup().await;
+        for text in [
+            "permessi 3 ruolo admin",
+            "permessi 3 concedi utenti",
+            "permessi 4 revoca utenti",
+            "permessi 3 concedi spegnimento",
+            "permessi 3 concedi gestione-promemoria",
+        ] {
+            assert!(
+                !is_authorized(&message(simple(1), text), &handler, repo.as_ref()).await,
+                "{text}"
+            );
+            assert!(
+                is_authorized(&message(simple(2), text), &handler, repo.as_ref()).await,
+                "{text}"
+            );
+        }
+        assert!(
+            is_authorized(
+                &message(simple(1), "permessi 3 concedi download"),
+                &handler,
+                repo.as_ref()
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn requires_user_management() {
+        let (handler, repo) = setup().await;
+        assert!(!is_authorized(&message(simple(3), "permessi 4"), &handler, repo.as_ref()).await);
+        assert!(
+            !is_authorized(
+                &message(simple(3), "permessi 4 concedi download"),
+                &handler,
+                repo.as_ref()
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn only_admins_change_the_permissions_of_admins() {
+        let (handler, repo) = setup().await;
+        repo.set_permissions(UserId(3), &Permissions::new(Role::Admin))
+            .await
+            .unwrap();
+        handler
+            .handle(message(simple(1), "permessi 2 revoca download"))
+            .await
+            .unwrap();
+        assert!(repo
+            .get_permissions(UserId(2))
+            .await
+            .unwrap()
+            .unwrap()
+            .allows(Capability::Downloads));
+
+        handler
+            .handle(message(simple(2), "permessi 3 ruolo member"))
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(Role::Member),
+            repo.get_permissions(UserId(3))
+                .await
+                .unwrap()
+                .map(|p| p.role)
+        );
+    }
+}
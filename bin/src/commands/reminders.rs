@@ -1,46 +1,143 @@
 use std::sync::Arc;
 
-use ambrogio_reminders::interface::{try_parse, Reminder, ReminderDefinition, ReminderEngine};
-use ambrogio_users::data::User;
+use ambrogio_reminders::interface::{
+    day_of, explain, try_parse, AgendaEntry, AgendaSubscription, Attachment, Conflict,
+    DeliveryOutcome, Diagnostic, EngineStatus, Field, Firing, ParseOutcome, Payload, QuietHours,
+    Reminder, ReminderDefinition, ReminderEngine, ReminderStats, TimeExpr,
+};
+use ambrogio_users::{
+    data::{Capability, User},
+    UserRepository,
+};
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
-use chrono_tz::Europe;
+use chrono::{DateTime, Datelike, NaiveTime, Utc};
+use chrono_tz::{Europe, Tz, TZ_VARIANTS};
 use itertools::Itertools;
 use regex::Regex;
 
-use crate::telegram::TelegramProxy;
+use crate::{
+    calendar::{month_range, render_month, MONTH_NAMES},
+    quotas::Quotas,
+    telegram::{paginate, TelegramProxy},
+};
 
 use super::{InboundMessage, MessageHandler};
 
+const MAX_AGENDA_TIMES: usize = 5;
+
 enum Command {
-    Delete { reminder_id: i32 },
-    Create { definition: ReminderDefinition },
-    Read { reminder_id: i32 },
-    ReadAll,
+    Delete {
+        reminder_id: i32,
+    },
+    DeleteTagged {
+        tag: String,
+    },
+    Create {
+        definition: Box<ReminderDefinition>,
+        recipients: Vec<String>,
+        tags: Vec<String>,
+        urgent: bool,
+        warnings: Vec<Diagnostic>,
+    },
+    Read {
+        reminder_id: i32,
+    },
+    ReadAll {
+        tag: Option<String>,
+    },
+    Search {
+        query: String,
+    },
+    QuietHours {
+        window: Option<Option<(NaiveTime, NaiveTime)>>,
+        timezone: Option<Tz>,
+    },
+    Agenda {
+        subscription: Option<Option<AgendaSubscription>>,
+    },
+    Calendar {
+        month: Option<u32>,
+        year: Option<i32>,
+    },
+    History,
+    Stats,
+    Acknowledge {
+        reminder_id: i32,
+    },
+    Sweep,
+    Status,
+    Debug {
+        expression: Option<TimeExpr>,
+        outcome: Box<ParseOutcome>,
+    },
     JustAnswer(String),
 }
 
-impl Command {
-    fn new_create(definition: ReminderDefinition) -> Self {
-        Self::Create { definition }
-    }
-}
-
 pub struct RemindersHandler {
     telegram: Arc<dyn TelegramProxy + Send + Sync + 'static>,
     reminder_engine: Arc<ReminderEngine>,
+    users: Arc<dyn UserRepository + Send + Sync + 'static>,
+    quotas: Arc<Quotas>,
     regex: Regex,
 }
 
 impl RemindersHandler {
-    pub fn new<Proxy>(telegram: Arc<Proxy>, engine: Arc<ReminderEngine>) -> Self
+    pub fn new<Proxy, Repository>(
+        telegram: Arc<Proxy>,
+        engine: Arc<ReminderEngine>,
+        users: Arc<Repository>,
+        quotas: Arc<Quotas>,
+    ) -> Self
     where
         Proxy: TelegramProxy + Send + Sync + 'static,
+        Repository: UserRepository + Send + Sync + 'static,
     {
         Self {
             telegram,
             reminder_engine: engine,
-            regex: Regex::new(r"(?i)^(ricordami\s+|scordati\s+|promemoria)").unwrap(),
+            users,
+            quotas,
+            regex: Regex::new(r"(?i)^(ricordami\s+|scordati\s+|promemoria|stato\s+promemoria)")
+                .unwrap(),
+        }
+    }
+
+    async fn check_quotas(
+        &self,
+        user: &User,
+        definition: &ReminderDefinition,
+    ) -> Result<(), String> {
+        let active = self.reminder_engine.active_count(&user.id().0);
+        let interval = definition.schedule().shortest_interval(&Utc::now());
+        self.quotas.check_reminder(user, active, interval).await
+    }
+
+    // the timezone the user already picked, for quiet hours set without one
+    fn timezone_of(&self, user_id: &u64) -> Tz {
+        self.reminder_engine
+            .quiet_hours(user_id)
+            .map(|quiet_hours| quiet_hours.timezone())
+            .or_else(|| {
+                self.reminder_engine
+                    .agenda_subscription(user_id)
+                    .map(|subscription| subscription.timezone())
+            })
+            .unwrap_or(Europe::Rome)
+    }
+
+    async fn resolve_recipients(&self, names: &[String]) -> Result<Vec<u64>, Vec<String>> {
+        let mut ids = vec![];
+        let mut unknown = vec![];
+        for name in names {
+            match self.users.find_by_name(name).await {
+                Ok(Some(user)) => ids.push(user.id().0),
+                _ => unknown.push(format!("@{name}")),
+            }
+        }
+        if unknown.is_empty() {
+            Ok(ids)
+        } else {
+            Err(unknown)
         }
     }
 }
@@ -51,40 +148,106 @@ impl MessageHandler for RemindersHandler {
         self.regex.is_match(&msg.text)
     }
 
-    async fn handle(&self, InboundMessage { user, text }: InboundMessage) -> Result<(), String> {
+    fn capability(&self, msg: &InboundMessage) -> Capability {
+        match is_admin_command(&msg.text) {
+            true => Capability::RemindersAdmin,
+            false => Capability::Reminders,
+        }
+    }
+
+    async fn handle(
+        &self,
+        InboundMessage {
+            user,
+            text,
+            attachment,
+        }: InboundMessage,
+    ) -> Result<(), String> {
         let user_id = user.id();
-        let msg = match into_command(&text, user) {
+        let msg = match into_command(&text, user.clone(), attachment) {
             Command::Delete { reminder_id } => {
-                if self.reminder_engine.defuse(user_id.0, reminder_id).await {
+                let is_shared = self
+                    .reminder_engine
+                    .get(&user_id.0, &reminder_id)
+                    .is_some_and(|rem| rem.reminder_id().0 != user_id.0);
+                if is_shared {
+                    if self
+                        .reminder_engine
+                        .unsubscribe(user_id.0, reminder_id)
+                        .await
+                    {
+                        format!("Non riceverà più il promemoria con ID {reminder_id}")
+                    } else {
+                        format!(
+                            "Non sono riuscito a disiscriverla dal promemoria con ID {reminder_id}"
+                        )
+                    }
+                } else if self.reminder_engine.defuse(user_id.0, reminder_id).await {
                     format!("Promemoria con ID {reminder_id} eliminato")
                 } else {
                     format!("Non sono riuscito a terminare il promemoria con ID {reminder_id}")
                 }
             }
-            Command::Create { definition } => {
-                if let Some(id) = self.reminder_engine.add(definition).await {
-                    format!("Promemoria creato con ID {id}")
-                } else {
-                    "Non sono riuscito a creare un promemoria".to_string()
-                }
-            }
+            Command::Create {
+                definition,
+                recipients,
+                tags,
+                urgent,
+                warnings,
+            } => match self.check_quotas(&user, &definition).await {
+                Err(limit) => limit,
+                Ok(()) => match self.resolve_recipients(&recipients).await {
+                    Err(unknown) => format!(
+                        "Non conosco questi utenti, non ho creato il promemoria: {}",
+                        unknown.join(", ")
+                    ),
+                    Ok(recipients) => {
+                        let definition = (*definition)
+                            .with_recipients(recipients)
+                            .with_tags(tags)
+                            .with_urgent(urgent);
+                        if let Some(id) = self.reminder_engine.add(definition).await {
+                            match warnings.as_slice() {
+                                [] => format!("Promemoria creato con ID {id}"),
+                                warnings => format!(
+                                    "Promemoria creato con ID {id}\n⚠️ {}",
+                                    render_diagnostics(warnings)
+                                ),
+                            }
+                        } else {
+                            "Non sono riuscito a creare un promemoria".to_string()
+                        }
+                    }
+                },
+            },
             Command::Read { reminder_id } => self
                 .reminder_engine
                 .get(&user_id.0, &reminder_id)
                 .as_ref()
                 .map(render_full)
                 .unwrap_or_else(|| format!("Non ho trovato alcun promemoria con ID {reminder_id}")),
-            Command::ReadAll => {
-                let reminders = self
-                    .reminder_engine
-                    .get_all(&user_id.0)
-                    .values()
+            Command::DeleteTagged { tag } => {
+                match self.reminder_engine.defuse_tagged(user_id.0, &tag).await {
+                    0 => format!("Non ho trovato alcun promemoria con etichetta #{tag}"),
+                    n => format!("Ho eliminato {n} promemoria con etichetta #{tag}"),
+                }
+            }
+            Command::ReadAll { tag } => {
+                let reminders = match tag {
+                    Some(tag) => self.reminder_engine.get_tagged(&user_id.0, &tag),
+                    None => self.reminder_engine.get_all(&user_id.0),
+                };
+                let reminders = reminders
+                    .iter()
                     .sorted_by_key(|rem| {
                         rem.current_tick()
                             .map(|d| d.with_timezone(&Utc))
                             .unwrap_or(DateTime::<Utc>::MAX_UTC)
                     })
-                    .map(|rem| format!("• {}", render_line(rem)))
+                    .map(|rem| match rem.reminder_id().0 == user_id.0 {
+                        true => format!("• {}", render_line(rem)),
+                        false => format!("• 👥 {}", render_line(rem)),
+                    })
                     .collect::<Vec<_>>();
                 if !reminders.is_empty() {
                     let pages = reminders.chunks(20).collect::<Vec<_>>();
@@ -102,6 +265,130 @@ impl MessageHandler for RemindersHandler {
                 }
                 "Non sono riuscito a trovare alcun promemoria".to_string()
             }
+            Command::Search { query } => {
+                let reminders = self
+                    .reminder_engine
+                    .search(&user_id.0, &query)
+                    .iter()
+                    .take(20)
+                    .map(|rem| format!("• {}", render_line(rem)))
+                    .join("\n");
+                if reminders.is_empty() {
+                    format!("Non ho trovato alcun promemoria per \"{query}\"")
+                } else {
+                    format!("Promemoria trovati per \"{query}\":\n{reminders}")
+                }
+            }
+            Command::QuietHours { window: None, .. } => {
+                match self.reminder_engine.quiet_hours(&user_id.0) {
+                    Some(quiet_hours) => format!(
+                        "Ore di silenzio: dalle {} alle {} ({})",
+                        quiet_hours.start().format("%H:%M"),
+                        quiet_hours.end().format("%H:%M"),
+                        quiet_hours.timezone().name()
+                    ),
+                    None => "Non ha impostato ore di silenzio".to_string(),
+                }
+            }
+            Command::QuietHours {
+                window: Some(window),
+                timezone,
+            } => {
+                let enabled = window.is_some();
+                let timezone = timezone.unwrap_or_else(|| self.timezone_of(&user_id.0));
+                let quiet_hours = window.map(|(start, end)| QuietHours::new(start, end, timezone));
+                match (
+                    self.reminder_engine
+                        .set_quiet_hours(user_id.0, quiet_hours)
+                        .await,
+                    enabled,
+                ) {
+                    (true, true) => "Ore di silenzio impostate".to_string(),
+                    (true, false) => "Ore di silenzio disattivate".to_string(),
+                    (false, _) => "Non sono riuscito ad aggiornare le ore di silenzio".to_string(),
+                }
+            }
+            Command::Agenda { subscription: None } => {
+                let timezone = self
+                    .reminder_engine
+                    .agenda_subscription(&user_id.0)
+                    .map(|subscription| subscription.timezone())
+                    .unwrap_or(Europe::Rome);
+                let (from, to) = day_of(&Utc::now(), &timezone);
+                let entries = self.reminder_engine.agenda(&user_id.0, &from, &to);
+                for page in render_agenda("", &entries) {
+                    self.telegram.send_text_to_user(page, user_id).await?;
+                }
+                return Ok(());
+            }
+            Command::Agenda {
+                subscription: Some(subscription),
+            } => {
+                let enabled = subscription.is_some();
+                match (
+                    self.reminder_engine
+                        .subscribe_agenda(user_id.0, subscription)
+                        .await,
+                    enabled,
+                ) {
+                    (true, true) => "Le invierò l'agenda ogni giorno".to_string(),
+                    (true, false) => "Non le invierò più l'agenda".to_string(),
+                    (false, _) => "Non sono riuscito ad aggiornare l'agenda".to_string(),
+                }
+            }
+            Command::Calendar { month, year } => {
+                let now = Utc::now().with_timezone(&Europe::Rome);
+                let month = month.unwrap_or(now.month());
+                let year = year.unwrap_or(if month < now.month() {
+                    now.year() + 1
+                } else {
+                    now.year()
+                });
+                match month_range(year, month, &Europe::Rome) {
+                    Some((from, to)) => {
+                        let entries = self.reminder_engine.agenda(&user_id.0, &from, &to);
+                        let html = render_month(year, month, &entries, &Europe::Rome);
+                        return self.telegram.send_html(html, user_id).await;
+                    }
+                    None => "Non riesco a costruire il calendario di quel mese".to_owned(),
+                }
+            }
+            Command::History => {
+                let firings = self.reminder_engine.history(user_id.0, 20).await;
+                if firings.is_empty() {
+                    "Nessun promemoria è ancora scattato".to_string()
+                } else {
+                    let list = firings
+                        .iter()
+                        .map(|firing| format!("• {}", render_firing(firing)))
+                        .join("\n");
+                    format!("Ultimi promemoria scattati:\n{list}")
+                }
+            }
+            Command::Stats => render_stats(&self.reminder_engine.stats(user_id.0).await),
+            Command::Acknowledge { reminder_id } => {
+                if self
+                    .reminder_engine
+                    .acknowledge(user_id.0, reminder_id)
+                    .await
+                {
+                    format!("Promemoria con ID {reminder_id} segnato come fatto")
+                } else {
+                    format!("Non ho trovato alcun invio del promemoria con ID {reminder_id}")
+                }
+            }
+            Command::Sweep => {
+                let cleaned = self.reminder_engine.sweep().await;
+                format!("Ho archiviato {cleaned} promemoria scaduti")
+            }
+            Command::Status => render_status(&self.reminder_engine.status().await, &Utc::now()),
+            Command::Debug {
+                expression: None, ..
+            } => "Non riconosco alcuna espressione temporale".to_owned(),
+            Command::Debug {
+                expression: Some(expression),
+                outcome,
+            } => render_debug(&expression, &outcome),
             Command::JustAnswer(msg) => msg,
         };
         let _ = self.telegram.send_text_to_user(msg, user_id).await;
@@ -109,23 +396,22 @@ impl MessageHandler for RemindersHandler {
     }
 }
 
-fn into_command(text: &str, user: User) -> Command {
+fn into_command(text: &str, user: User, attachment: Option<Attachment>) -> Command {
     let arguments: Vec<&str> = text.splitn(2, '\n').filter(|txt| !txt.is_empty()).collect();
-    let lower_tokens = arguments[0]
-        .split(' ')
-        .filter(|e| !e.is_empty())
-        .map(|e| e.trim_matches(&[',', ':', '.', '!', '\n']))
-        .flat_map(|s| s.split('\''))
-        .map(|s| s.to_lowercase())
-        .collect::<Vec<String>>();
-
+    let lower_tokens = tokenize(arguments[0]);
     let tokens = lower_tokens.iter().map(|s| s.as_str()).collect::<Vec<_>>();
 
     match tokens.first().copied() {
+        Some("promemoria") if tokens.get(1) == Some(&"pulizia") => Command::Sweep,
         Some("promemoria") => into_promemoria(tokens),
-        Some("ricordami") if arguments.len() > 1 => {
-            into_ricordami(tokens, arguments[1].trim_start_matches('\n'), user)
-        }
+        Some("stato") if tokens.get(1) == Some(&"promemoria") => Command::Status,
+        Some("ricordami") if arguments.len() > 1 => into_ricordami(
+            tokens,
+            arguments[1].trim_start_matches('\n'),
+            user,
+            attachment,
+        ),
+        Some("ricordami") if attachment.is_some() => into_ricordami(tokens, "", user, attachment),
         Some("scordati") => into_scordati(tokens),
         x => {
             tracing::info!("Received pragma: {:?}", x);
@@ -134,9 +420,67 @@ fn into_command(text: &str, user: User) -> Command {
     }
 }
 
+fn tokenize(line: &str) -> Vec<String> {
+    line.split(' ')
+        .filter(|e| !e.is_empty())
+        .map(|e| e.trim_matches([',', ':', '.', '!', '\n']))
+        .flat_map(|s| s.split('\''))
+        .map(|s| s.to_lowercase())
+        .collect()
+}
+
+fn is_admin_command(text: &str) -> bool {
+    let first_line = text.lines().next().unwrap_or_default();
+    let tokens = tokenize(first_line);
+    match tokens.first().map(|token| token.as_str()) {
+        Some("promemoria") => tokens.get(1).is_some_and(|token| token == "pulizia"),
+        Some("stato") => tokens.get(1).is_some_and(|token| token == "promemoria"),
+        _ => false,
+    }
+}
+
 fn into_promemoria(tokens: Vec<&str>) -> Command {
+    if let Some(position) = tokens.iter().position(|token| *token == "debug") {
+        let tokens = &tokens[position..];
+        return Command::Debug {
+            expression: explain(tokens.to_vec()),
+            outcome: Box::new(try_parse(tokens.to_vec(), &Utc::now())),
+        };
+    }
+    if let Some(position) = tokens.iter().position(|token| *token == "cerca") {
+        let query = tokens[position + 1..].join(" ");
+        if !query.is_empty() {
+            return Command::Search { query };
+        }
+    }
     if tokens.contains(&"miei") {
-        return Command::ReadAll;
+        let tag = tokens
+            .iter()
+            .find(|token| token.starts_with('#'))
+            .map(|tag| tag.trim_start_matches('#').to_owned());
+        return Command::ReadAll { tag };
+    }
+    if let Some(position) = tokens.iter().position(|token| *token == "agenda") {
+        return into_agenda(&tokens[position + 1..]);
+    }
+    if let Some(position) = tokens.iter().position(|token| *token == "calendario") {
+        return into_calendario(&tokens[position + 1..]);
+    }
+    if let Some(position) = tokens.iter().position(|token| *token == "silenzio") {
+        return into_silenzio(&tokens[position + 1..]);
+    }
+    if tokens.contains(&"storico") {
+        return Command::History;
+    }
+    if tokens.contains(&"stats") {
+        return Command::Stats;
+    }
+    if tokens.contains(&"fatto") {
+        return tokens
+            .iter()
+            .find_map(|token| token.parse::<i32>().ok())
+            .map(|reminder_id| Command::Acknowledge { reminder_id })
+            .unwrap_or_else(promemoria_help);
     }
 
     for token in tokens {
@@ -148,7 +492,97 @@ fn into_promemoria(tokens: Vec<&str>) -> Command {
     promemoria_help()
 }
 
+fn parse_times(tokens: &[&str]) -> Vec<NaiveTime> {
+    tokens
+        .iter()
+        .filter_map(|token| {
+            NaiveTime::parse_from_str(token, "%H:%M").ok().or_else(|| {
+                token
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(|hour| NaiveTime::from_hms_opt(hour, 0, 0))
+            })
+        })
+        .collect()
+}
+
+fn into_agenda(tokens: &[&str]) -> Command {
+    let (tokens, city) = match tokens.iter().position(|token| *token == "meteo") {
+        Some(position) => (
+            &tokens[..position],
+            Some(tokens[position + 1..].join(" ")).filter(|city| !city.is_empty()),
+        ),
+        None => (tokens, None),
+    };
+    let times = parse_times(tokens);
+
+    match (tokens, times.as_slice(), city) {
+        ([], _, None) => Command::Agenda { subscription: None },
+        (_, [time], city) => Command::Agenda {
+            subscription: Some(Some(AgendaSubscription::new(*time, Europe::Rome, city))),
+        },
+        (tokens, _, None) if tokens.contains(&"no") => Command::Agenda {
+            subscription: Some(None),
+        },
+        _ => promemoria_help(),
+    }
+}
+
+fn into_calendario(tokens: &[&str]) -> Command {
+    let month = tokens.iter().find_map(|token| {
+        MONTH_NAMES
+            .iter()
+            .position(|name| name == token)
+            .map(|month0| month0 as u32 + 1)
+            .or_else(|| token.parse::<u32>().ok().filter(|m| (1..=12).contains(m)))
+    });
+    let year = tokens
+        .iter()
+        .find_map(|token| token.parse::<i32>().ok().filter(|year| *year > 1970));
+
+    match (tokens, month, year) {
+        ([], _, _) | (_, Some(_), _) | (_, _, Some(_)) => Command::Calendar { month, year },
+        _ => promemoria_help(),
+    }
+}
+
+fn parse_timezone(tokens: &[&str]) -> Option<Tz> {
+    tokens.iter().find_map(|token| {
+        TZ_VARIANTS
+            .iter()
+            .find(|timezone| timezone.name().eq_ignore_ascii_case(token))
+            .copied()
+    })
+}
+
+fn into_silenzio(tokens: &[&str]) -> Command {
+    let times = parse_times(tokens);
+    let timezone = parse_timezone(tokens);
+
+    match (tokens, times.as_slice()) {
+        ([], _) => Command::QuietHours {
+            window: None,
+            timezone,
+        },
+        (_, [start, end]) => Command::QuietHours {
+            window: Some(Some((*start, *end))),
+            timezone,
+        },
+        (tokens, _) if tokens.contains(&"no") => Command::QuietHours {
+            window: Some(None),
+            timezone,
+        },
+        _ => promemoria_help(),
+    }
+}
+
 fn into_scordati(tokens: Vec<&str>) -> Command {
+    if let Some(tag) = tokens.iter().find(|token| token.starts_with('#')) {
+        return Command::DeleteTagged {
+            tag: tag.trim_start_matches('#').to_owned(),
+        };
+    }
+
     for token in tokens {
         if let Ok(reminder_id) = token.parse::<i32>() {
             return Command::Delete { reminder_id };
@@ -158,13 +592,69 @@ fn into_scordati(tokens: Vec<&str>) -> Command {
     scordati_help()
 }
 
-fn into_ricordami(tokens: Vec<&str>, message: &str, user: User) -> Command {
-    try_parse(tokens, &Utc::now())
-        .map(|schedule| ReminderDefinition::new(schedule, user.id().0, message.to_owned()))
-        .map(Command::new_create)
+fn into_ricordami(
+    tokens: Vec<&str>,
+    message: &str,
+    user: User,
+    attachment: Option<Attachment>,
+) -> Command {
+    let (tokens, recipients) = extract_recipients(tokens);
+    let (tokens, tags) = extract_tags(tokens);
+    let urgent = tokens.contains(&"urgente");
+    let tokens = tokens
+        .into_iter()
+        .filter(|token| *token != "urgente")
+        .collect();
+    let outcome = try_parse(tokens, &Utc::now());
+    if outcome.has_errors() {
+        return Command::JustAnswer(format!(
+            "Non ho creato il promemoria: {}",
+            render_diagnostics(outcome.diagnostics())
+        ));
+    }
+    let warnings = outcome.diagnostics().to_vec();
+    outcome
+        .into_schedule()
+        .map(|schedule| {
+            ReminderDefinition::new(schedule, user.id().0, message.to_owned())
+                .with_attachment(attachment)
+        })
+        .map(|definition| Command::Create {
+            definition: Box::new(definition),
+            recipients,
+            tags,
+            urgent,
+            warnings,
+        })
         .unwrap_or_else(ricordami_help)
 }
 
+fn extract_tags(tokens: Vec<&str>) -> (Vec<&str>, Vec<String>) {
+    let (tags, remaining): (Vec<&str>, Vec<&str>) =
+        tokens.into_iter().partition(|token| token.starts_with('#'));
+    let tags = tags
+        .into_iter()
+        .map(|tag| tag.trim_start_matches('#').to_owned())
+        .collect();
+    (remaining, tags)
+}
+
+fn extract_recipients(tokens: Vec<&str>) -> (Vec<&str>, Vec<String>) {
+    let mut remaining = vec![];
+    let mut recipients = vec![];
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            "a" | "ad" | "e" if tokens.peek().is_some_and(|next| next.starts_with('@')) => (),
+            mention if mention.starts_with('@') => {
+                recipients.push(mention.trim_start_matches('@').to_owned())
+            }
+            _ => remaining.push(token),
+        }
+    }
+    (remaining, recipients)
+}
+
 fn generic_help() -> Command {
     Command::JustAnswer(
         r##"Sono costernato, ma non ho compreso il Suo desiderio.
@@ -177,8 +667,15 @@ Provi a scrivermi `ricordami`, `scordati`, `promemoria` così da aiutarmi ad aiu
 fn promemoria_help() -> Command {
     Command::JustAnswer(
         r##"Sono costernato, ma non ho compreso il Suo desiderio.
-Scriva `promemoria miei` per vedere una lista dei suoi promemoria.
+Scriva `promemoria miei` per vedere una lista dei suoi promemoria, `promemoria miei #<etichetta>` per filtrarli.
 Oppure scriva `promemoria <N>` (<N> è un numero) per vedere il promemoria identificato con N.
+Scriva `promemoria cerca <testo>` per cercare tra i messaggi dei suoi promemoria.
+Scriva `promemoria silenzio dalle <HH:MM> alle <HH:MM> [<fuso orario>]` per non ricevere promemoria in quella fascia, `promemoria silenzio no` per disattivarla.
+Scriva `promemoria agenda` per i promemoria di oggi, `promemoria agenda alle <HH:MM> [meteo <città>]` per riceverli ogni giorno, `promemoria agenda no` per smettere.
+Scriva `promemoria calendario [<mese> [<anno>]]` per vedere in quali giorni del mese scattano i suoi promemoria.
+Scriva `promemoria storico` per vedere gli ultimi promemoria scattati, `promemoria stats` per le statistiche.
+Scriva `promemoria fatto <N>` per segnare come fatto l'ultimo invio del promemoria identificato con N.
+Scriva `promemoria debug <espressione>` per vedere come interpreto un'espressione temporale.
 "##
         .to_string(),
     )
@@ -188,6 +685,7 @@ fn scordati_help() -> Command {
     Command::JustAnswer(
         r##"Sono costernato, ma non ho compreso il Suo desiderio.
 Scriva `scordati <N>` (<N> è un numero) per eliminare il promemoria identificato con N.
+Oppure scriva `scordati #<etichetta>` per eliminare tutti i promemoria con quell'etichetta.
 "##
         .to_string(),
     )
@@ -212,10 +710,36 @@ fn render_full(reminder: &Reminder) -> String {
         .unwrap_or_else(|| "N.D. (Terminato)".to_owned());
     let message = reminder.message().as_str().to_owned();
     let id = reminder.reminder_id().1;
+    let shared = match reminder.recipients().len() {
+        0 => "".to_owned(),
+        n => format!("👥 Condiviso con {n} utenti\n"),
+    };
+    let tags = match reminder.tags() {
+        [] => "".to_owned(),
+        tags => format!(
+            "🏷️ Etichette: {}\n",
+            tags.iter().map(|tag| format!("#{tag}")).join(" ")
+        ),
+    };
+    let attachment = match reminder.attachment() {
+        None => "",
+        Some(Attachment::Photo { .. }) => "📎 Allegato: foto\n",
+        Some(Attachment::Document { .. }) => "📎 Allegato: documento\n",
+        Some(Attachment::Voice { .. }) => "📎 Allegato: nota vocale\n",
+    };
+    let remaining = match reminder.remaining() {
+        None => "".to_owned(),
+        Some(1) => "🔁 Manca 1 ripetizione\n".to_owned(),
+        Some(n) => format!("🔁 Mancano {n} ripetizioni\n"),
+    };
+    let cron = match reminder.cron() {
+        None => "".to_owned(),
+        Some(cron) => format!("⏱️ Cron: {cron}\n"),
+    };
     format!(
         r#"📝 Promemoria ID: {id}
 🕰️ Prossima scadenza: {current_tick}
-
+{remaining}{cron}{shared}{tags}{attachment}
 💬 Messaggio
 {message}
 "#,
@@ -242,3 +766,379 @@ fn render_line(reminder: &Reminder) -> String {
     let id = reminder.reminder_id().1;
     format!("[ID {id}, {current_tick}]: {message}")
 }
+
+fn render_firing(firing: &Firing) -> String {
+    let fired_at = firing
+        .fired_at()
+        .with_timezone(&Europe::Rome)
+        .format("%d/%m/%y %H:%M %Z");
+    let outcome = match firing.outcome() {
+        DeliveryOutcome::Delivered => "✅ consegnato",
+        DeliveryOutcome::Failed(_) => "❌ non consegnato",
+    };
+    let acknowledged = if firing.is_acknowledged() {
+        ", 👍 fatto"
+    } else {
+        ""
+    };
+    let id = firing.reminder_id().1;
+    format!("[ID {id}, {fired_at}]: {outcome}{acknowledged}")
+}
+
+fn render_status(status: &EngineStatus, now: &DateTime<Utc>) -> String {
+    let format_date = |date: &DateTime<Utc>| {
+        date.with_timezone(&Europe::Rome)
+            .format("%d/%m/%y %H:%M %Z")
+            .to_string()
+    };
+    let running = match status.running_since {
+        Some(since) => {
+            let minutes = now.signed_duration_since(since).num_minutes().max(0);
+            format!(
+                "dal {} ({}g {}h {}m)",
+                format_date(&since),
+                minutes / (24 * 60),
+                minutes / 60 % 24,
+                minutes % 60
+            )
+        }
+        None => "non avviato".to_owned(),
+    };
+    let per_user = status
+        .per_user
+        .iter()
+        .map(|(user_id, active)| format!("• utente {user_id}: {active}"))
+        .join("\n");
+    let next_ticks = status
+        .next_ticks
+        .iter()
+        .map(|(tick, user_id, id)| format!("• {}: utente {user_id}, ID {id}", format_date(tick)))
+        .join("\n");
+    let failures = status
+        .failures
+        .iter()
+        .map(|firing| {
+            let (user_id, id) = firing.reminder_id();
+            let reason = match firing.outcome() {
+                DeliveryOutcome::Failed(reason) => reason.as_str(),
+                DeliveryOutcome::Delivered => "",
+            };
+            format!(
+                "• {}: utente {user_id}, ID {id}: {reason}",
+                format_date(firing.fired_at())
+            )
+        })
+        .join("\n");
+    let or_none = |list: String| match list.is_empty() {
+        true => "nessuno".to_owned(),
+        false => format!("\n{list}"),
+    };
+
+    format!(
+        "📊 Stato promemoria\nAttivi: {}\nMotore: {running}\nCoda: {}, indice: {} ({} eliminati in attesa di rimozione)\n\nPer utente: {}\n\nProssimi invii: {}\n\nUltimi invii falliti: {}",
+        status.active,
+        status.queue_size,
+        status.lookup_size,
+        status.lookup_size - status.active,
+        or_none(per_user),
+        or_none(next_ticks),
+        or_none(failures),
+    )
+}
+
+fn render_stats(stats: &[ReminderStats]) -> String {
+    if stats.is_empty() {
+        return "Nessun promemoria è ancora scattato".to_string();
+    }
+
+    let total = stats
+        .iter()
+        .fold(ReminderStats::default(), |acc, s| ReminderStats {
+            firings: acc.firings + s.firings,
+            delivered: acc.delivered + s.delivered,
+            acknowledged: acc.acknowledged + s.acknowledged,
+            ..acc
+        });
+    let lines = stats
+        .iter()
+        .map(|s| {
+            format!(
+                "• [ID {}]: {} invii, {} consegnati, {} fatti ({})",
+                s.reminder_id,
+                s.firings,
+                s.delivered,
+                s.acknowledged,
+                render_rate(s.completion_rate())
+            )
+        })
+        .join("\n");
+    format!(
+        "Statistiche promemoria:\n{lines}\n\nTasso di completamento: {}",
+        render_rate(total.completion_rate())
+    )
+}
+
+fn render_rate(rate: Option<f64>) -> String {
+    rate.map(|r| format!("{:.0}%", r * 100.0))
+        .unwrap_or_else(|| "N.D.".to_owned())
+}
+
+pub fn render_agenda(greeting: &str, entries: &[AgendaEntry]) -> Vec<String> {
+    if entries.is_empty() {
+        return vec![format!("{greeting}Oggi non ha alcun promemoria in agenda")];
+    }
+    let lines = group_by_reminder(entries.iter().map(|entry| (entry.reminder_id(), entry)))
+        .into_iter()
+        .map(|(reminder_id, entries)| {
+            let times = entries
+                .iter()
+                .take(MAX_AGENDA_TIMES)
+                .map(|entry| entry.when().with_timezone(&Europe::Rome).format("%H:%M"))
+                .join(", ");
+            let others = match entries.len().saturating_sub(MAX_AGENDA_TIMES) {
+                0 => "".to_owned(),
+                others => format!(" e altre {others} volte"),
+            };
+            let message = {
+                let msg = entries[0].message().as_str().to_owned();
+                match msg.len() {
+                    ..=50 => msg,
+                    _ => format!("{:.47}...", msg),
+                }
+            };
+            format!("• {times}{others} [ID {reminder_id}]: {message}")
+        })
+        .collect();
+    paginate(&format!("{greeting}Agenda di oggi:"), lines, "\n")
+}
+
+pub fn render_digest(reminders: &[(i32, Payload)]) -> Vec<String> {
+    let lines = group_by_reminder(
+        reminders
+            .iter()
+            .map(|(reminder_id, payload)| (*reminder_id, payload)),
+    )
+    .into_iter()
+    .map(|(reminder_id, payloads)| {
+        let clip = match payloads[0].attachment() {
+            Some(_) => "📎 ",
+            None => "",
+        };
+        let times = match payloads.len() {
+            1 => "".to_owned(),
+            times => format!(", arrivato {times} volte"),
+        };
+        format!(
+            "• {clip}{}\n(promemoria ID {reminder_id}{times})",
+            payloads[0].message().as_str()
+        )
+    })
+    .collect();
+    paginate(
+        "🌙 Promemoria arrivati durante le ore di silenzio:",
+        lines,
+        "\n\n",
+    )
+}
+
+fn group_by_reminder<T>(items: impl Iterator<Item = (i32, T)>) -> Vec<(i32, Vec<T>)> {
+    let mut groups: Vec<(i32, Vec<T>)> = vec![];
+    for (reminder_id, item) in items {
+        match groups.iter_mut().find(|(id, _)| *id == reminder_id) {
+            Some((_, group)) => group.push(item),
+            None => groups.push((reminder_id, vec![item])),
+        }
+    }
+    groups
+}
+
+fn render_debug(expression: &TimeExpr, outcome: &ParseOutcome) -> String {
+    let next_tick = outcome
+        .schedule()
+        .and_then(|schedule| schedule.next_tick(&Utc::now()))
+        .map(|d| {
+            d.with_timezone(&Europe::Rome)
+                .format("%d/%m/%Y %T %Z")
+                .to_string()
+        })
+        .unwrap_or_else(|| "nessuno".to_owned());
+    let diagnostics = match outcome.diagnostics() {
+        [] => "nessuno".to_owned(),
+        diagnostics => render_diagnostics(diagnostics),
+    };
+    format!("{expression:#?}\nProssimo invio: {next_tick}\nAvvisi: {diagnostics}")
+}
+
+fn render_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    let ignored = diagnostics
+        .iter()
+        .filter_map(|diagnostic| match diagnostic {
+            Diagnostic::Ignored { token } => Some(format!("\"{token}\"")),
+            _ => None,
+        })
+        .join(", ");
+    diagnostics
+        .iter()
+        .filter_map(|diagnostic| match diagnostic {
+            Diagnostic::Ignored { .. } => None,
+            Diagnostic::OutOfRange { token, field } => Some(format!(
+                "\"{token}\" non è {} valido",
+                match field {
+                    Field::Hour => "un'ora",
+                    Field::Minute => "un minuto",
+                    Field::Day => "un giorno",
+                    Field::Month => "un mese",
+                    Field::Amount => "un intervallo",
+                }
+            )),
+            Diagnostic::Conflicting(Conflict::InThePast) => {
+                Some("la data indicata è già passata".to_owned())
+            }
+            Diagnostic::Conflicting(Conflict::UntilBeforeSince) => {
+                Some("la fine viene prima dell'inizio".to_owned())
+            }
+            Diagnostic::Conflicting(Conflict::NeverFires) => {
+                Some("il promemoria non scatterebbe mai".to_owned())
+            }
+            Diagnostic::Conflicting(Conflict::PartialHourWindow) => Some(
+                "con ogni minuto l'intervallo deve iniziare e finire a ore intere o restare nella stessa ora".to_owned(),
+            ),
+            Diagnostic::InvalidCron { reason } => Some(format!("cron non valido, {reason}")),
+            Diagnostic::FarAway { first } => Some(format!(
+                "il primo invio sarà il {}",
+                first.with_timezone(&Europe::Rome).format("%d/%m/%Y %H:%M")
+            )),
+        })
+        .chain((!ignored.is_empty()).then(|| format!("ho ignorato {ignored}")))
+        .join("; ")
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use ambrogio_reminders::interface::{AgendaEntry, Attachment, Payload};
+    use chrono::{DateTime, Duration, Utc};
+
+    use ambrogio_users::data::{User, UserId};
+
+    use super::{
+        into_command, into_silenzio, is_admin_command, parse_timezone, render_agenda,
+        render_digest, Command,
+    };
+    use crate::telegram::MESSAGE_LIMIT;
+
+    fn at(date: &str) -> DateTime<Utc> {
+        date.parse::<DateTime<Utc>>().unwrap()
+    }
+
+    #[test]
+    fn agenda_summarizes_frequent_reminders() {
+        let message = Arc::new("bere".to_owned());
+        let entries = (0..60)
+            .map(|minute| {
+                AgendaEntry::new(
+                    at("2024-08-17T09:00:00+02:00") + Duration::minutes(minute),
+                    1,
+                    message.clone(),
+                )
+            })
+            .chain([AgendaEntry::new(
+                at("2024-08-17T09:30:00+02:00"),
+                2,
+                Arc::new("riunione".to_owned()),
+            )])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "☀️ Buongiorno!\nAgenda di oggi:\n• 09:00, 09:01, 09:02, 09:03, 09:04 e altre 55 volte [ID 1]: bere\n• 09:30 [ID 2]: riunione"
+            ],
+            render_agenda("☀️ Buongiorno!\n", &entries)
+        );
+        assert_eq!(
+            vec!["Oggi non ha alcun promemoria in agenda"],
+            render_agenda("", &[])
+        );
+    }
+
+    #[test]
+    fn agenda_is_split_in_messages() {
+        let entries = (0..500)
+            .map(|id| {
+                AgendaEntry::new(
+                    at("2024-08-17T09:00:00+02:00"),
+                    id,
+                    Arc::new("x".repeat(80)),
+                )
+            })
+            .collect::<Vec<_>>();
+        let pages = render_agenda("", &entries);
+        assert!(pages.len() > 1);
+        assert!(pages
+            .iter()
+            .all(|page| page.chars().count() <= MESSAGE_LIMIT));
+        assert_eq!(
+            500,
+            pages
+                .iter()
+                .map(|page| page.matches("• 09:00").count())
+                .sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn digest_groups_repeated_firings() {
+        let photo = Some(Attachment::Photo {
+            file_id: "abc".to_owned(),
+        });
+        let reminders =
+            std::iter::repeat_n((1, Payload::new(Arc::new("bere".to_owned()), None)), 120)
+                .chain([(2, Payload::new(Arc::new("foto".to_owned()), photo))])
+                .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "🌙 Promemoria arrivati durante le ore di silenzio:\n\n• bere\n(promemoria ID 1, arrivato 120 volte)\n\n• 📎 foto\n(promemoria ID 2)"
+            ],
+            render_digest(&reminders)
+        );
+    }
+
+    #[test]
+    fn quiet_hours_take_an_optional_timezone() {
+        match into_silenzio(&["dalle", "23:30", "alle", "7", "america/new_york"]) {
+            Command::QuietHours {
+                window: Some(Some((start, end))),
+                timezone,
+            } => {
+                assert_eq!("23:30", start.format("%H:%M").to_string());
+                assert_eq!("07:00", end.format("%H:%M").to_string());
+                assert_eq!(Some(chrono_tz::America::New_York), timezone);
+            }
+            _ => panic!("expected quiet hours"),
+        }
+        assert!(matches!(
+            into_silenzio(&["dalle", "23", "alle", "7"]),
+            Command::QuietHours {
+                window: Some(Some(_)),
+                timezone: None,
+            }
+        ));
+        assert_eq!(Some(chrono_tz::UTC), parse_timezone(&["alle", "utc"]));
+        assert_eq!(None, parse_timezone(&["dalle", "23", "no"]));
+    }
+
+    #[test]
+    fn only_promemoria_pulizia_sweeps() {
+        let user = User::SimpleUser { id: UserId(1) };
+        assert!(matches!(
+            into_command("promemoria pulizia", user.clone(), None),
+            Command::Sweep
+        ));
+        assert!(is_admin_command("promemoria pulizia"));
+        match into_command("promemoria cerca pulizia", user, None) {
+            Command::Search { query } => assert_eq!("pulizia", query),
+            _ => panic!("expected a search"),
+        }
+        assert!(!is_admin_command("promemoria cerca pulizia"));
+    }
+}
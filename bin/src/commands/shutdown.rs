@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use crate::telegram::TelegramProxy;
-use ambrogio_users::data::User;
+use ambrogio_users::data::Capability;
 use async_trait::async_trait;
 
 use super::{InboundMessage, MessageHandler};
@@ -21,8 +21,12 @@ impl ShutdownHandler {
 
 #[async_trait]
 impl MessageHandler for ShutdownHandler {
-    fn can_accept(&self, InboundMessage { user, text }: &InboundMessage) -> bool {
-        text.eq_ignore_ascii_case("dormi pure") && matches!(user, &User::SuperUser { .. })
+    fn can_accept(&self, InboundMessage { text, .. }: &InboundMessage) -> bool {
+        text.eq_ignore_ascii_case("dormi pure")
+    }
+
+    fn capability(&self, _: &InboundMessage) -> Capability {
+        Capability::Shutdown
     }
 
     async fn handle(&self, InboundMessage { user, .. }: InboundMessage) -> Result<(), String> {
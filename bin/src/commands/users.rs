@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use ambrogio_users::{
-    data::{User, UserId},
+    data::{Capability, User, UserId},
     UserRepository,
 };
 use async_trait::async_trait;
@@ -54,10 +54,17 @@ impl UserHandler {
 #[async_trait]
 impl MessageHandler for UserHandler {
     fn can_accept(&self, msg: &InboundMessage) -> bool {
-        self.regex.is_match(&msg.text) && msg.user.is_super_user()
+        self.regex.is_match(&msg.text)
     }
 
-    async fn handle(&self, InboundMessage { user, text }: InboundMessage) -> Result<(), String> {
+    fn capability(&self, _: &InboundMessage) -> Capability {
+        Capability::Users
+    }
+
+    async fn handle(
+        &self,
+        InboundMessage { user, text, .. }: InboundMessage,
+    ) -> Result<(), String> {
         let target = Self::extract_user_id(&text)?;
         let result_message = self.add_or_remove_user(&text, target).await;
 
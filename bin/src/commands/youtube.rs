@@ -1,7 +1,10 @@
 use std::{collections::HashMap, env, path::PathBuf, sync::Arc};
 
-use crate::telegram::TelegramProxy;
-use ambrogio_users::data::UserId;
+use crate::{
+    quotas::{DownloadPermit, Quotas},
+    telegram::TelegramProxy,
+};
+use ambrogio_users::data::{Capability, UserId};
 use async_process::Command;
 use async_trait::async_trait;
 use redis::AsyncCommands;
@@ -17,6 +20,7 @@ pub struct YoutubeDownloadHandler {
     telegram: Arc<dyn TelegramProxy + Send + Sync + 'static>,
     client: Client,
     redis: redis::aio::MultiplexedConnection,
+    quotas: Arc<Quotas>,
     regex: Regex,
 }
 
@@ -25,6 +29,7 @@ impl YoutubeDownloadHandler {
         telegram: Arc<Proxy>,
         redis: redis::aio::MultiplexedConnection,
         client: &Client,
+        quotas: Arc<Quotas>,
     ) -> Self
     where
         Proxy: TelegramProxy + Send + Sync + 'static,
@@ -33,6 +38,7 @@ impl YoutubeDownloadHandler {
             telegram,
             client: client.clone(),
             redis,
+            quotas,
             regex: Regex::new(r"(?i)^(video|audio)(\s+[^\s]+)+").unwrap(),
         }
     }
@@ -55,6 +61,7 @@ impl YoutubeDownloadHandler {
         id: UserId,
         video_id: String,
         target_name: Option<String>,
+        permit: DownloadPermit,
     ) -> Result<(), String> {
         let command = DownloadCommand::Video {
             id,
@@ -62,7 +69,7 @@ impl YoutubeDownloadHandler {
             target_name,
         };
 
-        self.download_and_upload(command).await
+        self.download_and_upload(command, permit).await
     }
 
     async fn download_audio(
@@ -70,6 +77,7 @@ impl YoutubeDownloadHandler {
         id: UserId,
         video_id: String,
         target_name: Option<String>,
+        permit: DownloadPermit,
     ) -> Result<(), String> {
         let command = DownloadCommand::Audio {
             id,
@@ -77,16 +85,21 @@ impl YoutubeDownloadHandler {
             target_name,
         };
 
-        self.download_and_upload(command).await
+        self.download_and_upload(command, permit).await
     }
 
-    async fn download_and_upload(&self, command: DownloadCommand) -> Result<(), String> {
+    async fn download_and_upload(
+        &self,
+        command: DownloadCommand,
+        permit: DownloadPermit,
+    ) -> Result<(), String> {
         tokio::spawn({
             let telegram = self.telegram.clone();
             let client = self.client.clone();
             let mut redis = self.redis.clone();
 
             async move {
+                let _permit = permit;
                 let id = command.user_id();
                 let video_id = command.video_id();
                 let download_name = command.file_name(video_id);
@@ -114,7 +127,7 @@ impl YoutubeDownloadHandler {
                         if !matches!(fs::try_exists(&download_path).await, Ok(true)) {
                             tracing::info!("Downloading {video_id} into {download_path:?}");
                             let download = command
-                                .into_download_command(download_path.to_str().unwrap())
+                                .to_download_command(download_path.to_str().unwrap())
                                 .output()
                                 .await;
 
@@ -211,7 +224,14 @@ impl MessageHandler for YoutubeDownloadHandler {
         self.regex.is_match(text)
     }
 
-    async fn handle(&self, InboundMessage { user, text }: InboundMessage) -> Result<(), String> {
+    fn capability(&self, _: &InboundMessage) -> Capability {
+        Capability::Downloads
+    }
+
+    async fn handle(
+        &self,
+        InboundMessage { user, text, .. }: InboundMessage,
+    ) -> Result<(), String> {
         let id = user.id();
         let pieces = text
             .split(' ')
@@ -236,20 +256,33 @@ impl MessageHandler for YoutubeDownloadHandler {
                 .map(|c| c.clone().into_owned())
                 .or_else(|| {
                     url.path_segments()
-                        .and_then(|p| p.last())
+                        .and_then(|mut p| p.next_back())
                         .map(|x| x.to_owned())
                 })
                 .unwrap_or(video.to_owned()),
             _ => video.to_owned(),
         };
 
+        let permit = match self.quotas.start_download(&user).await {
+            Ok(permit) => permit,
+            Err(limit) => return self.telegram.send_text_to_user(limit, id).await,
+        };
+
         self.telegram
             .send_text_to_user(format!("Sto scaricando {} {}", command, video_id), id)
             .await?;
 
         match command.to_lowercase().as_str() {
-            "audio" => return self.download_audio(id, video_id, slugified_name).await,
-            _ => return self.download_video(id, video_id, slugified_name).await,
+            "audio" => {
+                return self
+                    .download_audio(id, video_id, slugified_name, permit)
+                    .await
+            }
+            _ => {
+                return self
+                    .download_video(id, video_id, slugified_name, permit)
+                    .await
+            }
         };
     }
 }
@@ -402,7 +435,7 @@ impl DownloadCommand {
             DownloadCommand::Audio { video_id, .. } => format!("audio:{}", video_id),
         }
     }
-    pub fn into_download_command(&self, download_path: &str) -> Command {
+    pub fn to_download_command(&self, download_path: &str) -> Command {
         match self {
             DownloadCommand::Video { video_id, .. } => {
                 let mut cmd = Command::new("yt-dlp");
@@ -444,7 +477,7 @@ mod test {
 
         File::create(path.as_path())
             .unwrap()
-            .write("test".as_bytes())
+            .write_all("test".as_bytes())
             .unwrap();
 
         let url = upload_file(client, path, ".delme").await.unwrap();
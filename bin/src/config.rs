@@ -10,6 +10,7 @@ pub struct AmbrogioConfig {
     pub ferrero: FerreroConfig,
     pub updates: UpdatesConfig,
     pub forecast: ForecastConfig,
+    pub limits: LimitsConfig,
 }
 
 pub struct RedisConfig {
@@ -30,6 +31,13 @@ pub struct ForecastConfig {
     pub geocoding_root: String,
 }
 
+pub struct LimitsConfig {
+    pub max_reminders: u64,
+    pub min_interval_minutes: u64,
+    pub commands_per_minute: u64,
+    pub concurrent_downloads: u64,
+}
+
 #[derive(Clone)]
 pub struct UpdatesConfig {
     pub webhook_domain: String,
@@ -60,6 +68,12 @@ pub async fn get_config() -> &'static AmbrogioConfig {
             forecast: ForecastConfig {
                 forecast_root: env_var("FORECAST_MAIN_ROOT").unwrap_or("https://api.open-meteo.com".to_owned()), 
                 geocoding_root: env_var("FORECAST_GEO_ROOT").unwrap_or("https://geocoding-api.open-meteo.com".to_owned()) 
+            },
+            limits: LimitsConfig {
+                max_reminders: env_var_as_u64("LIMIT_MAX_REMINDERS").unwrap_or(100),
+                min_interval_minutes: env_var_as_u64("LIMIT_MIN_INTERVAL_MINUTES").unwrap_or(5),
+                commands_per_minute: env_var_as_u64("LIMIT_COMMANDS_PER_MINUTE").unwrap_or(30),
+                concurrent_downloads: env_var_as_u64("LIMIT_CONCURRENT_DOWNLOADS").unwrap_or(2),
             }
         })
     })
@@ -1,27 +1,45 @@
+mod calendar;
 mod commands;
 mod config;
 mod info;
+mod quotas;
 mod telegram;
 mod update_listener;
 
+use ambrogio_reminders::interface::AgendaEntry;
+use ambrogio_reminders::interface::AgendaSubscription;
+use ambrogio_reminders::interface::Attachment;
 use ambrogio_reminders::interface::ChronoTimeProvider;
+use ambrogio_reminders::interface::LeaseProvider;
+use ambrogio_reminders::interface::Payload;
 use ambrogio_reminders::interface::ReminderCallback;
 use ambrogio_reminders::interface::ReminderEngine;
+use ambrogio_reminders::interface::LEASE_TTL;
 use ambrogio_users::data::User as AmbrogioUser;
 use ambrogio_users::data::UserId as AmbrogioUserId;
 use ambrogio_users::RedisUserRepository;
-use ambrogio_users::UserRepository;
 use async_once_cell::OnceCell;
 use axum::async_trait;
+use commands::authenticate_user;
 use commands::ferrero::FerreroHandler;
+use commands::forecast::ForecastHandler;
+use commands::is_authorized;
+use commands::limits::LimitsHandler;
+use commands::permissions::PermissionsHandler;
+use commands::reminders::render_agenda;
+use commands::reminders::render_digest;
 use commands::reminders::RemindersHandler;
 use commands::shutdown::ShutdownHandler;
 use commands::youtube::YoutubeDownloadHandler;
-use commands::InboundMessage;
+use itertools::Itertools;
+use open_meteo::ForecastClient;
+use open_meteo::ForecastRequest;
 use open_meteo::ReqwestForecastClient;
+use quotas::Quotas;
 use redis::aio::MultiplexedConnection;
 use std::sync::Arc;
 use std::time::SystemTime;
+use telegram::split_caption;
 use telegram::TelegramProxy;
 use teloxide::prelude::*;
 use teloxide::types::User;
@@ -29,7 +47,6 @@ use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
 use crate::commands::echo::EchoMessageHandler;
-use crate::commands::forecast::ForecastHandler;
 use crate::commands::users::UserHandler;
 use crate::commands::MessageHandler;
 use crate::config::get_config;
@@ -44,6 +61,7 @@ static USERS: OnceCell<Arc<RedisUserRepository>> = OnceCell::new();
 static REDIS: OnceCell<Arc<MultiplexedConnection>> = OnceCell::new();
 static REMINDER_ENGINE: OnceCell<Arc<ReminderEngine>> = OnceCell::new();
 static REMINDER_CALLBACK: OnceCell<Arc<TelegramReminderCallback>> = OnceCell::new();
+static QUOTAS: OnceCell<Arc<Quotas>> = OnceCell::new();
 
 #[tokio::main]
 async fn main() {
@@ -79,6 +97,24 @@ async fn main() {
         }
     });
 
+    tokio::spawn({
+        let telegram = Arc::new(TeloxideProxy::new(&bot.clone()));
+        let engine = get_engine(telegram).await.unwrap().clone();
+        async move {
+            tracing::info!("Running agenda scheduler");
+            engine.run_agenda().await
+        }
+    });
+
+    tokio::spawn({
+        let telegram = Arc::new(TeloxideProxy::new(&bot.clone()));
+        let engine = get_engine(telegram).await.unwrap().clone();
+        async move {
+            tracing::info!("Running reconciliation");
+            engine.run_reconciliation().await
+        }
+    });
+
     greet_master(&bot, super_user_id)
         .await
         .inspect_err(|e|
@@ -100,13 +136,14 @@ async fn main() {
             let repo = get_users_repo().await.unwrap();
             let telegram = get_telegram(&bot).await;
             let handlers = get_handlers(&bot).await.unwrap();
+            let quotas = get_quotas().await.unwrap();
 
             let message = match extract_message(&msg, super_user_id) {
                 None => return Ok(()),
                 Some(msg) => msg,
             };
 
-            let message = match authenticate_user(message, repo).await {
+            let message = match authenticate_user(message, repo.as_ref()).await {
                 Err(e) => {
                     tracing::info!("Unable to authenticate message: {e}");
                     return Ok(());
@@ -117,8 +154,24 @@ async fn main() {
             let command = message.text.clone();
             let user = message.user.id();
 
+            if let Err(limit) = quotas.check_command(&message.user).await {
+                tracing::info!(user = user.0, "Command rate limit exceeded");
+                let _ = telegram.send_text_to_user(limit, user).await;
+                return Ok(());
+            }
+
             match handlers.iter().find(|h| h.can_accept(&message)) {
                 None => tracing::info!("Unrecognised command from {:?} '{}'", user, message.text),
+                Some(handler)
+                    if !is_authorized(&message, handler.as_ref(), repo.as_ref()).await =>
+                {
+                    let _ = telegram
+                        .send_text_to_user(
+                            "Mi spiace, ma non ha il permesso di chiedermelo".to_owned(),
+                            user,
+                        )
+                        .await;
+                }
                 Some(handler) => {
                     if let Some(error) = handler.handle(message).await.err() {
                         tracing::error!({ 
@@ -177,6 +230,7 @@ async fn setup_handlers(bot: &Bot) -> Result<Vec<Arc<Handler>>, String> {
     ));
     let telegram_proxy = Arc::new(TeloxideProxy::new(&bot.clone()));
     let engine = get_engine(telegram_proxy.clone()).await?;
+    let quotas = get_quotas().await?;
 
     Ok(vec![
         Arc::new(ForecastHandler::new(
@@ -184,6 +238,11 @@ async fn setup_handlers(bot: &Bot) -> Result<Vec<Arc<Handler>>, String> {
             forecast_client.clone(),
         )),
         Arc::new(UserHandler::new(telegram_proxy.clone(), repo.clone())),
+        Arc::new(LimitsHandler::new(telegram_proxy.clone(), quotas.clone())),
+        Arc::new(PermissionsHandler::new(
+            telegram_proxy.clone(),
+            repo.clone(),
+        )),
         Arc::new(FerreroHandler::new(
             telegram_proxy.clone(),
             config.ferrero.gif_url.clone(),
@@ -192,8 +251,14 @@ async fn setup_handlers(bot: &Bot) -> Result<Vec<Arc<Handler>>, String> {
             telegram_proxy.clone(),
             redis,
             &client,
+            quotas.clone(),
+        )),
+        Arc::new(RemindersHandler::new(
+            telegram_proxy.clone(),
+            engine,
+            repo.clone(),
+            quotas,
         )),
-        Arc::new(RemindersHandler::new(telegram_proxy.clone(), engine)),
         Arc::new(ShutdownHandler::new(telegram_proxy.clone())),
         Arc::new(EchoMessageHandler::new(telegram_proxy.clone())),
     ])
@@ -224,10 +289,15 @@ async fn get_engine(
             let config = get_config().await;
 
             let callback = get_callback(telegram).await?;
+            let leases = Arc::new(RedisLeaseProvider {
+                connection: get_redis_connection().await?.as_ref().clone(),
+                holder: std::env::var("HOSTNAME").unwrap_or(VERSION.to_owned()),
+            });
             Ok(Arc::new(
                 ReminderEngine::new_and_init(
                     Arc::new(ChronoTimeProvider {}),
                     callback,
+                    leases,
                     &config.mongo.url,
                     &config.mongo.db,
                 )
@@ -242,7 +312,30 @@ async fn get_callback(
     telegram: Arc<dyn TelegramProxy + Send + Sync + 'static>,
 ) -> Result<Arc<TelegramReminderCallback>, String> {
     REMINDER_CALLBACK
-        .get_or_try_init(async { Ok(Arc::new(TelegramReminderCallback { telegram })) })
+        .get_or_try_init(async {
+            let config = get_config().await;
+            let client = reqwest::ClientBuilder::new()
+                .build()
+                .map_err(|e| e.to_string())?;
+            let forecast = Arc::new(ReqwestForecastClient::new(
+                &client,
+                config.forecast.geocoding_root.clone(),
+                config.forecast.forecast_root.clone(),
+            ));
+            Ok(Arc::new(TelegramReminderCallback { telegram, forecast }))
+        })
+        .await
+        .cloned()
+}
+
+async fn get_quotas() -> Result<Arc<Quotas>, String> {
+    QUOTAS
+        .get_or_try_init(async {
+            let config = get_config().await;
+            let connection = get_redis_connection().await?.as_ref().clone();
+
+            Ok(Arc::new(Quotas::new(connection, (&config.limits).into())))
+        })
         .await
         .cloned()
 }
@@ -276,14 +369,33 @@ fn extract_message(
     msg: &Message,
     super_user_id: AmbrogioUserId,
 ) -> Option<commands::InboundMessage> {
+    let attachment =
+        extract_attachment(msg).or_else(|| msg.reply_to_message().and_then(extract_attachment));
     msg.from()
-        .zip(msg.text())
+        .zip(msg.text().or(msg.caption()))
         .map(|(user, text)| commands::InboundMessage {
             text: text.to_owned(),
             user: extract_user(user, super_user_id),
+            attachment,
         })
 }
 
+fn extract_attachment(msg: &Message) -> Option<Attachment> {
+    if let Some(photo) = msg.photo().and_then(|sizes| sizes.last()) {
+        return Some(Attachment::Photo {
+            file_id: photo.file.id.clone(),
+        });
+    }
+    if let Some(document) = msg.document() {
+        return Some(Attachment::Document {
+            file_id: document.file.id.clone(),
+        });
+    }
+    msg.voice().map(|voice| Attachment::Voice {
+        file_id: voice.file.id.clone(),
+    })
+}
+
 fn extract_user(user: &User, super_user_id: AmbrogioUserId) -> AmbrogioUser {
     let ambrogio_id = AmbrogioUserId(user.id.0);
     match (user.username.clone(), ambrogio_id) {
@@ -310,36 +422,112 @@ async fn greet_master(bot: &Bot, super_user_id: UserId) -> Result<(), String> {
     .map_err(|e| e.to_string())
 }
 
-async fn authenticate_user(
-    message: InboundMessage,
-    repo: Arc<RedisUserRepository>,
-) -> Result<InboundMessage, String> {
-    let user_id = message.user.id();
+struct RedisLeaseProvider {
+    connection: MultiplexedConnection,
+    holder: String,
+}
 
-    if let AmbrogioUser::SuperUser { .. } = message.user {
-        return Ok(message);
+#[async_trait]
+impl LeaseProvider for RedisLeaseProvider {
+    async fn try_acquire(&self, key: &str) -> Result<bool, String> {
+        let mut connection = self.connection.clone();
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(format!("lease:{key}"))
+            .arg(&self.holder)
+            .arg("NX")
+            .arg("PX")
+            .arg(LEASE_TTL.as_millis() as u64)
+            .query_async(&mut connection)
+            .await
+            .map_err(|e| format!("{e}"))?;
+        Ok(acquired.is_some())
     }
-
-    repo.get(user_id)
-        .await?
-        .ok_or(format!("Utente sconosciuto {}", user_id.0))
-        .map(|user| InboundMessage {
-            user,
-            text: message.text,
-        })
 }
 
 struct TelegramReminderCallback {
     telegram: Arc<dyn TelegramProxy + Send + Sync + 'static>,
+    forecast: Arc<dyn ForecastClient + Send + Sync + 'static>,
+}
+
+impl TelegramReminderCallback {
+    async fn send_payload(
+        &self,
+        text: String,
+        attachment: Option<&Attachment>,
+        user: AmbrogioUserId,
+    ) -> Result<(), String> {
+        let attachment = match attachment {
+            None => return self.telegram.send_text_to_user(text, user).await,
+            Some(attachment) => attachment,
+        };
+        let (text, caption) = split_caption(text);
+        if let Some(text) = text {
+            self.telegram.send_text_to_user(text, user).await?;
+        }
+        match attachment {
+            Attachment::Photo { file_id } => self.telegram.send_photo(file_id, caption, user).await,
+            Attachment::Document { file_id } => {
+                self.telegram.send_document(file_id, caption, user).await
+            }
+            Attachment::Voice { file_id } => self.telegram.send_voice(file_id, caption, user).await,
+        }
+    }
 }
 
 #[async_trait]
 impl ReminderCallback for TelegramReminderCallback {
-    async fn call(&self, user: u64, reminder_id: i32, message: Arc<String>) {
-        let msg = format!("{}\n(promemoria ID {})", message.as_str(), reminder_id);
-        let _ = self
-            .telegram
+    async fn call(&self, user: u64, reminder_id: i32, payload: Payload) -> Result<(), String> {
+        let msg = format!(
+            "{}\n(promemoria ID {})",
+            payload.message().as_str(),
+            reminder_id
+        );
+        self.send_payload(msg, payload.attachment(), AmbrogioUserId(user))
+            .await
+    }
+
+    async fn digest(&self, user: u64, reminders: Vec<(i32, Payload)>) -> Result<(), String> {
+        for page in render_digest(&reminders) {
+            self.telegram
+                .send_text_to_user(page, AmbrogioUserId(user))
+                .await?;
+        }
+
+        let attached = reminders
+            .iter()
+            .filter(|(_, payload)| payload.attachment().is_some())
+            .unique_by(|(reminder_id, _)| *reminder_id);
+        for (reminder_id, payload) in attached {
+            if let Some(attachment) = payload.attachment() {
+                let caption = format!("(promemoria ID {reminder_id})");
+                self.send_payload(caption, Some(attachment), AmbrogioUserId(user))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn agenda(
+        &self,
+        user: u64,
+        entries: Vec<AgendaEntry>,
+        subscription: AgendaSubscription,
+    ) -> Result<(), String> {
+        for page in render_agenda("☀️ Buongiorno!\n", &entries) {
+            self.telegram
+                .send_text_to_user(page, AmbrogioUserId(user))
+                .await?;
+        }
+
+        let city = match subscription.city() {
+            Some(city) => city,
+            None => return Ok(()),
+        };
+        let forecast = ForecastRequest::city_specific_day(city, 0)?;
+        let meteo = self.forecast.weather_forecast(&forecast).await?;
+        let msg = ForecastHandler::render_forecast(meteo, city, true).join("\n");
+        self.telegram
             .send_text_to_user(msg, AmbrogioUserId(user))
-            .await;
+            .await
     }
 }
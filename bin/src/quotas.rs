@@ -0,0 +1,273 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ambrogio_users::data::{User, UserId};
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+
+use crate::config::LimitsConfig;
+
+const COMMAND_WINDOW: Duration = Duration::from_secs(60);
+
+pub const LIMIT_NAMES: [&str; 4] = ["promemoria", "intervallo", "comandi", "download"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub max_reminders: u64,
+    pub min_interval_minutes: u64,
+    pub commands_per_minute: u64,
+    pub concurrent_downloads: u64,
+}
+
+impl Limits {
+    fn set(&mut self, name: &str, value: u64) -> bool {
+        match name {
+            "promemoria" => self.max_reminders = value,
+            "intervallo" => self.min_interval_minutes = value,
+            "comandi" => self.commands_per_minute = value,
+            "download" => self.concurrent_downloads = value,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn render(&self) -> String {
+        format!(
+            "• promemoria attivi: {}\n• intervallo minimo: {} minuti\n• comandi al minuto: {}\n• download contemporanei: {}",
+            self.max_reminders,
+            self.min_interval_minutes,
+            self.commands_per_minute,
+            self.concurrent_downloads
+        )
+    }
+}
+
+impl From<&LimitsConfig> for Limits {
+    fn from(value: &LimitsConfig) -> Self {
+        Self {
+            max_reminders: value.max_reminders,
+            min_interval_minutes: value.min_interval_minutes,
+            commands_per_minute: value.commands_per_minute,
+            concurrent_downloads: value.concurrent_downloads,
+        }
+    }
+}
+
+type Downloads = Arc<Mutex<HashMap<u64, u64>>>;
+
+pub struct DownloadPermit {
+    user: u64,
+    downloads: Downloads,
+}
+
+impl Drop for DownloadPermit {
+    fn drop(&mut self) {
+        let mut downloads = self.downloads.lock().unwrap();
+        if let Some(count) = downloads.get_mut(&self.user) {
+            *count -= 1;
+            if *count == 0 {
+                downloads.remove(&self.user);
+            }
+        }
+    }
+}
+
+pub struct Quotas {
+    redis: MultiplexedConnection,
+    defaults: Limits,
+    commands: Mutex<HashMap<u64, VecDeque<Instant>>>,
+    downloads: Downloads,
+}
+
+impl Quotas {
+    pub fn new(redis: MultiplexedConnection, defaults: Limits) -> Self {
+        Self {
+            redis,
+            defaults,
+            commands: Mutex::new(HashMap::new()),
+            downloads: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn limits_of(&self, user: UserId) -> Limits {
+        let mut limits = self.defaults;
+        let overrides: HashMap<String, u64> = match self.redis.clone().hgetall(key(user)).await {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                tracing::warn!(user = user.0, error = %e, "Unable to read limits overrides");
+                return limits;
+            }
+        };
+        for (name, value) in overrides {
+            limits.set(&name, value);
+        }
+        limits
+    }
+
+    pub async fn set_limit(&self, user: UserId, name: &str, value: u64) -> Result<Limits, String> {
+        if !LIMIT_NAMES.contains(&name) {
+            return Err(format!("Non conosco il limite \"{name}\""));
+        }
+        let _: () = self
+            .redis
+            .clone()
+            .hset(key(user), name, value)
+            .await
+            .map_err(|e| format!("{e}"))?;
+        Ok(self.limits_of(user).await)
+    }
+
+    pub async fn reset_limits(&self, user: UserId) -> Result<Limits, String> {
+        let _: () = self
+            .redis
+            .clone()
+            .del(key(user))
+            .await
+            .map_err(|e| format!("{e}"))?;
+        Ok(self.defaults)
+    }
+
+    pub async fn check_command(&self, user: &User) -> Result<(), String> {
+        if user.is_super_user() {
+            return Ok(());
+        }
+        let limit = self.limits_of(user.id()).await.commands_per_minute;
+        let now = Instant::now();
+        let mut commands = self.commands.lock().unwrap();
+        evict(&mut commands, now);
+        let window = commands.entry(user.id().0).or_default();
+        if allow(window, now, limit) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Mi scusi, ma ha già inviato {limit} comandi nell'ultimo minuto: riprovi tra poco"
+            ))
+        }
+    }
+
+    pub async fn check_reminder(
+        &self,
+        user: &User,
+        active: usize,
+        interval: Option<chrono::Duration>,
+    ) -> Result<(), String> {
+        if user.is_super_user() {
+            return Ok(());
+        }
+        let limits = self.limits_of(user.id()).await;
+        if active as u64 >= limits.max_reminders {
+            return Err(format!(
+                "Mi scusi, ma ha già {active} promemoria attivi e non può superarne {}",
+                limits.max_reminders
+            ));
+        }
+        let too_frequent = interval.is_some_and(|interval| {
+            interval < chrono::Duration::minutes(limits.min_interval_minutes as i64)
+        });
+        if too_frequent {
+            return Err(format!(
+                "Mi scusi, ma i promemoria non possono ripetersi più spesso di ogni {} minuti",
+                limits.min_interval_minutes
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn start_download(&self, user: &User) -> Result<DownloadPermit, String> {
+        let limit = match user.is_super_user() {
+            true => u64::MAX,
+            false => self.limits_of(user.id()).await.concurrent_downloads,
+        };
+        let mut downloads = self.downloads.lock().unwrap();
+        let count = downloads.entry(user.id().0).or_default();
+        if *count >= limit {
+            return Err(format!(
+                "Mi scusi, ma ha già {count} download in corso: attenda che finiscano"
+            ));
+        }
+        *count += 1;
+        Ok(DownloadPermit {
+            user: user.id().0,
+            downloads: self.downloads.clone(),
+        })
+    }
+}
+
+fn key(user: UserId) -> String {
+    format!("limits:{}", user.0)
+}
+
+fn allow(window: &mut VecDeque<Instant>, now: Instant, limit: u64) -> bool {
+    while window
+        .front()
+        .is_some_and(|then| now.duration_since(*then) >= COMMAND_WINDOW)
+    {
+        window.pop_front();
+    }
+    if window.len() as u64 >= limit {
+        return false;
+    }
+    window.push_back(now);
+    true
+}
+
+fn evict(commands: &mut HashMap<u64, VecDeque<Instant>>, now: Instant) {
+    commands.retain(|_, window| {
+        window
+            .back()
+            .is_some_and(|then| now.duration_since(*then) < COMMAND_WINDOW)
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{HashMap, VecDeque},
+        time::{Duration, Instant},
+    };
+
+    use super::{allow, evict, Limits};
+
+    #[test]
+    fn allows_commands_within_the_window_limit() {
+        let start = Instant::now();
+        let mut window = VecDeque::new();
+        assert!(allow(&mut window, start, 2));
+        assert!(allow(&mut window, start + Duration::from_secs(10), 2));
+        assert!(!allow(&mut window, start + Duration::from_secs(20), 2));
+        assert!(allow(&mut window, start + Duration::from_secs(60), 2));
+        assert!(!allow(&mut window, start + Duration::from_secs(65), 2));
+    }
+
+    #[test]
+    fn evicts_users_without_recent_commands() {
+        let start = Instant::now();
+        let mut commands = HashMap::new();
+        commands.insert(1, VecDeque::from([start]));
+        commands.insert(2, VecDeque::from([start, start + Duration::from_secs(30)]));
+        commands.insert(3, VecDeque::new());
+
+        evict(&mut commands, start + Duration::from_secs(60));
+        assert_eq!(vec![&2], commands.keys().collect::<Vec<_>>());
+        evict(&mut commands, start + Duration::from_secs(90));
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn overrides_limits_by_name() {
+        let mut limits = Limits {
+            max_reminders: 100,
+            min_interval_minutes: 5,
+            commands_per_minute: 30,
+            concurrent_downloads: 2,
+        };
+        assert!(limits.set("intervallo", 1));
+        assert!(limits.set("download", 0));
+        assert!(!limits.set("video", 3));
+        assert_eq!(1, limits.min_interval_minutes);
+        assert_eq!(0, limits.concurrent_downloads);
+        assert_eq!(100, limits.max_reminders);
+    }
+}
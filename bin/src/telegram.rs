@@ -3,8 +3,9 @@ use std::{path::PathBuf, str::FromStr};
 use ambrogio_users::data::UserId as AmbrogioUserId;
 use async_trait::async_trait;
 use teloxide::{
+    payloads::{SendDocumentSetters, SendMessageSetters, SendPhotoSetters, SendVoiceSetters},
     requests::Requester,
-    types::{InputFile, UserId},
+    types::{InputFile, ParseMode, UserId},
     Bot,
 };
 use url::Url;
@@ -17,10 +18,61 @@ pub trait TelegramProxy {
         message: String,
         user_id: AmbrogioUserId,
     ) -> Result<(), String>;
+    async fn send_html(&self, html: String, user_id: AmbrogioUserId) -> Result<(), String>;
     async fn send_gif_from_url(&self, raw_url: &str, user_id: AmbrogioUserId)
         -> Result<(), String>;
     async fn send_local_video(&self, path: PathBuf, user_id: AmbrogioUserId) -> Result<(), String>;
     async fn send_local_audio(&self, path: PathBuf, user_id: AmbrogioUserId) -> Result<(), String>;
+    async fn send_photo(
+        &self,
+        file_id: &str,
+        caption: String,
+        user_id: AmbrogioUserId,
+    ) -> Result<(), String>;
+    async fn send_document(
+        &self,
+        file_id: &str,
+        caption: String,
+        user_id: AmbrogioUserId,
+    ) -> Result<(), String>;
+    async fn send_voice(
+        &self,
+        file_id: &str,
+        caption: String,
+        user_id: AmbrogioUserId,
+    ) -> Result<(), String>;
+}
+
+pub const MESSAGE_LIMIT: usize = 4096;
+pub const CAPTION_LIMIT: usize = 1024;
+
+pub fn paginate(header: &str, lines: Vec<String>, separator: &str) -> Vec<String> {
+    let mut pages = vec![];
+    let mut page = header.to_owned();
+    for line in lines {
+        let line = line.chars().take(MESSAGE_LIMIT).collect::<String>();
+        let length = page.chars().count() + separator.chars().count() + line.chars().count();
+        if length > MESSAGE_LIMIT && !page.is_empty() {
+            pages.push(std::mem::take(&mut page));
+        }
+        if !page.is_empty() {
+            page.push_str(separator);
+        }
+        page.push_str(&line);
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+// media captions are capped by Telegram, longer texts are sent on their own before the media
+pub fn split_caption(text: String) -> (Option<String>, String) {
+    if text.chars().count() > CAPTION_LIMIT {
+        (Some(text), String::new())
+    } else {
+        (None, text)
+    }
 }
 
 #[derive(Clone)]
@@ -48,6 +100,19 @@ impl TelegramProxy for TeloxideProxy {
             .map_err(|e| e.to_string())
             .map(|_| ())
     }
+    async fn send_html(
+        &self,
+        html: String,
+        AmbrogioUserId(user_id): AmbrogioUserId,
+    ) -> Result<(), String> {
+        let user = UserId(user_id);
+        self.bot
+            .send_message(user, html)
+            .parse_mode(ParseMode::Html)
+            .await
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
     async fn send_gif_from_url(
         &self,
         raw_url: &str,
@@ -92,4 +157,107 @@ impl TelegramProxy for TeloxideProxy {
             .map_err(|e| e.to_string())
             .map(|_| ())
     }
+    async fn send_photo(
+        &self,
+        file_id: &str,
+        caption: String,
+        AmbrogioUserId(user_id): AmbrogioUserId,
+    ) -> Result<(), String> {
+        let user = UserId(user_id);
+
+        self.bot
+            .send_photo(user, InputFile::file_id(file_id))
+            .caption(caption)
+            .await
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+    async fn send_document(
+        &self,
+        file_id: &str,
+        caption: String,
+        AmbrogioUserId(user_id): AmbrogioUserId,
+    ) -> Result<(), String> {
+        let user = UserId(user_id);
+
+        self.bot
+            .send_document(user, InputFile::file_id(file_id))
+            .caption(caption)
+            .await
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+    async fn send_voice(
+        &self,
+        file_id: &str,
+        caption: String,
+        AmbrogioUserId(user_id): AmbrogioUserId,
+    ) -> Result<(), String> {
+        let user = UserId(user_id);
+
+        self.bot
+            .send_voice(user, InputFile::file_id(file_id))
+            .caption(caption)
+            .await
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{paginate, split_caption, CAPTION_LIMIT, MESSAGE_LIMIT};
+
+    #[test]
+    fn keeps_short_messages_in_one_page() {
+        assert_eq!(
+            vec!["Agenda:\n• uno\n• due".to_owned()],
+            paginate(
+                "Agenda:",
+                vec!["• uno".to_owned(), "• due".to_owned()],
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn splits_long_messages_between_lines() {
+        let lines = (0..100)
+            .map(|i| format!("{i:>3}{}", "x".repeat(97)))
+            .collect::<Vec<_>>();
+        let pages = paginate("Titolo", lines, "\n\n");
+        assert_eq!(3, pages.len());
+        assert!(pages
+            .iter()
+            .all(|page| page.chars().count() <= MESSAGE_LIMIT));
+        assert!(pages[0].starts_with("Titolo\n\n  0x"));
+        assert!(pages[1].starts_with(" 40x"));
+        assert_eq!(
+            100,
+            pages
+                .iter()
+                .map(|page| page.split("\n\n").count())
+                .sum::<usize>()
+                - 1
+        );
+    }
+
+    #[test]
+    fn clips_lines_over_the_limit() {
+        let pages = paginate("Titolo", vec!["è".repeat(5000)], "\n");
+        assert_eq!(2, pages.len());
+        assert_eq!(MESSAGE_LIMIT, pages[1].chars().count());
+    }
+
+    #[test]
+    fn keeps_short_captions_on_the_media() {
+        let caption = "è".repeat(CAPTION_LIMIT);
+        assert_eq!((None, caption.clone()), split_caption(caption));
+    }
+
+    #[test]
+    fn sends_long_captions_separately() {
+        let text = "è".repeat(CAPTION_LIMIT + 1);
+        assert_eq!((Some(text.clone()), String::new()), split_caption(text));
+    }
 }
@@ -0,0 +1,51 @@
+use ambrogio_reminders::interface::{try_parse, Schedule};
+use chrono::{DateTime, Utc};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn schedule_of(expression: &str, now: &DateTime<Utc>) -> Schedule {
+    try_parse(expression.split(' ').collect(), now)
+        .into_schedule()
+        .unwrap()
+}
+
+fn occurrences(c: &mut Criterion) {
+    let from = "2025-01-01T00:00:00+01:00"
+        .parse::<DateTime<Utc>>()
+        .unwrap();
+    let to = "2026-01-01T00:00:00+01:00"
+        .parse::<DateTime<Utc>>()
+        .unwrap();
+
+    let every_minute = schedule_of("ricordami cron * * * * *", &from);
+    let mut group = c.benchmark_group("occurrences_between");
+    group.sample_size(10);
+    group.bench_function("every minute for a year", |b| {
+        b.iter(|| {
+            every_minute
+                .occurrences_between(black_box(&from), black_box(&to))
+                .count()
+        })
+    });
+    let daily = schedule_of("ricordami ogni giorno alle 9", &from);
+    group.bench_function("daily for a year", |b| {
+        b.iter(|| {
+            daily
+                .occurrences_between(black_box(&from), black_box(&to))
+                .count()
+        })
+    });
+    group.finish();
+
+    let first_monday = schedule_of("ricordami ogni primo lunedì alle 9", &from);
+    c.bench_function("occurrences_after first monday, 100 ticks", |b| {
+        b.iter(|| {
+            first_monday
+                .occurrences_after(black_box(&from))
+                .take(100)
+                .count()
+        })
+    });
+}
+
+criterion_group!(benches, occurrences);
+criterion_main!(benches);
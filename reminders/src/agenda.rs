@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AgendaSubscription {
+    time: NaiveTime,
+    timezone: Tz,
+    city: Option<String>,
+}
+
+impl AgendaSubscription {
+    pub fn new(time: NaiveTime, timezone: Tz, city: Option<String>) -> Self {
+        Self {
+            time,
+            timezone,
+            city,
+        }
+    }
+
+    pub fn time(&self) -> &NaiveTime {
+        &self.time
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    pub fn city(&self) -> Option<&str> {
+        self.city.as_deref()
+    }
+
+    pub fn is_due(&self, now: &DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        local.hour() == self.time.hour() && local.minute() == self.time.minute()
+    }
+
+    pub fn day_of(&self, now: &DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        day_of(now, &self.timezone)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AgendaEntry {
+    when: DateTime<Utc>,
+    reminder_id: i32,
+    message: Arc<String>,
+}
+
+impl AgendaEntry {
+    pub fn new(when: DateTime<Utc>, reminder_id: i32, message: Arc<String>) -> Self {
+        Self {
+            when,
+            reminder_id,
+            message,
+        }
+    }
+
+    pub fn when(&self) -> &DateTime<Utc> {
+        &self.when
+    }
+
+    pub fn reminder_id(&self) -> i32 {
+        self.reminder_id
+    }
+
+    pub fn message(&self) -> Arc<String> {
+        self.message.clone()
+    }
+}
+
+pub fn day_of(now: &DateTime<Utc>, timezone: &Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+    let date = now.with_timezone(timezone).date_naive();
+    let start_of = |date: chrono::NaiveDate| {
+        timezone
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|d| d.with_timezone(&Utc))
+            .unwrap_or_else(|| date.and_time(NaiveTime::MIN).and_utc())
+    };
+    let start = start_of(date);
+    let end = date
+        .succ_opt()
+        .map(start_of)
+        .unwrap_or(start + Duration::days(1));
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, NaiveTime, Utc};
+    use chrono_tz::{America, Europe};
+
+    use super::{day_of, AgendaSubscription};
+
+    fn at(date: &str) -> DateTime<Utc> {
+        date.parse::<DateTime<Utc>>().unwrap()
+    }
+
+    #[test]
+    fn is_due_at_the_local_minute() {
+        let subscription = AgendaSubscription::new(
+            NaiveTime::from_hms_opt(7, 30, 0).unwrap(),
+            Europe::Rome,
+            None,
+        );
+        assert!(subscription.is_due(&at("2024-08-17T07:30:00+02:00")));
+        assert!(subscription.is_due(&at("2024-08-17T07:30:59+02:00")));
+        assert!(!subscription.is_due(&at("2024-08-17T07:31:00+02:00")));
+        assert!(!subscription.is_due(&at("2024-08-17T07:30:00Z")));
+        assert!(subscription.is_due(&at("2024-12-17T06:30:00Z")));
+
+        let subscription = AgendaSubscription::new(
+            NaiveTime::from_hms_opt(7, 30, 0).unwrap(),
+            America::New_York,
+            None,
+        );
+        assert!(subscription.is_due(&at("2024-08-17T11:30:00Z")));
+    }
+
+    #[test]
+    fn day_of_spans_the_local_day() {
+        let (from, to) = day_of(&at("2024-08-17T23:30:00Z"), &Europe::Rome);
+        assert_eq!(at("2024-08-18T00:00:00+02:00"), from);
+        assert_eq!(at("2024-08-19T00:00:00+02:00"), to);
+    }
+
+    #[test]
+    fn day_of_follows_dst_changes() {
+        let (from, to) = day_of(&at("2024-03-31T12:00:00Z"), &Europe::Rome);
+        assert_eq!(at("2024-03-31T00:00:00+01:00"), from);
+        assert_eq!(Duration::hours(23), to - from);
+
+        let (from, to) = day_of(&at("2024-10-27T12:00:00Z"), &Europe::Rome);
+        assert_eq!(at("2024-10-27T00:00:00+02:00"), from);
+        assert_eq!(Duration::hours(25), to - from);
+    }
+}
@@ -1,214 +1,361 @@
-use std::num::NonZeroUsize;
+use std::{fmt, num::NonZeroUsize};
 
-struct BitmapIterator<'a> {
-    underlying: &'a Bitmap,
-    current_index: Option<usize>,
-}
-
-impl<'a> Iterator for BitmapIterator<'a> {
-    type Item = usize;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let result = self
-            .current_index
-            .and_then(|idx| self.underlying.next_set(idx));
-        self.current_index = result;
-        result
-    }
-}
+const WORD_BITS: usize = 64;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
-pub enum Bitmap {
-    Byte(u8),
-    Short(u16),
-    Int(u32),
-    Longs(Vec<u64>),
+pub struct Bitmap {
+    size: usize,
+    words: Vec<u64>,
 }
 
 impl Bitmap {
-    fn of_size(size: NonZeroUsize) -> Self {
-        match size.get() {
-            1..=8 => Self::Byte(0u8),
-            9..=16 => Self::Short(0u16),
-            17..=32 => Self::Int(0u32),
-            x => {
-                let v = (0..=(x / 64)).map(|_| 0u64).collect::<Vec<_>>();
-                Self::Longs(v)
-            }
+    pub fn empty(size: NonZeroUsize) -> Self {
+        Self {
+            size: size.get(),
+            words: vec![0u64; size.get().div_ceil(WORD_BITS)],
         }
     }
 
     pub fn explicitly_set(size: NonZeroUsize, bits: Vec<usize>) -> Self {
-        let mut result = Self::of_size(size);
+        let mut result = Self::empty(size);
         for &bit in bits.iter() {
-            result.set(bit)
+            let in_range = result.set(bit);
+            debug_assert!(in_range, "bit {bit} out of a bitmap of size {size}");
         }
         result
     }
 
     pub fn all_set(size: NonZeroUsize) -> Self {
-        let mut result = Self::of_size(size);
-        for i in 0..size.get() {
-            result.set(i)
-        }
+        let mut result = Self::empty(size);
+        result.fill();
         result
     }
 
-    pub fn set(&mut self, position: usize) {
-        self.set_internal(position, true)
+    pub fn from_bytes(size: NonZeroUsize, bytes: &[u8]) -> Result<Self, String> {
+        let mut result = Self::empty(size);
+        for (i, byte) in bytes.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (1u8 << bit) != 0 && !result.set(i * 8 + bit) {
+                    return Err(format!("Bit {} fuori dai {size} previsti", i * 8 + bit));
+                }
+            }
+        }
+        Ok(result)
     }
 
-    pub fn unset(&mut self, position: usize) {
-        self.set_internal(position, false)
+    pub fn size(&self) -> usize {
+        self.size
     }
 
-    pub fn clear(&mut self) {
-        *self = match self {
-            Self::Byte(_) => Self::Byte(0u8),
-            Self::Short(_) => Self::Short(0u16),
-            Self::Int(_) => Self::Int(0u32),
-            Self::Longs(v) => Self::Longs(v.iter_mut().map(|_| 0).collect()),
-        }
+    // set, unset and toggle return false when the position is out of range
+    pub fn set(&mut self, position: usize) -> bool {
+        self.locate(position)
+            .map(|(word, mask)| self.words[word] |= mask)
+            .is_some()
+    }
+
+    pub fn unset(&mut self, position: usize) -> bool {
+        self.locate(position)
+            .map(|(word, mask)| self.words[word] &= !mask)
+            .is_some()
+    }
+
+    pub fn toggle(&mut self, position: usize) -> bool {
+        self.locate(position)
+            .map(|(word, mask)| self.words[word] ^= mask)
+            .is_some()
     }
 
     pub fn get(&self, position: usize) -> bool {
-        match self {
-            Self::Byte(x) => x & (1u8 << position) != 0,
-            Self::Short(x) => x & (1u16 << position) != 0,
-            Self::Int(x) => x & (1u32 << position) != 0,
-            Self::Longs(v) if position < v.len() * 64 => {
-                v[position / 64] & (1u64 << (position % 64)) != 0
-            }
-            _ => false,
-        }
+        self.locate(position)
+            .is_some_and(|(word, mask)| self.words[word] & mask != 0)
     }
 
-    fn len(&self) -> usize {
-        match self {
-            Self::Byte(_) => 8,
-            Self::Short(_) => 16,
-            Self::Int(_) => 32,
-            Self::Longs(v) => v.len() * 64,
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|word| *word = 0);
+    }
+
+    pub fn fill(&mut self) {
+        self.words.iter_mut().for_each(|word| *word = u64::MAX);
+        self.trim();
+    }
+
+    pub fn count(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn and(&self, other: &Bitmap) -> Bitmap {
+        self.combine(other, |x, y| x & y)
+    }
+
+    pub fn or(&self, other: &Bitmap) -> Bitmap {
+        self.combine(other, |x, y| x | y)
+    }
+
+    pub fn iter(&self, from: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.next_set(from), |&current| self.next_set(current))
+    }
+
+    pub fn first_set(&self) -> Option<usize> {
+        match self.get(0) {
+            true => Some(0),
+            false => self.next_set(0),
         }
     }
 
-    fn next_set(&self, from: usize) -> Option<usize> {
-        (from + 1..self.len()).find(|&i| self.get(i))
+    fn locate(&self, position: usize) -> Option<(usize, u64)> {
+        (position < self.size).then(|| (position / WORD_BITS, 1u64 << (position % WORD_BITS)))
     }
 
-    fn set_internal(&mut self, position: usize, value: bool) {
-        let bit = if value { 1 } else { 0 };
-        match self {
-            Self::Byte(ref mut x) => *x |= (bit as u8) << position,
-            Self::Short(ref mut x) => *x |= (bit as u16) << position,
-            Self::Int(ref mut x) => *x |= (bit as u32) << position,
-            Self::Longs(ref mut v) if position < v.len() * 64 => {
-                v[position / 64] |= (bit as u64) << (position % 64)
+    fn next_set(&self, from: usize) -> Option<usize> {
+        let start = from.checked_add(1)?;
+        if start >= self.size {
+            return None;
+        }
+        let mut word = start / WORD_BITS;
+        let mut bits = self.words[word] & (u64::MAX << (start % WORD_BITS));
+        loop {
+            if bits != 0 {
+                return Some(word * WORD_BITS + bits.trailing_zeros() as usize);
             }
-            _ => (),
+            word += 1;
+            bits = *self.words.get(word)?;
         }
     }
 
-    pub fn iter(&self, from: usize) -> impl Iterator<Item = usize> + '_ {
-        BitmapIterator {
-            underlying: self,
-            current_index: Some(from),
+    fn combine(&self, other: &Bitmap, op: impl Fn(u64, u64) -> u64) -> Bitmap {
+        let words = self
+            .words
+            .iter()
+            .enumerate()
+            .map(|(i, &word)| op(word, other.words.get(i).copied().unwrap_or(0)))
+            .collect();
+        let mut result = Self {
+            size: self.size,
+            words,
+        };
+        result.trim();
+        result
+    }
+
+    fn trim(&mut self) {
+        let used = self.size % WORD_BITS;
+        if used != 0 {
+            if let Some(last) = self.words.last_mut() {
+                *last &= (1u64 << used) - 1;
+            }
         }
     }
 
-    pub fn first_set(&self) -> Option<usize> {
-        self.next_set(0usize)
+    fn nibble(&self, index: usize) -> u8 {
+        ((self.words[index * 4 / WORD_BITS] >> (index * 4 % WORD_BITS)) & 0xF) as u8
     }
 }
 
-impl From<u8> for Bitmap {
-    fn from(value: u8) -> Self {
-        Self::Byte(value)
+impl fmt::Display for Bitmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.size)?;
+        for index in (0..self.size.div_ceil(4)).rev() {
+            write!(f, "{:x}", self.nibble(index))?;
+        }
+        Ok(())
     }
 }
 
-impl From<u16> for Bitmap {
-    fn from(value: u16) -> Self {
-        Self::Short(value)
+impl std::str::FromStr for Bitmap {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (size, digits) = value
+            .split_once(':')
+            .ok_or_else(|| format!("Bitmap senza dimensione: {value}"))?;
+        let size = size
+            .parse::<NonZeroUsize>()
+            .map_err(|_| format!("Dimensione non valida: {size}"))?;
+        if digits.len() != size.get().div_ceil(4) {
+            return Err(format!(
+                "Attese {} cifre per {} bit, trovate {}",
+                size.get().div_ceil(4),
+                size,
+                digits.len()
+            ));
+        }
+
+        let mut result = Self::empty(size);
+        for (index, digit) in digits.chars().rev().enumerate() {
+            let nibble = digit
+                .to_digit(16)
+                .ok_or_else(|| format!("Cifra non valida: {digit}"))?;
+            for bit in 0..4 {
+                let position = index * 4 + bit;
+                if nibble & (1 << bit) == 0 {
+                    continue;
+                }
+                if position >= size.get() {
+                    return Err(format!("Bit {position} fuori dai {size} previsti"));
+                }
+                result.set(position);
+            }
+        }
+        Ok(result)
     }
 }
 
-impl From<u32> for Bitmap {
-    fn from(value: u32) -> Self {
-        Self::Int(value)
+impl Serialize for Bitmap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
     }
 }
 
-impl From<u64> for Bitmap {
-    fn from(value: u64) -> Self {
-        Self::Longs(vec![value])
+impl<'de> Deserialize<'de> for Bitmap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
     }
 }
 
-impl From<Vec<u64>> for Bitmap {
-    fn from(value: Vec<u64>) -> Self {
-        Self::Longs(value)
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use proptest::prelude::*;
+
+    use super::Bitmap;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Set(usize),
+        Unset(usize),
+        Toggle(usize),
+        Clear,
+        Fill,
     }
-}
 
-impl From<Vec<u8>> for Bitmap {
-    fn from(value: Vec<u8>) -> Self {
-        match value.len() {
-            1 => Self::Byte(value[0]),
-            2 => Self::Short(
-                value
-                    .into_iter()
-                    .enumerate()
-                    .fold(0u16, |v, (i, x)| v | ((x as u16) << (i * 8))),
-            ),
-            3..=4 => Self::Int(
-                value
-                    .into_iter()
-                    .enumerate()
-                    .fold(0u32, |v, (i, x)| v | ((x as u32) << (i * 8))),
-            ),
-            _ => Self::Longs(
-                value
-                    .chunks(8)
-                    .map(|x| {
-                        x.iter()
-                            .enumerate()
-                            .fold(0u64, |v, (i, x)| v | ((*x as u64) << (i * 8)))
-                    })
-                    .collect(),
-            ),
-        }
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..80usize).prop_map(Op::Set),
+            (0..80usize).prop_map(Op::Unset),
+            (0..80usize).prop_map(Op::Toggle),
+            Just(Op::Clear),
+            Just(Op::Fill),
+        ]
     }
-}
 
-impl From<Bitmap> for Vec<u8> {
-    fn from(value: Bitmap) -> Self {
-        match value {
-            Bitmap::Byte(x) => vec![x],
-            Bitmap::Short(x) => split_u16(x),
-            Bitmap::Int(x) => split_u32(x),
-            Bitmap::Longs(v) => v
-                .into_iter()
-                .flat_map(|x| split_u64(x).into_iter())
-                .collect(),
+    fn apply(bitmap: &mut Bitmap, model: &mut [bool], op: &Op) {
+        match *op {
+            Op::Set(i) => {
+                assert_eq!(i < model.len(), bitmap.set(i));
+                if let Some(bit) = model.get_mut(i) {
+                    *bit = true;
+                }
+            }
+            Op::Unset(i) => {
+                assert_eq!(i < model.len(), bitmap.unset(i));
+                if let Some(bit) = model.get_mut(i) {
+                    *bit = false;
+                }
+            }
+            Op::Toggle(i) => {
+                assert_eq!(i < model.len(), bitmap.toggle(i));
+                if let Some(bit) = model.get_mut(i) {
+                    *bit = !*bit;
+                }
+            }
+            Op::Clear => {
+                bitmap.clear();
+                model.iter_mut().for_each(|bit| *bit = false);
+            }
+            Op::Fill => {
+                bitmap.fill();
+                model.iter_mut().for_each(|bit| *bit = true);
+            }
         }
     }
-}
 
-fn split_u16(x: u16) -> Vec<u8> {
-    (0u8..2u8)
-        .map(|l| (((0xFF << (l * 8)) & x) >> (l * 8)) as u8)
-        .collect::<Vec<u8>>()
-}
+    fn bitmap_and_model() -> impl Strategy<Value = (Bitmap, Vec<bool>)> {
+        (1..72usize, prop::collection::vec(op(), 0..40)).prop_map(|(size, ops)| {
+            let mut bitmap = Bitmap::empty(NonZeroUsize::new(size).unwrap());
+            let mut model = vec![false; size];
+            for op in ops.iter() {
+                apply(&mut bitmap, &mut model, op);
+            }
+            (bitmap, model)
+        })
+    }
 
-fn split_u32(x: u32) -> Vec<u8> {
-    (0u8..4u8)
-        .map(|l| (((0xFF << (l * 8)) & x) >> (l * 8)) as u8)
-        .collect::<Vec<u8>>()
-}
+    fn model_set(model: &[bool]) -> Vec<usize> {
+        (0..model.len()).filter(|&i| model[i]).collect()
+    }
+
+    proptest! {
+        #[test]
+        fn behaves_like_the_model((bitmap, model) in bitmap_and_model()) {
+            for i in 0..100 {
+                prop_assert_eq!(bitmap.get(i), model.get(i).copied().unwrap_or(false));
+            }
+            prop_assert_eq!(bitmap.count(), model.iter().filter(|&&bit| bit).count());
+            prop_assert_eq!(bitmap.first_set(), model.iter().position(|&bit| bit));
+        }
+
+        #[test]
+        fn iterates_set_bits_after_position((bitmap, model) in bitmap_and_model(), from in 0..80usize) {
+            let expected = model_set(&model).into_iter().filter(|&i| i > from).collect::<Vec<_>>();
+            prop_assert_eq!(bitmap.iter(from).collect::<Vec<_>>(), expected);
+        }
+
+        #[test]
+        fn combines_like_the_model((left, left_model) in bitmap_and_model(), (right, right_model) in bitmap_and_model()) {
+            let and = left.and(&right);
+            let or = left.or(&right);
+            prop_assert_eq!(and.size(), left.size());
+            prop_assert_eq!(or.size(), left.size());
+            for (i, &bit) in left_model.iter().enumerate() {
+                let other = right_model.get(i).copied().unwrap_or(false);
+                prop_assert_eq!(and.get(i), bit && other);
+                prop_assert_eq!(or.get(i), bit || other);
+            }
+            prop_assert_eq!(or.get(left.size()), false);
+        }
+
+        #[test]
+        fn round_trips_through_compact_format((bitmap, _) in bitmap_and_model()) {
+            let text = bitmap.to_string();
+            prop_assert_eq!(text.parse::<Bitmap>(), Ok(bitmap.clone()));
+            let json = serde_json::to_string(&bitmap).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Bitmap>(&json).unwrap(), bitmap);
+        }
+
+        #[test]
+        fn round_trips_through_legacy_bytes((bitmap, model) in bitmap_and_model()) {
+            let mut bytes = vec![0u8; model.len().div_ceil(8)];
+            for i in model_set(&model) {
+                bytes[i / 8] |= 1 << (i % 8);
+            }
+            prop_assert_eq!(Bitmap::from_bytes(NonZeroUsize::new(model.len()).unwrap(), &bytes), Ok(bitmap));
+        }
+    }
 
-fn split_u64(x: u64) -> Vec<u8> {
-    (0u8..8u8)
-        .map(|l| (((0xFF << (l * 8)) & x) >> (l * 8)) as u8)
-        .collect::<Vec<u8>>()
+    #[test]
+    fn compact_format() {
+        let minutes = Bitmap::explicitly_set(NonZeroUsize::new(60).unwrap(), vec![0, 30, 59]);
+        assert_eq!(minutes.to_string(), "60:800000040000001");
+        assert_eq!(
+            Bitmap::explicitly_set(NonZeroUsize::new(7).unwrap(), vec![5, 6]).to_string(),
+            "7:60"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_compact_format() {
+        assert!("60".parse::<Bitmap>().is_err());
+        assert!("0:0".parse::<Bitmap>().is_err());
+        assert!("7:0".parse::<Bitmap>().is_err());
+        assert!("7:80".parse::<Bitmap>().is_err());
+        assert!("7:0g".parse::<Bitmap>().is_err());
+    }
 }
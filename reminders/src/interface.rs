@@ -1,15 +1,20 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     num::{NonZeroU8, NonZeroUsize},
     sync::{Arc, Mutex, MutexGuard},
     time::Duration,
 };
 
+pub use crate::agenda::{day_of, AgendaEntry, AgendaSubscription};
+pub use crate::lease::{LeaseProvider, LocalLeaseProvider, LEASE_TTL};
+pub use crate::payload::{Attachment, Payload};
+pub use crate::quiet::QuietHours;
+pub use crate::text::ast::TimeExpr;
+pub use crate::text::diagnostics::*;
 pub use crate::text::parsing::*;
 use async_trait::async_trait;
 use chrono::{DateTime, Month, NaiveTime, Timelike, Utc, Weekday};
 use chrono_tz::Tz;
-use futures::{pin_mut, StreamExt};
 use mongodb::Client;
 use tokio::sync::Mutex as AsyncMutex;
 use tokio::{
@@ -19,10 +24,16 @@ use tokio::{
 
 use crate::{
     bitmap::Bitmap,
-    memory::{persistent::MongoloidStorage, transient::InMemoryStorage},
+    memory::{persistent::MongoloidStorage, store::ReminderStore, transient::InMemoryStorage},
     schedule::ScheduleGrid,
+    text::cron,
 };
 
+const RECONCILIATION_PERIOD: Duration = Duration::from_secs(10 * 60);
+const STATUS_NEXT_TICKS: usize = 10;
+const STATUS_FAILURES: i64 = 5;
+const INTERVAL_SAMPLE: usize = 1500;
+
 pub trait TimeProvider {
     fn now(&self) -> DateTime<Utc>;
 }
@@ -42,74 +53,234 @@ enum EngineMessage {
 
 #[async_trait]
 pub trait ReminderCallback {
-    async fn call(&self, user: u64, reminder_id: i32, message: Arc<String>);
+    async fn call(&self, user: u64, reminder_id: i32, payload: Payload) -> Result<(), String>;
+    async fn digest(&self, user: u64, reminders: Vec<(i32, Payload)>) -> Result<(), String>;
+    async fn agenda(
+        &self,
+        user: u64,
+        entries: Vec<AgendaEntry>,
+        subscription: AgendaSubscription,
+    ) -> Result<(), String>;
 }
 
+// held reminders of each recipient, flagged with whether the store has them too
+type HeldReminders = Arc<Mutex<HashMap<u64, Vec<(i32, Payload, bool)>>>>;
+
 pub struct ReminderEngine {
     storage: Arc<Mutex<InMemoryStorage>>,
     time_provider: Arc<dyn TimeProvider + Send + Sync>,
     receiver: AsyncMutex<Receiver<EngineMessage>>,
     sender: Sender<EngineMessage>,
     callback: Arc<dyn ReminderCallback + Send + Sync>,
-    permanent_storage: MongoloidStorage,
+    leases: Arc<dyn LeaseProvider + Send + Sync>,
+    permanent_storage: Arc<dyn ReminderStore + Send + Sync>,
+    quiet_hours: Mutex<HashMap<u64, QuietHours>>,
+    agenda_subscriptions: Mutex<HashMap<u64, AgendaSubscription>>,
+    held: HeldReminders,
+    running_since: Mutex<Option<DateTime<Utc>>>,
 }
 
 impl ReminderEngine {
     pub async fn new_and_init(
         time_provider: Arc<dyn TimeProvider + Send + Sync>,
         callback: Arc<dyn ReminderCallback + Send + Sync>,
+        leases: Arc<dyn LeaseProvider + Send + Sync>,
         mongo_url: &str,
         mongo_db: &str,
     ) -> Self {
-        let (sender, receiver) = channel::<EngineMessage>(512);
         let db = Client::with_uri_str(mongo_url)
             .await
             .unwrap()
             .database(mongo_db);
+        Self::with_store(
+            time_provider,
+            callback,
+            leases,
+            Arc::new(MongoloidStorage::new(db)),
+        )
+        .await
+    }
+
+    pub(crate) async fn with_store(
+        time_provider: Arc<dyn TimeProvider + Send + Sync>,
+        callback: Arc<dyn ReminderCallback + Send + Sync>,
+        leases: Arc<dyn LeaseProvider + Send + Sync>,
+        permanent_storage: Arc<dyn ReminderStore + Send + Sync>,
+    ) -> Self {
+        let (sender, receiver) = channel::<EngineMessage>(512);
         let ret = Self {
             storage: Arc::new(Mutex::new(InMemoryStorage::new())),
             time_provider: time_provider.clone(),
             receiver: AsyncMutex::new(receiver),
             sender,
             callback,
-            permanent_storage: MongoloidStorage::new(db),
+            leases,
+            permanent_storage,
+            quiet_hours: Mutex::new(HashMap::new()),
+            agenda_subscriptions: Mutex::new(HashMap::new()),
+            held: Arc::new(Mutex::new(HashMap::new())),
+            running_since: Mutex::new(None),
         };
 
         tracing::info!("Initialising state");
         let start = Instant::now();
-        let records = ret.permanent_storage.get_all().await;
-        pin_mut!(records);
+        let (migrated, quarantined) = ret.permanent_storage.migrate().await;
+        if migrated > 0 || quarantined > 0 {
+            tracing::info!(
+                migrated_count = migrated,
+                quarantined_count = quarantined,
+                "Migrated reminders to the current schema"
+            );
+        }
+        ret.refresh_preferences().await;
         let now = time_provider.now();
-        while let Some(record) = records.next().await {
-            record
-                .ok()
-                .map(|(definition, id)| ret.obtain_storage().insert(definition, &now, Some(id)));
+        let mut expired = vec![];
+        for (definition, id) in ret.permanent_storage.get_all().await.unwrap_or_default() {
+            let user_id = definition.user_id();
+            if ret.obtain_storage().insert(definition, &now, id).is_none() {
+                expired.push((user_id, id));
+            }
+        }
+        let cleaned = ret.archive_all(expired).await;
+        let mut held = ret
+            .permanent_storage
+            .get_all_held()
+            .await
+            .unwrap_or_default();
+        // the latest window of each recipient schedules the digest, so that it covers every entry
+        held.sort_by_key(|(_, _, _, until)| std::cmp::Reverse(*until));
+        let held_count = held.len();
+        for (recipient, reminder_id, payload, until) in held {
+            ret.enqueue_held(recipient, reminder_id, payload, until, true);
         }
         let size = ret.obtain_storage().size();
         let elapsed = start.elapsed().as_micros();
         tracing::info!(
             elapsed_micros = elapsed,
             record_count = size,
+            cleaned_count = cleaned,
+            held_count = held_count,
             "State is initialised"
         );
 
         ret
     }
 
+    pub async fn sweep(&self) -> usize {
+        let now = self.time_provider.now();
+        let expired = self
+            .permanent_storage
+            .get_all()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(definition, _)| definition.next_tick(&now).is_none())
+            .map(|(definition, id)| (definition.user_id(), id))
+            .collect::<Vec<_>>();
+        self.archive_all(expired).await
+    }
+
+    pub async fn reconcile(&self) -> (usize, usize) {
+        self.refresh_preferences().await;
+        let in_memory = self.obtain_storage().live_ids();
+        let stored = match self.permanent_storage.get_all().await {
+            Some(stored) => stored,
+            None => return (0, 0),
+        };
+        let now = self.time_provider.now();
+
+        let mut restored = 0usize;
+        let mut expired = vec![];
+        let mut stored_ids = HashSet::new();
+        for (definition, id) in stored {
+            let user_id = definition.user_id();
+            stored_ids.insert((user_id, id));
+            if in_memory.contains(&(user_id, id)) {
+                continue;
+            }
+            tracing::warn!(
+                user_id = user_id,
+                reminder_id = id,
+                "Reminder is stored but not scheduled"
+            );
+            match self.obtain_storage().insert(definition, &now, id) {
+                Some(_) => restored += 1,
+                None => expired.push((user_id, id)),
+            }
+        }
+        if restored > 0 {
+            let _ = self.sender.try_send(EngineMessage::WakeUp);
+        }
+        if !expired.is_empty() {
+            self.archive_all(expired).await;
+        }
+
+        let mut dropped = 0usize;
+        for (user_id, id) in in_memory.difference(&stored_ids) {
+            tracing::warn!(
+                user_id = user_id,
+                reminder_id = id,
+                "Reminder is scheduled but not stored"
+            );
+            self.internal_defuse(user_id, id);
+            dropped += 1;
+        }
+        (restored, dropped)
+    }
+
+    pub async fn run_reconciliation(&self) {
+        loop {
+            tokio::time::sleep(RECONCILIATION_PERIOD).await;
+            let start = Instant::now();
+            let (restored, dropped) = self.reconcile().await;
+            tracing::info!(
+                elapsed_micros = start.elapsed().as_micros(),
+                restored_count = restored,
+                dropped_count = dropped,
+                "Reconciled scheduled reminders with storage"
+            );
+        }
+    }
+
+    pub async fn status(&self) -> EngineStatus {
+        let status = self.obtain_storage().status(STATUS_NEXT_TICKS);
+        EngineStatus {
+            failures: self.permanent_storage.get_failures(STATUS_FAILURES).await,
+            running_since: *self.running_since.lock().unwrap(),
+            ..status
+        }
+    }
+
     pub fn stop(&self) -> bool {
         self.sender.try_send(EngineMessage::Stop).is_ok()
     }
 
     pub async fn add(&self, def: ReminderDefinition) -> Option<i32> {
-        let id = self
-            .obtain_storage()
-            .insert(def.clone(), &self.time_provider.now(), None)?;
-        let _ = self.sender.try_send(EngineMessage::WakeUp);
+        let now = self.time_provider.now();
+        def.next_tick(&now)?;
+        let id = self.obtain_storage().allocate_id();
         if !self.permanent_storage.create(&def, id).await {
-            self.internal_defuse(&def.user_id, &id);
-            None
+            return None;
+        }
+        self.obtain_storage().insert(def, &now, id);
+        let _ = self.sender.try_send(EngineMessage::WakeUp);
+        Some(id)
+    }
+
+    pub async fn unsubscribe(&self, user: u64, id: i32) -> bool {
+        let owner = match self.obtain_storage().owner_of_shared(&user, &id) {
+            Some(owner) => owner,
+            None => return false,
+        };
+        if self
+            .permanent_storage
+            .remove_recipient(owner, id, user)
+            .await
+        {
+            self.obtain_storage().unsubscribe(&owner, &id, &user);
+            true
         } else {
-            Some(id)
+            false
         }
     }
 
@@ -123,6 +294,7 @@ impl ReminderEngine {
     }
 
     pub async fn run(&self) {
+        *self.running_since.lock().unwrap() = Some(self.time_provider.now());
         loop {
             let reminder = match self.dequeue_next() {
                 None => match self.listen().await {
@@ -145,20 +317,21 @@ impl ReminderEngine {
                 );
                 tokio::select! {
                     _ = tokio::time::sleep(Duration::from_millis(time_to_wait)) => {
-                        tokio::spawn({
-                            let message = reminder.message().clone();
-                            let callback = self.callback.clone();
-                            async move {
-                                let start = Instant::now();
-                                callback.call(user_id, reminder_id, message).await;
-                                tracing::info!(
-                                    elapsed_micros = start.elapsed().as_micros(),
-                                    user_id = user_id,
-                                    reminder_id = reminder_id,
-                                    "Executed reminder callback"
-                                );
+                        let claimed = self.claim_occurrence(user_id, reminder_id, &date).await;
+                        if claimed {
+                            let recipients = std::iter::once(user_id).chain(reminder.recipients().iter().copied());
+                            for recipient in recipients {
+                                self.deliver(recipient, reminder_id, reminder.payload().clone(), reminder.is_urgent()).await;
                             }
-                        });
+                        }
+                        let counted = self.obtain_storage().consume_occurrence(&user_id, &reminder_id);
+                        if claimed && counted && !self.permanent_storage.consume_occurrence(user_id, reminder_id).await {
+                            tracing::error!(
+                                user_id = user_id,
+                                reminder_id = reminder_id,
+                                "Could not store remaining occurrences"
+                            );
+                        }
                     }
                     message = self.listen() => {
                         if let None | Some(EngineMessage::Stop) = message {return};
@@ -171,18 +344,369 @@ impl ReminderEngine {
                 reminder_id = reminder_id,
                 "Reinserting reminder"
             );
-            self.advance(reminder);
+            if !self.advance(reminder) && self.permanent_storage.archive(user_id, reminder_id).await
+            {
+                tracing::info!(
+                    user_id = user_id,
+                    reminder_id = reminder_id,
+                    "Archived finished reminder"
+                );
+            }
+        }
+    }
+
+    pub async fn run_agenda(&self) {
+        loop {
+            let now = self.time_provider.now();
+            let to_next_minute = 60_000 - (now.timestamp_millis().rem_euclid(60_000)) as u64;
+            tokio::time::sleep(Duration::from_millis(to_next_minute)).await;
+
+            let now = self.time_provider.now();
+            let due = self
+                .agenda_subscriptions
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, subscription)| subscription.is_due(&now))
+                .map(|(user_id, subscription)| (*user_id, subscription.clone()))
+                .collect::<Vec<_>>();
+            for (user_id, subscription) in due {
+                let (from, to) = subscription.day_of(&now);
+                let key = format!("agenda:{user_id}:{}", from.timestamp_millis());
+                if !self.claim(&key).await {
+                    continue;
+                }
+                let entries = self.agenda(&user_id, &from, &to);
+                tokio::spawn({
+                    let callback = self.callback.clone();
+                    async move {
+                        let outcome = callback.agenda(user_id, entries, subscription).await;
+                        tracing::info!(
+                            user_id = user_id,
+                            success = outcome.is_ok(),
+                            "Delivered daily agenda"
+                        );
+                    }
+                });
+            }
+        }
+    }
+
+    pub fn agenda(
+        &self,
+        user_id: &u64,
+        from: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Vec<AgendaEntry> {
+        self.obtain_storage().occurrences_between(user_id, from, to)
+    }
+
+    pub fn agenda_subscription(&self, user_id: &u64) -> Option<AgendaSubscription> {
+        self.agenda_subscriptions
+            .lock()
+            .unwrap()
+            .get(user_id)
+            .cloned()
+    }
+
+    pub async fn subscribe_agenda(
+        &self,
+        user_id: u64,
+        subscription: Option<AgendaSubscription>,
+    ) -> bool {
+        if !self
+            .permanent_storage
+            .set_agenda_subscription(user_id, subscription.as_ref())
+            .await
+        {
+            return false;
+        }
+        let mut lookup = self.agenda_subscriptions.lock().unwrap();
+        match subscription {
+            Some(subscription) => lookup.insert(user_id, subscription),
+            None => lookup.remove(&user_id),
+        };
+        true
+    }
+
+    pub fn quiet_hours(&self, user_id: &u64) -> Option<QuietHours> {
+        self.quiet_hours.lock().unwrap().get(user_id).copied()
+    }
+
+    pub async fn set_quiet_hours(&self, user_id: u64, quiet_hours: Option<QuietHours>) -> bool {
+        if !self
+            .permanent_storage
+            .set_quiet_hours(user_id, quiet_hours.as_ref())
+            .await
+        {
+            return false;
         }
+        let mut lookup = self.quiet_hours.lock().unwrap();
+        match quiet_hours {
+            Some(quiet_hours) => lookup.insert(user_id, quiet_hours),
+            None => lookup.remove(&user_id),
+        };
+        true
     }
 
     pub fn get(&self, user_id: &u64, reminder_id: &i32) -> Option<Reminder> {
         self.obtain_storage().get(user_id, reminder_id)
     }
 
-    pub fn get_all(&self, user_id: &u64) -> HashMap<i32, Reminder> {
+    pub fn get_all(&self, user_id: &u64) -> Vec<Reminder> {
         self.obtain_storage().get_all(user_id)
     }
 
+    pub fn active_count(&self, user_id: &u64) -> usize {
+        self.obtain_storage().active_count(user_id)
+    }
+
+    pub fn search(&self, user_id: &u64, query: &str) -> Vec<Reminder> {
+        self.obtain_storage().search(user_id, query)
+    }
+
+    pub fn get_tagged(&self, user_id: &u64, tag: &str) -> Vec<Reminder> {
+        self.obtain_storage().get_tagged(user_id, tag)
+    }
+
+    pub async fn defuse_tagged(&self, user_id: u64, tag: &str) -> usize {
+        let ids = self
+            .get_tagged(&user_id, tag)
+            .iter()
+            .map(|reminder| reminder.reminder_id().1)
+            .collect::<Vec<_>>();
+        let mut defused = 0usize;
+        for id in ids {
+            if self.defuse(user_id, id).await {
+                defused += 1;
+            }
+        }
+        defused
+    }
+
+    pub async fn history(&self, user_id: u64, limit: i64) -> Vec<Firing> {
+        self.permanent_storage
+            .get_firings(user_id, Some(limit))
+            .await
+    }
+
+    pub async fn stats(&self, user_id: u64) -> Vec<ReminderStats> {
+        ReminderStats::from_firings(&self.permanent_storage.get_firings(user_id, None).await)
+    }
+
+    pub async fn acknowledge(&self, user_id: u64, reminder_id: i32) -> bool {
+        self.permanent_storage
+            .acknowledge_last_firing(user_id, reminder_id)
+            .await
+    }
+
+    async fn deliver(&self, recipient: u64, reminder_id: i32, payload: Payload, urgent: bool) {
+        let now = self.time_provider.now();
+        let quiet_until = match urgent {
+            true => None,
+            false => self
+                .quiet_hours(&recipient)
+                .and_then(|quiet_hours| quiet_hours.end_of_window(&now)),
+        };
+        if let Some(until) = quiet_until {
+            return self.hold(recipient, reminder_id, payload, until).await;
+        }
+
+        tokio::spawn({
+            let callback = self.callback.clone();
+            let permanent_storage = self.permanent_storage.clone();
+            let time_provider = self.time_provider.clone();
+            async move {
+                let start = Instant::now();
+                let outcome = callback.call(recipient, reminder_id, payload).await;
+                tracing::info!(
+                    elapsed_micros = start.elapsed().as_micros(),
+                    user_id = recipient,
+                    reminder_id = reminder_id,
+                    success = outcome.is_ok(),
+                    "Executed reminder callback"
+                );
+                let firing = Firing::new(
+                    recipient,
+                    reminder_id,
+                    time_provider.now(),
+                    outcome.into(),
+                    false,
+                );
+                Self::record_firing(permanent_storage.as_ref(), &firing).await;
+            }
+        });
+    }
+
+    async fn hold(&self, recipient: u64, reminder_id: i32, payload: Payload, until: DateTime<Utc>) {
+        let stored = self
+            .permanent_storage
+            .hold(recipient, reminder_id, &payload, &until)
+            .await;
+        if !stored {
+            tracing::warn!(
+                user_id = recipient,
+                reminder_id = reminder_id,
+                "Unable to store held reminder"
+            );
+        }
+        self.enqueue_held(recipient, reminder_id, payload, until, stored);
+    }
+
+    fn enqueue_held(
+        &self,
+        recipient: u64,
+        reminder_id: i32,
+        payload: Payload,
+        until: DateTime<Utc>,
+        stored: bool,
+    ) {
+        let is_first = {
+            let mut held = self.held.lock().unwrap();
+            let batch = held.entry(recipient).or_default();
+            batch.push((reminder_id, payload, stored));
+            batch.len() == 1
+        };
+        tracing::info!(
+            user_id = recipient,
+            reminder_id = reminder_id,
+            until = until.to_rfc3339(),
+            "Holding reminder during quiet hours"
+        );
+        if !is_first {
+            return;
+        }
+
+        let time_to_wait = until
+            .signed_duration_since(self.time_provider.now())
+            .num_milliseconds()
+            .max(0) as u64;
+        tokio::spawn({
+            let callback = self.callback.clone();
+            let permanent_storage = self.permanent_storage.clone();
+            let time_provider = self.time_provider.clone();
+            let leases = self.leases.clone();
+            let held = self.held.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(time_to_wait)).await;
+                let key = format!("digest:{recipient}:{}", until.timestamp_millis());
+                let winner = Self::acquire(leases.as_ref(), &key).await;
+                // the store has whatever any instance held for this window, only
+                // the reminders that could not be stored are left to each instance
+                let mut batch = if winner {
+                    permanent_storage
+                        .get_held(recipient, &until)
+                        .await
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(_, reminder_id, payload, _)| (reminder_id, payload))
+                        .collect()
+                } else {
+                    vec![]
+                };
+                batch.extend(
+                    held.lock()
+                        .unwrap()
+                        .remove(&recipient)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|(_, _, stored)| !stored)
+                        .map(|(reminder_id, payload, _)| (reminder_id, payload)),
+                );
+                if batch.is_empty() {
+                    return;
+                }
+                let outcome: DeliveryOutcome =
+                    callback.digest(recipient, batch.clone()).await.into();
+                tracing::info!(
+                    user_id = recipient,
+                    reminder_count = batch.len(),
+                    success = outcome == DeliveryOutcome::Delivered,
+                    "Delivered quiet hours digest"
+                );
+                let now = time_provider.now();
+                for (reminder_id, _) in batch {
+                    let firing = Firing::new(recipient, reminder_id, now, outcome.clone(), false);
+                    Self::record_firing(permanent_storage.as_ref(), &firing).await;
+                }
+                if winner && !permanent_storage.release(recipient, &until).await {
+                    tracing::warn!(user_id = recipient, "Unable to release held reminders");
+                }
+            }
+        });
+    }
+
+    async fn refresh_preferences(&self) {
+        if let Some(quiet_hours) = self.permanent_storage.get_all_quiet_hours().await {
+            *self.quiet_hours.lock().unwrap() = quiet_hours;
+        }
+        if let Some(subscriptions) = self.permanent_storage.get_all_agenda_subscriptions().await {
+            *self.agenda_subscriptions.lock().unwrap() = subscriptions;
+        }
+    }
+
+    async fn claim_occurrence(&self, user_id: u64, reminder_id: i32, tick: &DateTime<Utc>) -> bool {
+        let key = format!(
+            "reminder:{user_id}:{reminder_id}:{}",
+            tick.timestamp_millis()
+        );
+        if !self.claim(&key).await {
+            tracing::info!(
+                user_id = user_id,
+                reminder_id = reminder_id,
+                "Occurrence claimed by another instance"
+            );
+            return false;
+        }
+        if !self.permanent_storage.exists(user_id, reminder_id).await {
+            tracing::info!(
+                user_id = user_id,
+                reminder_id = reminder_id,
+                "Reminder was removed by another instance"
+            );
+            self.internal_defuse(&user_id, &reminder_id);
+            return false;
+        }
+        true
+    }
+
+    async fn claim(&self, key: &str) -> bool {
+        Self::acquire(self.leases.as_ref(), key).await
+    }
+
+    async fn acquire(leases: &(dyn LeaseProvider + Send + Sync), key: &str) -> bool {
+        leases.try_acquire(key).await.unwrap_or_else(|e| {
+            tracing::warn!(
+                key = key,
+                error = e,
+                "Cannot acquire lease, proceeding anyway"
+            );
+            true
+        })
+    }
+
+    async fn record_firing(permanent_storage: &(dyn ReminderStore + Send + Sync), firing: &Firing) {
+        if !permanent_storage.record_firing(firing).await {
+            let (user_id, reminder_id) = firing.reminder_id();
+            tracing::warn!(
+                user_id = user_id,
+                reminder_id = reminder_id,
+                "Unable to record reminder firing"
+            );
+        }
+    }
+
+    async fn archive_all(&self, reminders: Vec<(u64, i32)>) -> usize {
+        let mut cleaned = 0usize;
+        for (user_id, reminder_id) in reminders {
+            if self.permanent_storage.archive(user_id, reminder_id).await {
+                cleaned += 1;
+            }
+        }
+        tracing::info!(cleaned_count = cleaned, "Archived expired reminders");
+        cleaned
+    }
+
     fn dequeue_next(&self) -> Option<Reminder> {
         self.obtain_storage().dequeue_next()
     }
@@ -191,16 +715,16 @@ impl ReminderEngine {
         self.obtain_storage().defuse(user_id, id);
     }
 
-    fn advance(&self, reminder: Reminder) {
+    fn advance(&self, reminder: Reminder) -> bool {
         self.obtain_storage()
-            .advance(reminder, &self.time_provider.now());
+            .advance(reminder, &self.time_provider.now())
     }
 
     async fn listen(&self) -> Option<EngineMessage> {
         self.receiver.lock().await.recv().await
     }
 
-    fn obtain_storage(&self) -> MutexGuard<InMemoryStorage> {
+    fn obtain_storage(&self) -> MutexGuard<'_, InMemoryStorage> {
         self.storage.lock().unwrap()
     }
 }
@@ -209,7 +733,13 @@ pub struct Reminder {
     user_id: u64,
     id: i32,
     current_tick: Option<DateTime<Utc>>,
-    message: Arc<String>,
+    payload: Payload,
+    recipients: Vec<u64>,
+    tags: Vec<String>,
+    urgent: bool,
+    remaining: Option<u32>,
+    cron: Option<String>,
+    generation: u64,
 }
 
 impl Reminder {
@@ -217,16 +747,64 @@ impl Reminder {
         user_id: u64,
         id: i32,
         current_tick: Option<DateTime<Utc>>,
-        message: Arc<String>,
+        payload: Payload,
+        recipients: Vec<u64>,
+        tags: Vec<String>,
+        urgent: bool,
     ) -> Self {
         Self {
             user_id,
             id,
             current_tick,
-            message,
+            payload,
+            recipients,
+            tags,
+            urgent,
+            remaining: None,
+            cron: None,
+            generation: 0,
         }
     }
 
+    pub fn with_remaining(mut self, remaining: Option<u32>) -> Self {
+        self.remaining = remaining;
+        self
+    }
+
+    pub fn remaining(&self) -> Option<u32> {
+        self.remaining
+    }
+
+    pub fn with_cron(mut self, cron: Option<String>) -> Self {
+        self.cron = cron;
+        self
+    }
+
+    pub(crate) fn with_generation(mut self, generation: u64) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn cron(&self) -> Option<&str> {
+        self.cron.as_deref()
+    }
+
+    pub fn is_urgent(&self) -> bool {
+        self.urgent
+    }
+
+    pub fn recipients(&self) -> &[u64] {
+        &self.recipients
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
     pub fn reminder_id(&self) -> (u64, i32) {
         (self.user_id, self.id)
     }
@@ -236,7 +814,120 @@ impl Reminder {
     }
 
     pub fn message(&self) -> Arc<String> {
-        self.message.clone()
+        self.payload.message()
+    }
+
+    pub fn attachment(&self) -> Option<&Attachment> {
+        self.payload.attachment()
+    }
+
+    pub fn payload(&self) -> &Payload {
+        &self.payload
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    Delivered,
+    Failed(String),
+}
+
+impl From<Result<(), String>> for DeliveryOutcome {
+    fn from(value: Result<(), String>) -> Self {
+        match value {
+            Ok(()) => Self::Delivered,
+            Err(reason) => Self::Failed(reason),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Firing {
+    user_id: u64,
+    reminder_id: i32,
+    fired_at: DateTime<Utc>,
+    outcome: DeliveryOutcome,
+    acknowledged: bool,
+}
+
+impl Firing {
+    pub fn new(
+        user_id: u64,
+        reminder_id: i32,
+        fired_at: DateTime<Utc>,
+        outcome: DeliveryOutcome,
+        acknowledged: bool,
+    ) -> Self {
+        Self {
+            user_id,
+            reminder_id,
+            fired_at,
+            outcome,
+            acknowledged,
+        }
+    }
+
+    pub fn reminder_id(&self) -> (u64, i32) {
+        (self.user_id, self.reminder_id)
+    }
+
+    pub fn fired_at(&self) -> &DateTime<Utc> {
+        &self.fired_at
+    }
+
+    pub fn outcome(&self) -> &DeliveryOutcome {
+        &self.outcome
+    }
+
+    pub fn is_acknowledged(&self) -> bool {
+        self.acknowledged
+    }
+}
+
+pub struct EngineStatus {
+    pub active: usize,
+    pub per_user: Vec<(u64, usize)>,
+    pub next_ticks: Vec<(DateTime<Utc>, u64, i32)>,
+    pub queue_size: usize,
+    pub lookup_size: usize,
+    pub failures: Vec<Firing>,
+    pub running_since: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReminderStats {
+    pub reminder_id: i32,
+    pub firings: usize,
+    pub delivered: usize,
+    pub acknowledged: usize,
+}
+
+impl ReminderStats {
+    pub fn from_firings(firings: &[Firing]) -> Vec<Self> {
+        let mut stats = HashMap::<i32, Self>::new();
+        for firing in firings {
+            let entry = stats.entry(firing.reminder_id).or_insert_with(|| Self {
+                reminder_id: firing.reminder_id,
+                ..Default::default()
+            });
+            entry.firings += 1;
+            if firing.outcome == DeliveryOutcome::Delivered {
+                entry.delivered += 1;
+            }
+            if firing.acknowledged && firing.outcome == DeliveryOutcome::Delivered {
+                entry.acknowledged += 1;
+            }
+        }
+        let mut stats = stats.into_values().collect::<Vec<_>>();
+        stats.sort_by_key(|s| s.reminder_id);
+        stats
+    }
+
+    pub fn completion_rate(&self) -> Option<f64> {
+        match self.delivered {
+            0 => None,
+            delivered => Some(self.acknowledged as f64 / delivered as f64),
+        }
     }
 }
 
@@ -244,7 +935,10 @@ impl Reminder {
 pub struct ReminderDefinition {
     schedule: Schedule,
     user_id: u64,
-    message: Arc<String>,
+    payload: Payload,
+    recipients: Vec<u64>,
+    tags: Vec<String>,
+    urgent: bool,
 }
 
 impl ReminderDefinition {
@@ -252,10 +946,66 @@ impl ReminderDefinition {
         Self {
             schedule,
             user_id,
-            message: Arc::new(message),
+            payload: Payload::new(Arc::new(message), None),
+            recipients: vec![],
+            tags: vec![],
+            urgent: false,
         }
     }
 
+    pub fn with_attachment(mut self, attachment: Option<Attachment>) -> Self {
+        self.payload = Payload::new(self.payload.message(), attachment);
+        self
+    }
+
+    pub fn attachment(&self) -> Option<&Attachment> {
+        self.payload.attachment()
+    }
+
+    pub fn with_urgent(mut self, urgent: bool) -> Self {
+        self.urgent = urgent;
+        self
+    }
+
+    pub fn is_urgent(&self) -> bool {
+        self.urgent
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags
+            .into_iter()
+            .map(|tag| tag.trim_start_matches('#').to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        self.tags.sort_unstable();
+        self.tags.dedup();
+        self
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn with_recipients(mut self, recipients: Vec<u64>) -> Self {
+        self.recipients = recipients
+            .into_iter()
+            .filter(|recipient| *recipient != self.user_id)
+            .collect();
+        self.recipients.sort_unstable();
+        self.recipients.dedup();
+        self
+    }
+
+    pub fn recipients(&self) -> &[u64] {
+        &self.recipients
+    }
+
+    pub fn remove_recipient(&mut self, recipient: &u64) -> bool {
+        let size = self.recipients.len();
+        self.recipients.retain(|r| r != recipient);
+        size != self.recipients.len()
+    }
+
     pub fn next_tick(&self, now: &DateTime<Utc>) -> Option<DateTime<Utc>> {
         self.schedule.next_tick(now)
     }
@@ -265,12 +1015,20 @@ impl ReminderDefinition {
     }
 
     pub fn message(&self) -> Arc<String> {
-        self.message.clone()
+        self.payload.message()
+    }
+
+    pub fn payload(&self) -> &Payload {
+        &self.payload
     }
 
     pub fn schedule(&self) -> &Schedule {
         &self.schedule
     }
+
+    pub fn consume_occurrence(&mut self) -> bool {
+        self.schedule.consume_occurrence()
+    }
 }
 
 #[cfg_attr(test, derive(Eq, PartialEq))]
@@ -288,6 +1046,12 @@ pub enum Schedule {
         until: DateTime<Utc>,
         schedule: ScheduleGrid,
     },
+    RecurrentCount {
+        since: DateTime<Utc>,
+        until: Option<DateTime<Utc>>,
+        remaining: u32,
+        schedule: ScheduleGrid,
+    },
 }
 
 impl Schedule {
@@ -302,9 +1066,72 @@ impl Schedule {
             } => schedule
                 .next_scheduled_after(now.max(since))
                 .filter(|then| *then < *until),
+            Self::RecurrentCount { remaining: 0, .. } => None,
+            Self::RecurrentCount {
+                since,
+                until,
+                schedule,
+                ..
+            } => schedule
+                .next_scheduled_after(now.max(since))
+                .filter(|then| until.is_none_or(|until| *then < until)),
+            _ => None,
+        }
+    }
+
+    pub fn occurrences_after(
+        &self,
+        now: &DateTime<Utc>,
+    ) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        std::iter::successors(self.next_tick(now), |current| self.next_tick(current)).take(
+            self.remaining()
+                .map_or(usize::MAX, |remaining| remaining as usize),
+        )
+    }
+
+    pub fn occurrences_between(
+        &self,
+        from: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        let to = *to;
+        self.occurrences_after(&(*from - chrono::Duration::nanoseconds(1)))
+            .take_while(move |tick| *tick < to)
+    }
+
+    pub fn shortest_interval(&self, now: &DateTime<Utc>) -> Option<chrono::Duration> {
+        let ticks = self
+            .occurrences_after(now)
+            .take(INTERVAL_SAMPLE)
+            .collect::<Vec<_>>();
+        ticks.windows(2).map(|pair| pair[1] - pair[0]).min()
+    }
+
+    pub fn remaining(&self) -> Option<u32> {
+        match self {
+            Self::RecurrentCount { remaining, .. } => Some(*remaining),
             _ => None,
         }
     }
+
+    pub fn cron(&self) -> Option<String> {
+        match self {
+            Self::Once { .. } => None,
+            Self::Recurrent { schedule, .. }
+            | Self::RecurrentUntil { schedule, .. }
+            | Self::RecurrentCount { schedule, .. } => cron::render(schedule),
+        }
+    }
+
+    pub fn consume_occurrence(&mut self) -> bool {
+        match self {
+            Self::RecurrentCount { remaining, .. } => {
+                *remaining = remaining.saturating_sub(1);
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 pub struct ScheduleGridBuilder {
@@ -323,7 +1150,7 @@ impl ScheduleGridBuilder {
     pub fn new(timezone: Tz) -> Self {
         Self {
             minutes: Bitmap::all_set(NonZeroUsize::new(60).unwrap()),
-            hours: Bitmap::all_set(NonZeroUsize::new(60).unwrap()),
+            hours: Bitmap::all_set(NonZeroUsize::new(24).unwrap()),
             weeks_of_month: Bitmap::all_set(NonZeroUsize::new(5).unwrap()),
             days_of_month: Bitmap::all_set(NonZeroUsize::new(31).unwrap()),
             days_of_week: Bitmap::all_set(NonZeroUsize::new(7).unwrap()),
@@ -365,9 +1192,7 @@ impl ScheduleGridBuilder {
     }
 
     pub fn all_hours(&mut self) -> &mut Self {
-        for i in 0..23 {
-            self.hours.set(i as usize);
-        }
+        self.hours.fill();
         self
     }
 
@@ -380,9 +1205,7 @@ impl ScheduleGridBuilder {
     }
 
     pub fn all_minutes(&mut self) -> &mut Self {
-        for i in 0..59 {
-            self.minutes.set(i as usize);
-        }
+        self.minutes.fill();
         self
     }
 
@@ -395,9 +1218,7 @@ impl ScheduleGridBuilder {
     }
 
     pub fn all_weeks(&mut self) -> &mut Self {
-        for i in 0..4 {
-            self.weeks_of_month.set(i as usize);
-        }
+        self.weeks_of_month.fill();
         self
     }
 
@@ -424,9 +1245,7 @@ impl ScheduleGridBuilder {
     }
 
     pub fn all_months(&mut self) -> &mut Self {
-        for i in 0..11 {
-            self.months_of_year.set(i as usize);
-        }
+        self.months_of_year.fill();
         self
     }
 
@@ -439,9 +1258,7 @@ impl ScheduleGridBuilder {
     }
 
     pub fn all_days_of_month(&mut self) -> &mut Self {
-        for i in 0..30 {
-            self.days_of_month.set(i as usize);
-        }
+        self.days_of_month.fill();
         self
     }
 
@@ -459,3 +1276,524 @@ pub enum ScheduleInspection {
     DaysOfWeek,
     MonthsOfYear,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+
+    use super::{
+        AgendaEntry, AgendaSubscription, DeliveryOutcome, Firing, LeaseProvider,
+        LocalLeaseProvider, Payload, QuietHours, ReminderCallback, ReminderDefinition,
+        ReminderEngine, ReminderStats, Schedule, ScheduleGridBuilder, TimeProvider,
+    };
+    use crate::memory::store::{LocalReminderStore, ReminderStore};
+
+    struct OffsetTimeProvider {
+        offset: chrono::Duration,
+    }
+
+    impl TimeProvider for OffsetTimeProvider {
+        fn now(&self) -> DateTime<Utc> {
+            Utc::now() + self.offset
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingCallback {
+        calls: Mutex<Vec<(u64, i32)>>,
+        digests: Mutex<Vec<(u64, Vec<i32>)>>,
+    }
+
+    #[async_trait]
+    impl ReminderCallback for RecordingCallback {
+        async fn call(&self, user: u64, reminder_id: i32, _: Payload) -> Result<(), String> {
+            self.calls.lock().unwrap().push((user, reminder_id));
+            Ok(())
+        }
+
+        async fn digest(&self, user: u64, reminders: Vec<(i32, Payload)>) -> Result<(), String> {
+            let ids = reminders.iter().map(|(id, _)| *id).collect();
+            self.digests.lock().unwrap().push((user, ids));
+            Ok(())
+        }
+
+        async fn agenda(
+            &self,
+            _: u64,
+            _: Vec<AgendaEntry>,
+            _: AgendaSubscription,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    struct Cluster {
+        engines: Vec<Arc<ReminderEngine>>,
+        store: Arc<LocalReminderStore>,
+        callback: Arc<RecordingCallback>,
+        time_provider: Arc<OffsetTimeProvider>,
+    }
+
+    impl Cluster {
+        // two instances sharing leases and storage, with the clock `before_minute` ahead of a minute boundary
+        async fn new(before_minute: chrono::Duration) -> Self {
+            Self::over(Arc::new(LocalReminderStore::new()), before_minute).await
+        }
+
+        async fn over(store: Arc<LocalReminderStore>, before_minute: chrono::Duration) -> Self {
+            let callback = Arc::new(RecordingCallback::default());
+            let leases: Arc<dyn LeaseProvider + Send + Sync> = Arc::new(LocalLeaseProvider::new());
+            let into_minute =
+                chrono::Duration::milliseconds(Utc::now().timestamp_millis() % 60_000);
+            let time_provider = Arc::new(OffsetTimeProvider {
+                offset: chrono::Duration::minutes(1) - before_minute - into_minute,
+            });
+            let mut engines = vec![];
+            for _ in 0..2 {
+                let engine = ReminderEngine::with_store(
+                    time_provider.clone(),
+                    callback.clone(),
+                    leases.clone(),
+                    store.clone(),
+                )
+                .await;
+                engines.push(Arc::new(engine));
+            }
+            Self {
+                engines,
+                store,
+                callback,
+                time_provider,
+            }
+        }
+
+        fn now(&self) -> DateTime<Utc> {
+            self.time_provider.now()
+        }
+
+        async fn run_for(&self, duration: Duration) {
+            let handles = self
+                .engines
+                .iter()
+                .map(|engine| {
+                    let engine = engine.clone();
+                    tokio::spawn(async move { engine.run().await })
+                })
+                .collect::<Vec<_>>();
+            tokio::time::sleep(duration).await;
+            for (engine, handle) in self.engines.iter().zip(handles) {
+                engine.stop();
+                handle.await.unwrap();
+            }
+        }
+
+        fn calls(&self) -> Vec<(u64, i32)> {
+            self.callback.calls.lock().unwrap().clone()
+        }
+
+        fn digests(&self) -> Vec<(u64, Vec<i32>)> {
+            self.callback.digests.lock().unwrap().clone()
+        }
+    }
+
+    fn once_in(cluster: &Cluster, delay: chrono::Duration) -> ReminderDefinition {
+        ReminderDefinition::new(
+            Schedule::Once {
+                when: cluster.now() + delay,
+            },
+            1,
+            "ciao".to_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn an_occurrence_is_delivered_by_a_single_instance() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let id = cluster.engines[0]
+            .add(once_in(&cluster, chrono::Duration::milliseconds(300)))
+            .await
+            .unwrap();
+        assert_eq!((1, 0), cluster.engines[1].reconcile().await);
+
+        cluster.run_for(Duration::from_millis(800)).await;
+        assert_eq!(vec![(1, id)], cluster.calls());
+        assert_eq!(1, cluster.store.get_firings(1, None).await.len());
+    }
+
+    #[tokio::test]
+    async fn a_reminder_removed_elsewhere_is_not_delivered() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let id = cluster.engines[0]
+            .add(once_in(&cluster, chrono::Duration::milliseconds(300)))
+            .await
+            .unwrap();
+        cluster.engines[1].reconcile().await;
+        assert!(cluster.engines[0].defuse(1, id).await);
+        assert!(cluster.engines[1].get(&1, &id).is_some());
+
+        cluster.run_for(Duration::from_millis(800)).await;
+        assert!(cluster.calls().is_empty());
+        assert!(cluster.engines[1].get(&1, &id).is_none());
+    }
+
+    #[tokio::test]
+    async fn remaining_occurrences_are_consumed_once() {
+        let cluster = Cluster::new(chrono::Duration::milliseconds(300)).await;
+        let definition = ReminderDefinition::new(
+            Schedule::RecurrentCount {
+                since: cluster.now(),
+                until: None,
+                remaining: 3,
+                schedule: ScheduleGridBuilder::new(chrono_tz::Europe::Rome).build(),
+            },
+            1,
+            "ciao".to_owned(),
+        );
+        let id = cluster.engines[0].add(definition).await.unwrap();
+        cluster.engines[1].reconcile().await;
+
+        cluster.run_for(Duration::from_millis(800)).await;
+        assert_eq!(vec![(1, id)], cluster.calls());
+        let stored = cluster.store.reminder(1, id).unwrap();
+        assert_eq!(Some(2), stored.schedule().remaining());
+        for engine in &cluster.engines {
+            assert_eq!(Some(2), engine.get(&1, &id).unwrap().remaining());
+        }
+    }
+
+    #[tokio::test]
+    async fn expired_reminders_are_archived_at_startup() {
+        let store = Arc::new(LocalReminderStore::new());
+        let now = Utc::now();
+        let expired = ReminderDefinition::new(
+            Schedule::Once {
+                when: now - chrono::Duration::hours(1),
+            },
+            1,
+            "ieri".to_owned(),
+        );
+        let pending = ReminderDefinition::new(
+            Schedule::Once {
+                when: now + chrono::Duration::hours(1),
+            },
+            1,
+            "domani".to_owned(),
+        );
+        assert!(store.create(&expired, 1).await);
+        assert!(store.create(&pending, 2).await);
+
+        let engine = ReminderEngine::with_store(
+            Arc::new(OffsetTimeProvider {
+                offset: chrono::Duration::zero(),
+            }),
+            Arc::new(RecordingCallback::default()),
+            Arc::new(LocalLeaseProvider::new()),
+            store.clone(),
+        )
+        .await;
+        assert!(store.is_archived(1, 1));
+        assert!(store.reminder(1, 2).is_some());
+        assert!(engine.get(&1, &1).is_none());
+        assert!(engine.get(&1, &2).is_some());
+    }
+
+    #[tokio::test]
+    async fn sweep_archives_only_expired_reminders() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let engine = &cluster.engines[0];
+        let id = engine
+            .add(once_in(&cluster, chrono::Duration::hours(1)))
+            .await
+            .unwrap();
+        let expired = once_in(&cluster, -chrono::Duration::hours(1));
+        assert!(cluster.store.create(&expired, 9).await);
+
+        assert_eq!(1, engine.sweep().await);
+        assert!(cluster.store.is_archived(1, 9));
+        assert!(!cluster.store.is_archived(1, id));
+        assert_eq!(0, engine.sweep().await);
+    }
+
+    #[tokio::test]
+    async fn finished_reminders_are_archived() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let id = cluster.engines[0]
+            .add(once_in(&cluster, chrono::Duration::milliseconds(300)))
+            .await
+            .unwrap();
+
+        cluster.run_for(Duration::from_millis(800)).await;
+        assert_eq!(vec![(1, id)], cluster.calls());
+        assert!(cluster.store.is_archived(1, id));
+        assert!(cluster.store.reminder(1, id).is_none());
+    }
+
+    #[tokio::test]
+    async fn reconcile_repairs_drift_in_both_directions() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let engine = &cluster.engines[0];
+        let stored = once_in(&cluster, chrono::Duration::hours(1));
+        assert!(cluster.store.create(&stored, 7).await);
+        let scheduled = engine
+            .add(once_in(&cluster, chrono::Duration::hours(1)))
+            .await
+            .unwrap();
+        cluster.store.delete(1, scheduled).await;
+
+        assert_eq!((1, 1), engine.reconcile().await);
+        assert!(engine.get(&1, &7).is_some());
+        assert!(engine.get(&1, &scheduled).unwrap().current_tick().is_none());
+        assert_eq!((0, 0), engine.reconcile().await);
+    }
+
+    #[tokio::test]
+    async fn reconcile_restores_a_defused_reminder_that_is_still_stored() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let engine = &cluster.engines[0];
+        let id = engine
+            .add(once_in(&cluster, chrono::Duration::hours(1)))
+            .await
+            .unwrap();
+        engine.internal_defuse(&1, &id);
+
+        assert_eq!((1, 0), engine.reconcile().await);
+        assert!(engine.get(&1, &id).unwrap().current_tick().is_some());
+        assert_eq!(1, engine.active_count(&1));
+    }
+
+    #[tokio::test]
+    async fn reminders_are_stored_before_being_scheduled() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let engine = &cluster.engines[0];
+        cluster.store.set_unavailable(true);
+        assert_eq!(
+            None,
+            engine
+                .add(once_in(&cluster, chrono::Duration::hours(1)))
+                .await
+        );
+        assert!(engine.get_all(&1).is_empty());
+        assert_eq!((0, 0), engine.reconcile().await);
+
+        cluster.store.set_unavailable(false);
+        let id = engine
+            .add(once_in(&cluster, chrono::Duration::hours(1)))
+            .await
+            .unwrap();
+        assert!(cluster.store.reminder(1, id).is_some());
+        assert_eq!(1, engine.active_count(&1));
+    }
+
+    #[tokio::test]
+    async fn defusing_a_tag_removes_only_its_live_reminders() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let engine = &cluster.engines[0];
+        let tagged = |tags: &[&str]| {
+            once_in(&cluster, chrono::Duration::hours(1))
+                .with_tags(tags.iter().map(|tag| tag.to_string()).collect())
+        };
+        let first = engine.add(tagged(&["casa"])).await.unwrap();
+        let second = engine.add(tagged(&["casa", "spesa"])).await.unwrap();
+        let other = engine.add(tagged(&["ufficio"])).await.unwrap();
+        assert!(engine.defuse(1, first).await);
+
+        assert_eq!(1, engine.defuse_tagged(1, "casa").await);
+        assert!(cluster.store.reminder(1, second).is_none());
+        assert!(cluster.store.reminder(1, other).is_some());
+        assert!(engine.get_tagged(&1, "casa").is_empty());
+        assert!(engine.get_tagged(&1, "spesa").is_empty());
+        assert_eq!(0, engine.defuse_tagged(1, "casa").await);
+        assert_eq!(1, engine.active_count(&1));
+    }
+
+    #[tokio::test]
+    async fn defusing_a_tag_keeps_reminders_that_cannot_be_deleted() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let engine = &cluster.engines[0];
+        let id = engine
+            .add(once_in(&cluster, chrono::Duration::hours(1)).with_tags(vec!["casa".to_owned()]))
+            .await
+            .unwrap();
+        cluster.store.set_unavailable(true);
+
+        assert_eq!(0, engine.defuse_tagged(1, "casa").await);
+        assert_eq!(
+            vec![(1, id)],
+            engine
+                .get_tagged(&1, "casa")
+                .iter()
+                .map(|reminder| reminder.reminder_id())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn held_reminders_are_stored_until_the_digest() {
+        let cluster = Cluster::new(chrono::Duration::milliseconds(300)).await;
+        let window_end = Utc
+            .timestamp_millis_opt((cluster.now().timestamp_millis() / 60_000 + 1) * 60_000)
+            .unwrap();
+        let quiet_hours = QuietHours::new(
+            (window_end - chrono::Duration::minutes(10)).time(),
+            window_end.time(),
+            chrono_tz::UTC,
+        );
+        assert!(
+            cluster.engines[0]
+                .set_quiet_hours(1, Some(quiet_hours))
+                .await
+        );
+        let id = cluster.engines[0]
+            .add(once_in(&cluster, chrono::Duration::milliseconds(100)))
+            .await
+            .unwrap();
+
+        let handle = {
+            let engine = cluster.engines[0].clone();
+            tokio::spawn(async move { engine.run().await })
+        };
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(1, cluster.store.held_count());
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        cluster.engines[0].stop();
+        handle.await.unwrap();
+
+        assert!(cluster.calls().is_empty());
+        assert_eq!(vec![(1, vec![id])], cluster.digests());
+        assert_eq!(0, cluster.store.held_count());
+        assert_eq!(1, cluster.store.get_firings(1, None).await.len());
+    }
+
+    #[tokio::test]
+    async fn held_reminders_are_replayed_at_startup_by_one_instance() {
+        let store = Arc::new(LocalReminderStore::new());
+        let payload = Payload::new(Arc::new("ciao".to_owned()), None);
+        let until = Utc::now() - chrono::Duration::hours(1);
+        store.hold(1, 5, &payload, &until).await;
+        store.hold(1, 6, &payload, &until).await;
+        store.hold(2, 7, &payload, &until).await;
+
+        let cluster = Cluster::over(store, chrono::Duration::seconds(30)).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut digests = cluster.digests();
+        digests.sort();
+        assert_eq!(vec![(1, vec![5, 6]), (2, vec![7])], digests);
+        assert_eq!(0, cluster.store.held_count());
+    }
+
+    #[tokio::test]
+    async fn held_reminders_of_every_instance_end_up_in_one_digest() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let payload = Payload::new(Arc::new("ciao".to_owned()), None);
+        let until = cluster.now() + chrono::Duration::milliseconds(100);
+        cluster.engines[0].hold(1, 5, payload.clone(), until).await;
+        cluster.engines[1].hold(1, 6, payload, until).await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let mut digests = cluster.digests();
+        for (_, ids) in digests.iter_mut() {
+            ids.sort();
+        }
+        assert_eq!(vec![(1, vec![5, 6])], digests);
+        assert_eq!(0, cluster.store.held_count());
+        assert_eq!(2, cluster.store.get_firings(1, None).await.len());
+    }
+
+    #[tokio::test]
+    async fn reconcile_refreshes_preferences() {
+        let cluster = Cluster::new(chrono::Duration::seconds(30)).await;
+        let quiet_hours = QuietHours::new(
+            NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            chrono_tz::Europe::Rome,
+        );
+        let subscription = AgendaSubscription::new(
+            NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            chrono_tz::Europe::Rome,
+            None,
+        );
+        assert!(
+            cluster.engines[0]
+                .set_quiet_hours(1, Some(quiet_hours))
+                .await
+        );
+        assert!(
+            cluster.engines[0]
+                .subscribe_agenda(1, Some(subscription.clone()))
+                .await
+        );
+        assert_eq!(None, cluster.engines[1].quiet_hours(&1));
+
+        cluster.engines[1].reconcile().await;
+        assert_eq!(Some(quiet_hours), cluster.engines[1].quiet_hours(&1));
+        assert_eq!(
+            Some(subscription),
+            cluster.engines[1].agenda_subscription(&1)
+        );
+
+        assert!(cluster.engines[0].set_quiet_hours(1, None).await);
+        cluster.engines[1].reconcile().await;
+        assert_eq!(None, cluster.engines[1].quiet_hours(&1));
+    }
+
+    fn firing(id: i32, outcome: DeliveryOutcome, acknowledged: bool) -> Firing {
+        let fired_at = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        Firing::new(1, id, fired_at, outcome, acknowledged)
+    }
+
+    #[test]
+    fn stats_are_grouped_by_reminder() {
+        let stats = ReminderStats::from_firings(&[
+            firing(2, DeliveryOutcome::Delivered, true),
+            firing(1, DeliveryOutcome::Delivered, false),
+            firing(2, DeliveryOutcome::Delivered, false),
+            firing(2, DeliveryOutcome::Failed("blocked".into()), false),
+        ]);
+        assert_eq!(
+            vec![
+                ReminderStats {
+                    reminder_id: 1,
+                    firings: 1,
+                    delivered: 1,
+                    acknowledged: 0,
+                },
+                ReminderStats {
+                    reminder_id: 2,
+                    firings: 3,
+                    delivered: 2,
+                    acknowledged: 1,
+                },
+            ],
+            stats
+        );
+        assert_eq!(Some(0.0), stats[0].completion_rate());
+        assert_eq!(Some(0.5), stats[1].completion_rate());
+    }
+
+    #[test]
+    fn acknowledged_failures_do_not_count_as_completed() {
+        let stats = ReminderStats::from_firings(&[
+            firing(1, DeliveryOutcome::Delivered, true),
+            firing(1, DeliveryOutcome::Failed("timeout".into()), true),
+        ]);
+        assert_eq!(1, stats[0].acknowledged);
+        assert_eq!(Some(1.0), stats[0].completion_rate());
+    }
+
+    #[test]
+    fn completion_rate_needs_a_delivery() {
+        let stats =
+            ReminderStats::from_firings(&[firing(1, DeliveryOutcome::Failed("x".into()), false)]);
+        assert_eq!(None, stats[0].completion_rate());
+        assert_eq!(None, ReminderStats::default().completion_rate());
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+pub const LEASE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[async_trait]
+pub trait LeaseProvider {
+    async fn try_acquire(&self, key: &str) -> Result<bool, String>;
+}
+
+#[derive(Default)]
+pub struct LocalLeaseProvider {
+    leases: Mutex<HashMap<String, Instant>>,
+}
+
+impl LocalLeaseProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl LeaseProvider for LocalLeaseProvider {
+    async fn try_acquire(&self, key: &str) -> Result<bool, String> {
+        let now = Instant::now();
+        let mut leases = self.leases.lock().map_err(|e| e.to_string())?;
+        leases.retain(|_, acquired| now.duration_since(*acquired) < LEASE_TTL);
+        if leases.contains_key(key) {
+            return Ok(false);
+        }
+        leases.insert(key.to_owned(), now);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::{LeaseProvider, LocalLeaseProvider};
+
+    #[tokio::test]
+    async fn a_lease_is_acquired_once() {
+        let leases = LocalLeaseProvider::new();
+        assert_eq!(Ok(true), leases.try_acquire("reminder:1:2:0").await);
+        assert_eq!(Ok(false), leases.try_acquire("reminder:1:2:0").await);
+        assert_eq!(Ok(true), leases.try_acquire("reminder:1:2:60000").await);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn instances_sharing_a_store_split_the_ticks() {
+        let shared: Arc<dyn LeaseProvider + Send + Sync> = Arc::new(LocalLeaseProvider::new());
+        let instances = (0..2)
+            .map(|instance| {
+                let leases = shared.clone();
+                tokio::spawn(async move {
+                    let mut won = vec![];
+                    for tick in 0..500 {
+                        let key = format!("reminder:1:2:{tick}");
+                        if leases.try_acquire(&key).await.unwrap() {
+                            won.push((tick, instance));
+                        }
+                    }
+                    won
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut winners = HashMap::new();
+        for instance in instances {
+            for (tick, winner) in instance.await.unwrap() {
+                assert_eq!(None, winners.insert(tick, winner));
+            }
+        }
+        assert_eq!(500, winners.len());
+    }
+}
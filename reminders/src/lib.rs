@@ -1,5 +1,9 @@
-mod bitmap;
+mod agenda;
+pub mod bitmap;
 pub mod interface;
+mod lease;
 mod memory;
+mod payload;
+mod quiet;
 mod schedule;
 mod text;
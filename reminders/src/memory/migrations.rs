@@ -0,0 +1,144 @@
+use std::num::NonZeroUsize;
+
+use mongodb::bson::{Bson, Document};
+
+use crate::bitmap::Bitmap;
+
+pub(crate) const SCHEMA_VERSION: i32 = 1;
+pub(crate) const VERSION_FIELD: &str = "schema_version";
+
+type Migration = fn(Document) -> Result<Document, String>;
+
+// MIGRATIONS[n] upgrades a document from version n to version n + 1
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [compact_bitmaps];
+
+const GRID_FIELDS: [(&str, usize); 6] = [
+    ("minutes", 60),
+    ("hours", 24),
+    ("weeks_of_month", 5),
+    ("days_of_month", 31),
+    ("days_of_week", 7),
+    ("months_of_year", 12),
+];
+
+pub(crate) fn version_of(document: &Document) -> i32 {
+    document.get_i32(VERSION_FIELD).unwrap_or(0)
+}
+
+// Documents written by a newer release are left untouched: Ok(None)
+pub(crate) fn upgrade(mut document: Document) -> Result<Option<Document>, String> {
+    let version = version_of(&document);
+    if version > SCHEMA_VERSION {
+        return Ok(None);
+    }
+    if version < 0 {
+        return Err(format!("unknown schema version {version}"));
+    }
+    for migration in MIGRATIONS[version as usize..].iter() {
+        document = migration(document)?;
+    }
+    document.insert(VERSION_FIELD, SCHEMA_VERSION);
+    Ok(Some(document))
+}
+
+fn compact_bitmaps(mut document: Document) -> Result<Document, String> {
+    let schedule = document
+        .get_document_mut("schedule")
+        .map_err(|e| format!("schedule: {e}"))?;
+    for (kind, value) in schedule.iter_mut() {
+        if kind == "Once" {
+            continue;
+        }
+        let grid = value
+            .as_document_mut()
+            .ok_or_else(|| format!("schedule.{kind} is not a document"))?
+            .get_document_mut("schedule")
+            .map_err(|e| format!("schedule.{kind}.schedule: {e}"))?;
+        for (field, size) in GRID_FIELDS {
+            let bytes = match grid.get(field) {
+                Some(Bson::String(_)) => continue,
+                Some(Bson::Array(items)) => items
+                    .iter()
+                    .map(|item| match item {
+                        Bson::Int32(x) => u8::try_from(*x).ok(),
+                        Bson::Int64(x) => u8::try_from(*x).ok(),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(|| format!("{field} is not a byte array"))?,
+                _ => return Err(format!("{field} is missing or malformed")),
+            };
+            let bitmap = Bitmap::from_bytes(NonZeroUsize::new(size).unwrap(), &bytes)
+                .map_err(|e| format!("{field}: {e}"))?;
+            grid.insert(field, bitmap.to_string());
+        }
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, Document};
+
+    use super::{upgrade, SCHEMA_VERSION};
+
+    fn legacy_reminder(minutes: Vec<i32>) -> Document {
+        doc! {
+            "_id": {"user": 1i64, "id": 7},
+            "message": "ciao",
+            "schedule": {"Recurrent": {
+                "since_micros": 0i64,
+                "schedule": {
+                    "minutes": minutes,
+                    "hours": [0, 1, 0, 0, 0, 0, 0, 0],
+                    "weeks_of_month": [31],
+                    "days_of_month": [255, 255, 255, 127],
+                    "days_of_week": [127],
+                    "months_of_year": [255, 15],
+                    "year_cadence": 1,
+                    "year_start": 2024,
+                    "timezone": "Europe/Rome",
+                },
+            }},
+        }
+    }
+
+    #[test]
+    fn upgrades_legacy_bitmaps() {
+        let upgraded = upgrade(legacy_reminder(vec![1, 0, 0, 0, 0, 0, 0, 0]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(Ok(SCHEMA_VERSION), upgraded.get_i32("schema_version"));
+        let grid = upgraded
+            .get_document("schedule")
+            .and_then(|schedule| schedule.get_document("Recurrent"))
+            .and_then(|recurrent| recurrent.get_document("schedule"))
+            .unwrap();
+        assert_eq!(Ok("60:000000000000001"), grid.get_str("minutes"));
+        assert_eq!(Ok("24:000100"), grid.get_str("hours"));
+        assert_eq!(Ok("31:7fffffff"), grid.get_str("days_of_month"));
+        assert_eq!(Ok("12:fff"), grid.get_str("months_of_year"));
+    }
+
+    #[test]
+    fn upgrade_is_idempotent() {
+        let upgraded = upgrade(legacy_reminder(vec![1, 0, 0, 0, 0, 0, 0, 0]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(Ok(Some(upgraded.clone())), upgrade(upgraded));
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(upgrade(legacy_reminder(vec![1, 300])).is_err());
+        assert!(upgrade(legacy_reminder(vec![0, 0, 0, 0, 0, 0, 0, 16])).is_err());
+        assert!(upgrade(doc! {"_id": 1, "message": "ciao"}).is_err());
+        assert!(upgrade(doc! {"_id": 1, "schema_version": -1}).is_err());
+    }
+
+    #[test]
+    fn leaves_documents_from_newer_releases_alone() {
+        let newer = doc! {"_id": 1, "schema_version": SCHEMA_VERSION + 1, "unknown": true};
+        assert_eq!(Ok(None), upgrade(newer));
+    }
+}
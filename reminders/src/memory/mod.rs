@@ -1,2 +1,5 @@
+pub(crate) mod migrations;
 pub(crate) mod persistent;
+pub(crate) mod search;
+pub(crate) mod store;
 pub(crate) mod transient;
@@ -1,15 +1,24 @@
-use std::num::NonZeroU8;
+use std::{collections::HashMap, num::NonZeroU8, sync::Arc};
 
-use chrono::{TimeZone, Utc};
-use futures::{Stream, StreamExt};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveTime, TimeZone, Timelike, Utc};
+use futures::{StreamExt, TryStreamExt};
 use mongodb::{
-    bson::{self, doc},
+    bson::{self, doc, Bson, Document},
     Collection, Database,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    interface::{ReminderDefinition, Schedule, ScheduleInspection},
+    bitmap::Bitmap,
+    interface::{
+        AgendaSubscription, Attachment, DeliveryOutcome, Firing, Payload, QuietHours,
+        ReminderDefinition, Schedule, ScheduleInspection,
+    },
+    memory::{
+        migrations::{upgrade, version_of, SCHEMA_VERSION, VERSION_FIELD},
+        store::{HeldReminder, ReminderStore},
+    },
     schedule::ScheduleGrid,
 };
 
@@ -23,18 +32,78 @@ struct ReminderMongoloidId {
 struct MongoloidReminder {
     #[serde(rename = "_id")]
     id: ReminderMongoloidId,
+    schema_version: i32,
     message: String,
     schedule: MongoloidSchedule,
+    #[serde(default)]
+    recipients: Vec<u64>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    urgent: bool,
+    #[serde(default)]
+    attachment: Option<MongoloidAttachment>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum MongoloidAttachment {
+    Photo { file_id: String },
+    Document { file_id: String },
+    Voice { file_id: String },
+}
+
+impl From<&Attachment> for MongoloidAttachment {
+    fn from(value: &Attachment) -> Self {
+        match value.clone() {
+            Attachment::Photo { file_id } => Self::Photo { file_id },
+            Attachment::Document { file_id } => Self::Document { file_id },
+            Attachment::Voice { file_id } => Self::Voice { file_id },
+        }
+    }
+}
+
+impl From<MongoloidAttachment> for Attachment {
+    fn from(value: MongoloidAttachment) -> Self {
+        match value {
+            MongoloidAttachment::Photo { file_id } => Self::Photo { file_id },
+            MongoloidAttachment::Document { file_id } => Self::Document { file_id },
+            MongoloidAttachment::Voice { file_id } => Self::Voice { file_id },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MongoloidQuietHours {
+    #[serde(rename = "_id")]
+    user: u64,
+    start_seconds: u32,
+    end_seconds: u32,
+    timezone: String,
+}
+
+#[derive(Serialize, Deserialize)]
+enum MongoloidDeliveryOutcome {
+    Delivered,
+    Failed { reason: String },
+}
+
+#[derive(Serialize, Deserialize)]
+struct MongoloidFiring {
+    user: u64,
+    id: i32,
+    fired_micros: i64,
+    outcome: MongoloidDeliveryOutcome,
+    acknowledged: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 struct MongoloidScheduleGrid {
-    minutes: Vec<u8>,
-    hours: Vec<u8>,
-    weeks_of_month: Vec<u8>,
-    days_of_month: Vec<u8>,
-    days_of_week: Vec<u8>,
-    months_of_year: Vec<u8>,
+    minutes: Bitmap,
+    hours: Bitmap,
+    weeks_of_month: Bitmap,
+    days_of_month: Bitmap,
+    days_of_week: Bitmap,
+    months_of_year: Bitmap,
     year_cadence: u8,
     year_start: u32,
     timezone: String,
@@ -54,6 +123,12 @@ enum MongoloidSchedule {
         until_micros: i64,
         schedule: MongoloidScheduleGrid,
     },
+    RecurrentCount {
+        since_micros: i64,
+        until_micros: Option<i64>,
+        remaining: u32,
+        schedule: MongoloidScheduleGrid,
+    },
 }
 
 impl From<Schedule> for MongoloidSchedule {
@@ -75,33 +150,73 @@ impl From<Schedule> for MongoloidSchedule {
                 until_micros: until.timestamp_micros(),
                 schedule: schedule.into(),
             },
+            Schedule::RecurrentCount {
+                since,
+                until,
+                remaining,
+                schedule,
+            } => Self::RecurrentCount {
+                since_micros: since.timestamp_micros(),
+                until_micros: until.map(|until| until.timestamp_micros()),
+                remaining,
+                schedule: schedule.into(),
+            },
         }
     }
 }
 
-impl From<MongoloidSchedule> for Schedule {
-    fn from(value: MongoloidSchedule) -> Self {
-        match value {
+fn skip_newer(collection: &Collection<Document>, id: &Bson, version: i32) {
+    tracing::warn!(
+        collection = collection.name(),
+        id = %id,
+        version,
+        latest = SCHEMA_VERSION,
+        "Skipping reminder written with a newer schema"
+    );
+}
+
+fn instant_of(micros: i64) -> Result<DateTime<Utc>, String> {
+    Utc.timestamp_micros(micros)
+        .single()
+        .ok_or_else(|| format!("invalid timestamp {micros}"))
+}
+
+impl TryFrom<MongoloidSchedule> for Schedule {
+    type Error = String;
+
+    fn try_from(value: MongoloidSchedule) -> Result<Self, Self::Error> {
+        Ok(match value {
             MongoloidSchedule::Once { when_micros } => Self::Once {
-                when: Utc.timestamp_micros(when_micros).unwrap(),
+                when: instant_of(when_micros)?,
             },
             MongoloidSchedule::Recurrent {
                 since_micros,
                 schedule,
             } => Self::Recurrent {
-                since: Utc.timestamp_micros(since_micros).unwrap(),
-                schedule: schedule.into(),
+                since: instant_of(since_micros)?,
+                schedule: schedule.try_into()?,
             },
             MongoloidSchedule::RecurrentUntil {
                 since_micros,
                 until_micros,
                 schedule,
             } => Self::RecurrentUntil {
-                since: Utc.timestamp_micros(since_micros).unwrap(),
-                until: Utc.timestamp_micros(until_micros).unwrap(),
-                schedule: schedule.into(),
+                since: instant_of(since_micros)?,
+                until: instant_of(until_micros)?,
+                schedule: schedule.try_into()?,
             },
-        }
+            MongoloidSchedule::RecurrentCount {
+                since_micros,
+                until_micros,
+                remaining,
+                schedule,
+            } => Self::RecurrentCount {
+                since: instant_of(since_micros)?,
+                until: until_micros.map(instant_of).transpose()?,
+                remaining,
+                schedule: schedule.try_into()?,
+            },
+        })
     }
 }
 
@@ -122,19 +237,166 @@ impl From<ScheduleGrid> for MongoloidScheduleGrid {
     }
 }
 
-impl From<MongoloidScheduleGrid> for ScheduleGrid {
-    fn from(value: MongoloidScheduleGrid) -> Self {
-        Self::new(
-            value.minutes.into(),
-            value.hours.into(),
-            value.weeks_of_month.into(),
-            value.days_of_month.into(),
-            value.days_of_week.into(),
-            value.months_of_year.into(),
-            NonZeroU8::new(value.year_cadence).unwrap(),
+fn sized(bitmap: Bitmap, field: &str, size: usize) -> Result<Bitmap, String> {
+    match bitmap.size() == size {
+        true => Ok(bitmap),
+        false => Err(format!(
+            "{field} has {} bits instead of {size}",
+            bitmap.size()
+        )),
+    }
+}
+
+impl TryFrom<MongoloidScheduleGrid> for ScheduleGrid {
+    type Error = String;
+
+    fn try_from(value: MongoloidScheduleGrid) -> Result<Self, Self::Error> {
+        Ok(Self::new(
+            sized(value.minutes, "minutes", 60)?,
+            sized(value.hours, "hours", 24)?,
+            sized(value.weeks_of_month, "weeks_of_month", 5)?,
+            sized(value.days_of_month, "days_of_month", 31)?,
+            sized(value.days_of_week, "days_of_week", 7)?,
+            sized(value.months_of_year, "months_of_year", 12)?,
+            NonZeroU8::new(value.year_cadence).ok_or("year_cadence is 0")?,
             value.year_start,
-            value.timezone.parse().unwrap(),
-        )
+            value
+                .timezone
+                .parse()
+                .map_err(|_| format!("unknown timezone {}", value.timezone))?,
+        ))
+    }
+}
+
+impl From<&DeliveryOutcome> for MongoloidDeliveryOutcome {
+    fn from(value: &DeliveryOutcome) -> Self {
+        match value {
+            DeliveryOutcome::Delivered => Self::Delivered,
+            DeliveryOutcome::Failed(reason) => Self::Failed {
+                reason: reason.clone(),
+            },
+        }
+    }
+}
+
+impl From<MongoloidDeliveryOutcome> for DeliveryOutcome {
+    fn from(value: MongoloidDeliveryOutcome) -> Self {
+        match value {
+            MongoloidDeliveryOutcome::Delivered => Self::Delivered,
+            MongoloidDeliveryOutcome::Failed { reason } => Self::Failed(reason),
+        }
+    }
+}
+
+impl From<&Firing> for MongoloidFiring {
+    fn from(value: &Firing) -> Self {
+        let (user, id) = value.reminder_id();
+        Self {
+            user,
+            id,
+            fired_micros: value.fired_at().timestamp_micros(),
+            outcome: value.outcome().into(),
+            acknowledged: value.is_acknowledged(),
+        }
+    }
+}
+
+impl TryFrom<MongoloidFiring> for Firing {
+    type Error = String;
+
+    fn try_from(value: MongoloidFiring) -> Result<Self, Self::Error> {
+        Ok(Self::new(
+            value.user,
+            value.id,
+            instant_of(value.fired_micros)?,
+            value.outcome.into(),
+            value.acknowledged,
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MongoloidHeldReminder {
+    user: u64,
+    id: i32,
+    message: String,
+    attachment: Option<MongoloidAttachment>,
+    until_micros: i64,
+}
+
+impl MongoloidHeldReminder {
+    fn new(user: u64, id: i32, payload: &Payload, until: &DateTime<Utc>) -> Self {
+        Self {
+            user,
+            id,
+            message: payload.message().to_string(),
+            attachment: payload.attachment().map(MongoloidAttachment::from),
+            until_micros: until.timestamp_micros(),
+        }
+    }
+}
+
+impl TryFrom<MongoloidHeldReminder> for HeldReminder {
+    type Error = String;
+
+    fn try_from(value: MongoloidHeldReminder) -> Result<Self, Self::Error> {
+        let payload = Payload::new(
+            Arc::new(value.message),
+            value.attachment.map(Attachment::from),
+        );
+        Ok((
+            value.user,
+            value.id,
+            payload,
+            instant_of(value.until_micros)?,
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MongoloidAgendaSubscription {
+    #[serde(rename = "_id")]
+    user: u64,
+    time_seconds: u32,
+    timezone: String,
+    city: Option<String>,
+}
+
+impl MongoloidAgendaSubscription {
+    fn new(user: u64, subscription: &AgendaSubscription) -> Self {
+        Self {
+            user,
+            time_seconds: subscription.time().num_seconds_from_midnight(),
+            timezone: subscription.timezone().name().to_owned(),
+            city: subscription.city().map(|city| city.to_owned()),
+        }
+    }
+
+    fn into_subscription(self) -> Option<AgendaSubscription> {
+        Some(AgendaSubscription::new(
+            NaiveTime::from_num_seconds_from_midnight_opt(self.time_seconds, 0)?,
+            self.timezone.parse().ok()?,
+            self.city,
+        ))
+    }
+}
+
+impl MongoloidQuietHours {
+    fn new(user: u64, quiet_hours: &QuietHours) -> Self {
+        Self {
+            user,
+            start_seconds: quiet_hours.start().num_seconds_from_midnight(),
+            end_seconds: quiet_hours.end().num_seconds_from_midnight(),
+            timezone: quiet_hours.timezone().name().to_owned(),
+        }
+    }
+
+    fn into_quiet_hours(self) -> Option<QuietHours> {
+        Some(QuietHours::new(
+            NaiveTime::from_num_seconds_from_midnight_opt(self.start_seconds, 0)?,
+            NaiveTime::from_num_seconds_from_midnight_opt(self.end_seconds, 0)?,
+            self.timezone.parse().ok()?,
+        ))
     }
 }
 
@@ -145,48 +407,446 @@ impl MongoloidReminder {
                 user: definition.user_id(),
                 id,
             },
+            schema_version: SCHEMA_VERSION,
             message: definition.message().to_string(),
             schedule: definition.schedule().clone().into(),
+            recipients: definition.recipients().to_vec(),
+            tags: definition.tags().to_vec(),
+            urgent: definition.is_urgent(),
+            attachment: definition.attachment().map(MongoloidAttachment::from),
         }
     }
 }
 
+fn parse_reminder(document: Document) -> Result<(ReminderDefinition, i32), String> {
+    let reminder = bson::from_document::<MongoloidReminder>(document).map_err(|e| e.to_string())?;
+    let definition = ReminderDefinition::new(
+        reminder.schedule.try_into()?,
+        reminder.id.user,
+        reminder.message,
+    )
+    .with_recipients(reminder.recipients)
+    .with_tags(reminder.tags)
+    .with_urgent(reminder.urgent)
+    .with_attachment(reminder.attachment.map(Attachment::from));
+    Ok((definition, reminder.id.id))
+}
+
+#[derive(Clone)]
 pub struct MongoloidStorage {
     collection: Collection<MongoloidReminder>,
+    archive: Collection<MongoloidReminder>,
+    quarantine: Collection<Document>,
+    history: Collection<MongoloidFiring>,
+    quiet_hours: Collection<MongoloidQuietHours>,
+    agenda_subscriptions: Collection<MongoloidAgendaSubscription>,
+    held: Collection<MongoloidHeldReminder>,
 }
 
 impl MongoloidStorage {
     pub fn new(db: Database) -> Self {
         Self {
             collection: db.collection::<MongoloidReminder>("reminders"),
+            archive: db.collection::<MongoloidReminder>("reminders_archive"),
+            quarantine: db.collection::<Document>("reminders_quarantine"),
+            history: db.collection::<MongoloidFiring>("reminders_history"),
+            quiet_hours: db.collection::<MongoloidQuietHours>("quiet_hours"),
+            agenda_subscriptions: db
+                .collection::<MongoloidAgendaSubscription>("agenda_subscriptions"),
+            held: db.collection::<MongoloidHeldReminder>("reminders_held"),
+        }
+    }
+
+    async fn find_held(&self, filter: Document) -> Option<Vec<HeldReminder>> {
+        let held = match self.held.find(filter).await {
+            Ok(cursor) => cursor.try_collect::<Vec<_>>().await,
+            Err(e) => Err(e),
+        };
+        match held {
+            Ok(held) => Some(
+                held.into_iter()
+                    .filter_map(|record| {
+                        HeldReminder::try_from(record)
+                            .inspect_err(
+                                |e| tracing::warn!(error = %e, "Skipping invalid held reminder"),
+                            )
+                            .ok()
+                    })
+                    .collect(),
+            ),
+            Err(e) => {
+                tracing::error!(error = %e, "Cannot read held reminders");
+                None
+            }
+        }
+    }
+
+    async fn find_documents(
+        collection: &Collection<Document>,
+        filter: Document,
+    ) -> Option<Vec<Document>> {
+        let documents = match collection.find(filter).await {
+            Ok(cursor) => cursor.try_collect().await,
+            Err(e) => Err(e),
+        };
+        documents
+            .inspect_err(
+                |e| tracing::error!(collection = collection.name(), error = %e, "Cannot query"),
+            )
+            .ok()
+    }
+
+    async fn quarantine(
+        &self,
+        source: &Collection<Document>,
+        document: Document,
+        reason: &str,
+    ) -> bool {
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        tracing::error!(
+            collection = source.name(),
+            id = %id,
+            reason,
+            "Quarantining reminder document"
+        );
+        let record = doc! {
+            "source": source.name(),
+            "reason": reason,
+            "quarantined_micros": Utc::now().timestamp_micros(),
+            "document": document,
+        };
+        self.quarantine.insert_one(record).await.is_ok()
+            && source.delete_one(doc! {"_id": id}).await.is_ok()
+    }
+
+    async fn find_firings(&self, filter: Document, limit: Option<i64>) -> Vec<Firing> {
+        let cursor = self
+            .history
+            .find(filter)
+            .sort(doc! {"fired_micros": -1})
+            .limit(limit.unwrap_or(0))
+            .await;
+        match cursor {
+            Ok(cursor) => {
+                cursor
+                    .filter_map(|firing| async move {
+                        match Firing::try_from(firing.ok()?) {
+                            Ok(firing) => Some(firing),
+                            Err(e) => {
+                                tracing::warn!(error = %e, "Skipping invalid firing in history");
+                                None
+                            }
+                        }
+                    })
+                    .collect()
+                    .await
+            }
+            Err(_) => vec![],
+        }
+    }
+}
+
+#[async_trait]
+impl ReminderStore for MongoloidStorage {
+    async fn migrate(&self) -> (u64, u64) {
+        let (mut migrated, mut quarantined) = (0, 0);
+        for collection in [&self.collection, &self.archive] {
+            let raw = collection.clone_with_type::<Document>();
+            let outdated = doc! {VERSION_FIELD: {"$ne": SCHEMA_VERSION}};
+            for document in Self::find_documents(&raw, outdated)
+                .await
+                .unwrap_or_default()
+            {
+                let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+                let from_version = version_of(&document);
+                let upgraded = upgrade(document.clone()).and_then(|upgraded| {
+                    upgraded
+                        .map(|upgraded| parse_reminder(upgraded.clone()).map(|_| upgraded))
+                        .transpose()
+                });
+                match upgraded {
+                    Ok(None) => skip_newer(&raw, &id, from_version),
+                    Ok(Some(upgraded)) => {
+                        match raw.replace_one(doc! {"_id": &id}, upgraded).await {
+                            Ok(_) => migrated += 1,
+                            Err(e) => tracing::error!(
+                                collection = raw.name(),
+                                id = %id,
+                                from_version,
+                                error = %e,
+                                "Cannot store migrated reminder"
+                            ),
+                        }
+                    }
+                    Err(reason) if self.quarantine(&raw, document, &reason).await => {
+                        quarantined += 1
+                    }
+                    Err(_) => (),
+                }
+            }
         }
+        (migrated, quarantined)
     }
 
-    pub async fn create(&self, definition: &ReminderDefinition, id: i32) -> bool {
+    async fn create(&self, definition: &ReminderDefinition, id: i32) -> bool {
         self.collection
             .insert_one(MongoloidReminder::new(definition, id))
             .await
             .is_ok()
     }
-    pub async fn delete(&self, user_id: u64, id: i32) -> bool {
+
+    async fn delete(&self, user_id: u64, id: i32) -> bool {
         let id = ReminderMongoloidId { user: user_id, id };
         self.collection
             .delete_one(doc! {"_id": bson::to_bson(&id).unwrap()})
             .await
             .is_ok()
     }
-    pub async fn get_all(
+
+    async fn exists(&self, user_id: u64, id: i32) -> bool {
+        let id = ReminderMongoloidId { user: user_id, id };
+        self.collection
+            .count_documents(doc! {"_id": bson::to_bson(&id).unwrap()})
+            .await
+            .map_or(true, |count| count > 0)
+    }
+
+    async fn remove_recipient(&self, user_id: u64, id: i32, recipient: u64) -> bool {
+        let id = ReminderMongoloidId { user: user_id, id };
+        self.collection
+            .update_one(
+                doc! {"_id": bson::to_bson(&id).unwrap()},
+                doc! {"$pull": {"recipients": bson::to_bson(&recipient).unwrap()}},
+            )
+            .await
+            .is_ok_and(|result| result.modified_count > 0)
+    }
+
+    async fn consume_occurrence(&self, user_id: u64, id: i32) -> bool {
+        let id = ReminderMongoloidId { user: user_id, id };
+        self.collection
+            .update_one(
+                doc! {"_id": bson::to_bson(&id).unwrap()},
+                doc! {"$inc": {"schedule.RecurrentCount.remaining": -1}},
+            )
+            .await
+            .is_ok_and(|result| result.modified_count > 0)
+    }
+
+    async fn archive(&self, user_id: u64, id: i32) -> bool {
+        let id = ReminderMongoloidId { user: user_id, id };
+        let reminder = match self
+            .collection
+            .find_one_and_delete(doc! {"_id": bson::to_bson(&id).unwrap()})
+            .await
+        {
+            Ok(Some(reminder)) => reminder,
+            _ => return false,
+        };
+        self.archive.insert_one(reminder).await.is_ok()
+    }
+
+    async fn record_firing(&self, firing: &Firing) -> bool {
+        self.history
+            .insert_one(MongoloidFiring::from(firing))
+            .await
+            .is_ok()
+    }
+
+    async fn acknowledge_last_firing(&self, user_id: u64, id: i32) -> bool {
+        self.history
+            .find_one_and_update(
+                doc! {"user": bson::to_bson(&user_id).unwrap(), "id": id},
+                doc! {"$set": {"acknowledged": true}},
+            )
+            .sort(doc! {"fired_micros": -1})
+            .await
+            .is_ok_and(|firing| firing.is_some())
+    }
+
+    async fn get_firings(&self, user_id: u64, limit: Option<i64>) -> Vec<Firing> {
+        self.find_firings(doc! {"user": bson::to_bson(&user_id).unwrap()}, limit)
+            .await
+    }
+
+    async fn get_failures(&self, limit: i64) -> Vec<Firing> {
+        self.find_firings(doc! {"outcome.Failed": {"$exists": true}}, Some(limit))
+            .await
+    }
+
+    async fn set_quiet_hours(&self, user_id: u64, quiet_hours: Option<&QuietHours>) -> bool {
+        let filter = doc! {"_id": bson::to_bson(&user_id).unwrap()};
+        match quiet_hours {
+            Some(quiet_hours) => self
+                .quiet_hours
+                .replace_one(filter, MongoloidQuietHours::new(user_id, quiet_hours))
+                .upsert(true)
+                .await
+                .is_ok(),
+            None => self.quiet_hours.delete_one(filter).await.is_ok(),
+        }
+    }
+
+    async fn get_all_quiet_hours(&self) -> Option<HashMap<u64, QuietHours>> {
+        match self.quiet_hours.find(doc! {}).await {
+            Ok(cursor) => Some(
+                cursor
+                    .filter_map(|record| async move {
+                        let record = record.ok()?;
+                        let user = record.user;
+                        record.into_quiet_hours().map(|q| (user, q))
+                    })
+                    .collect()
+                    .await,
+            ),
+            Err(e) => {
+                tracing::error!(error = %e, "Cannot read quiet hours");
+                None
+            }
+        }
+    }
+
+    async fn set_agenda_subscription(
         &self,
-    ) -> impl Stream<Item = Result<(ReminderDefinition, i32), mongodb::error::Error>> {
-        self.collection.find(doc! {}).await.unwrap().map(|rem| {
-            rem.map(|reminder| {
-                let def = ReminderDefinition::new(
-                    reminder.schedule.into(),
-                    reminder.id.user,
-                    reminder.message,
-                );
-                (def, reminder.id.id)
+        user_id: u64,
+        subscription: Option<&AgendaSubscription>,
+    ) -> bool {
+        let filter = doc! {"_id": bson::to_bson(&user_id).unwrap()};
+        match subscription {
+            Some(subscription) => self
+                .agenda_subscriptions
+                .replace_one(
+                    filter,
+                    MongoloidAgendaSubscription::new(user_id, subscription),
+                )
+                .upsert(true)
+                .await
+                .is_ok(),
+            None => self.agenda_subscriptions.delete_one(filter).await.is_ok(),
+        }
+    }
+
+    async fn get_all_agenda_subscriptions(&self) -> Option<HashMap<u64, AgendaSubscription>> {
+        match self.agenda_subscriptions.find(doc! {}).await {
+            Ok(cursor) => Some(
+                cursor
+                    .filter_map(|record| async move {
+                        let record = record.ok()?;
+                        let user = record.user;
+                        record.into_subscription().map(|s| (user, s))
+                    })
+                    .collect()
+                    .await,
+            ),
+            Err(e) => {
+                tracing::error!(error = %e, "Cannot read agenda subscriptions");
+                None
+            }
+        }
+    }
+
+    async fn hold(&self, user_id: u64, id: i32, payload: &Payload, until: &DateTime<Utc>) -> bool {
+        self.held
+            .insert_one(MongoloidHeldReminder::new(user_id, id, payload, until))
+            .await
+            .is_ok()
+    }
+
+    async fn release(&self, user_id: u64, until: &DateTime<Utc>) -> bool {
+        self.held
+            .delete_many(doc! {
+                "user": bson::to_bson(&user_id).unwrap(),
+                "until_micros": {"$lte": until.timestamp_micros()},
             })
+            .await
+            .is_ok()
+    }
+
+    async fn get_held(&self, user_id: u64, until: &DateTime<Utc>) -> Option<Vec<HeldReminder>> {
+        self.find_held(doc! {
+            "user": bson::to_bson(&user_id).unwrap(),
+            "until_micros": {"$lte": until.timestamp_micros()},
         })
+        .await
+    }
+
+    async fn get_all_held(&self) -> Option<Vec<HeldReminder>> {
+        self.find_held(doc! {}).await
+    }
+
+    async fn get_all(&self) -> Option<Vec<(ReminderDefinition, i32)>> {
+        let raw = self.collection.clone_with_type::<Document>();
+        let mut reminders = vec![];
+        for document in Self::find_documents(&raw, doc! {}).await? {
+            let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+            let version = version_of(&document);
+            match upgrade(document.clone())
+                .and_then(|upgraded| upgraded.map(parse_reminder).transpose())
+            {
+                Ok(Some(reminder)) => reminders.push(reminder),
+                Ok(None) => skip_newer(&raw, &id, version),
+                Err(reason) => {
+                    self.quarantine(&raw, document, &reason).await;
+                }
+            }
+        }
+        Some(reminders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use mongodb::bson;
+
+    use super::{parse_reminder, MongoloidReminder};
+    use crate::interface::{Attachment, ReminderDefinition, Schedule};
+
+    fn round_trip(definition: &ReminderDefinition) -> (ReminderDefinition, i32) {
+        let document = bson::to_document(&MongoloidReminder::new(definition, 42)).unwrap();
+        parse_reminder(document).unwrap()
+    }
+
+    fn reminder(attachment: Option<Attachment>) -> ReminderDefinition {
+        let when = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        ReminderDefinition::new(Schedule::Once { when }, 1, "guarda qui".to_owned())
+            .with_attachment(attachment)
+            .with_tags(vec!["casa".to_owned()])
+    }
+
+    #[test]
+    fn attachments_survive_a_round_trip() {
+        for attachment in [
+            Attachment::Photo {
+                file_id: "AgACAgQ".to_owned(),
+            },
+            Attachment::Document {
+                file_id: "BQACAgQ".to_owned(),
+            },
+            Attachment::Voice {
+                file_id: "AwACAgQ".to_owned(),
+            },
+        ] {
+            let definition = reminder(Some(attachment.clone()));
+            let (parsed, id) = round_trip(&definition);
+            assert_eq!(42, id);
+            assert_eq!(Some(&attachment), parsed.attachment());
+            assert_eq!("guarda qui", parsed.message().as_str());
+            assert_eq!(definition.schedule(), parsed.schedule());
+            assert_eq!(definition.tags(), parsed.tags());
+        }
+    }
+
+    #[test]
+    fn reminders_without_attachment_stay_plain() {
+        let (parsed, _) = round_trip(&reminder(None));
+        assert_eq!(None, parsed.attachment());
+
+        let mut document = bson::to_document(&MongoloidReminder::new(&reminder(None), 42)).unwrap();
+        document.remove("attachment");
+        let (parsed, _) = parse_reminder(document).unwrap();
+        assert_eq!(None, parsed.attachment());
     }
 }
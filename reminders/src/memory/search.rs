@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+#[derive(Default)]
+pub struct SearchIndex {
+    terms: HashMap<u64, HashMap<String, HashSet<i32>>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, user_id: u64, id: i32, text: &str) {
+        let terms = self.terms.entry(user_id).or_default();
+        for term in tokenize(text) {
+            terms.entry(term).or_default().insert(id);
+        }
+    }
+
+    pub fn remove(&mut self, user_id: &u64, id: &i32, text: &str) {
+        if let Some(terms) = self.terms.get_mut(user_id) {
+            for term in tokenize(text) {
+                if let Some(ids) = terms.get_mut(&term) {
+                    ids.remove(id);
+                }
+            }
+            terms.retain(|_, ids| !ids.is_empty());
+        }
+    }
+
+    pub fn search(&self, user_id: &u64, query: &str) -> Vec<(i32, usize)> {
+        let terms = match self.terms.get(user_id) {
+            Some(terms) => terms,
+            None => return vec![],
+        };
+
+        let mut scores = HashMap::<i32, usize>::new();
+        for query_term in tokenize(query) {
+            for (term, ids) in terms.iter().filter(|(term, _)| term.contains(&query_term)) {
+                let score = match term {
+                    t if *t == query_term => 3,
+                    t if t.starts_with(&query_term) => 2,
+                    _ => 1,
+                };
+                for id in ids {
+                    *scores.entry(*id).or_default() += score;
+                }
+            }
+        }
+
+        let mut ranked = scores.into_iter().collect::<Vec<_>>();
+        ranked
+            .sort_by(|(a_id, a_score), (b_id, b_score)| b_score.cmp(a_score).then(a_id.cmp(b_id)));
+        ranked
+    }
+}
+
+fn tokenize(text: &str) -> HashSet<String> {
+    normalize(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_owned())
+        .collect()
+}
+
+fn normalize(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchIndex;
+
+    #[test]
+    fn search_ignores_case_and_accents() {
+        let mut index = SearchIndex::new();
+        index.insert(1, 10, "Chiamare il DENTISTA per la pulizia");
+        index.insert(1, 20, "Perché non è già passato il postino?");
+
+        assert_eq!(vec![(10, 3)], index.search(&1, "dentista"));
+        assert_eq!(vec![(20, 6)], index.search(&1, "perche gia"));
+    }
+
+    #[test]
+    fn search_ranks_exact_matches_before_partial_ones() {
+        let mut index = SearchIndex::new();
+        index.insert(1, 10, "dentisti della zona");
+        index.insert(1, 20, "appuntamento dal dentista");
+        index.insert(1, 30, "pagare la fattura");
+
+        let ids = index
+            .search(&1, "dentist")
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![10, 20], ids);
+
+        let ids = index
+            .search(&1, "dentista")
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![20], ids);
+    }
+
+    #[test]
+    fn search_forgets_removed_reminders() {
+        let mut index = SearchIndex::new();
+        index.insert(1, 10, "comprare il latte");
+        index.insert(2, 10, "comprare il pane");
+        index.remove(&1, &10, "comprare il latte");
+
+        assert!(index.search(&1, "comprare").is_empty());
+        assert_eq!(vec![(10, 3)], index.search(&2, "comprare"));
+    }
+}
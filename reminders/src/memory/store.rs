@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::interface::{AgendaSubscription, Firing, Payload, QuietHours, ReminderDefinition};
+
+// recipient, reminder and end of the quiet hours window it is held for
+pub(crate) type HeldReminder = (u64, i32, Payload, DateTime<Utc>);
+
+#[async_trait]
+pub(crate) trait ReminderStore {
+    async fn migrate(&self) -> (u64, u64);
+    async fn create(&self, definition: &ReminderDefinition, id: i32) -> bool;
+    async fn delete(&self, user_id: u64, id: i32) -> bool;
+    async fn exists(&self, user_id: u64, id: i32) -> bool;
+    async fn remove_recipient(&self, user_id: u64, id: i32, recipient: u64) -> bool;
+    async fn consume_occurrence(&self, user_id: u64, id: i32) -> bool;
+    async fn archive(&self, user_id: u64, id: i32) -> bool;
+    async fn record_firing(&self, firing: &Firing) -> bool;
+    async fn acknowledge_last_firing(&self, user_id: u64, id: i32) -> bool;
+    async fn get_firings(&self, user_id: u64, limit: Option<i64>) -> Vec<Firing>;
+    async fn get_failures(&self, limit: i64) -> Vec<Firing>;
+    async fn set_quiet_hours(&self, user_id: u64, quiet_hours: Option<&QuietHours>) -> bool;
+    async fn get_all_quiet_hours(&self) -> Option<HashMap<u64, QuietHours>>;
+    async fn set_agenda_subscription(
+        &self,
+        user_id: u64,
+        subscription: Option<&AgendaSubscription>,
+    ) -> bool;
+    async fn get_all_agenda_subscriptions(&self) -> Option<HashMap<u64, AgendaSubscription>>;
+    async fn hold(&self, user_id: u64, id: i32, payload: &Payload, until: &DateTime<Utc>) -> bool;
+    async fn release(&self, user_id: u64, until: &DateTime<Utc>) -> bool;
+    async fn get_held(&self, user_id: u64, until: &DateTime<Utc>) -> Option<Vec<HeldReminder>>;
+    async fn get_all_held(&self) -> Option<Vec<HeldReminder>>;
+    async fn get_all(&self) -> Option<Vec<(ReminderDefinition, i32)>>;
+}
+
+#[cfg(test)]
+pub(crate) use local::LocalReminderStore;
+
+#[cfg(test)]
+mod local {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Mutex,
+        },
+    };
+
+    use async_trait::async_trait;
+    use chrono::{DateTime, Utc};
+
+    use super::{HeldReminder, ReminderStore};
+    use crate::interface::{
+        AgendaSubscription, DeliveryOutcome, Firing, Payload, QuietHours, ReminderDefinition,
+    };
+
+    #[derive(Default)]
+    struct State {
+        reminders: HashMap<(u64, i32), ReminderDefinition>,
+        archived: HashMap<(u64, i32), ReminderDefinition>,
+        history: Vec<Firing>,
+        quiet_hours: HashMap<u64, QuietHours>,
+        agenda_subscriptions: HashMap<u64, AgendaSubscription>,
+        held: Vec<HeldReminder>,
+    }
+
+    #[derive(Default)]
+    pub(crate) struct LocalReminderStore {
+        state: Mutex<State>,
+        unavailable: AtomicBool,
+    }
+
+    impl LocalReminderStore {
+        pub(crate) fn new() -> Self {
+            Self::default()
+        }
+
+        pub(crate) fn set_unavailable(&self, unavailable: bool) {
+            self.unavailable.store(unavailable, Ordering::SeqCst);
+        }
+
+        fn is_unavailable(&self) -> bool {
+            self.unavailable.load(Ordering::SeqCst)
+        }
+
+        pub(crate) fn reminder(&self, user_id: u64, id: i32) -> Option<ReminderDefinition> {
+            self.state
+                .lock()
+                .unwrap()
+                .reminders
+                .get(&(user_id, id))
+                .cloned()
+        }
+
+        pub(crate) fn held_count(&self) -> usize {
+            self.state.lock().unwrap().held.len()
+        }
+
+        pub(crate) fn is_archived(&self, user_id: u64, id: i32) -> bool {
+            self.state
+                .lock()
+                .unwrap()
+                .archived
+                .contains_key(&(user_id, id))
+        }
+    }
+
+    #[async_trait]
+    impl ReminderStore for LocalReminderStore {
+        async fn migrate(&self) -> (u64, u64) {
+            (0, 0)
+        }
+
+        async fn create(&self, definition: &ReminderDefinition, id: i32) -> bool {
+            if self.is_unavailable() {
+                return false;
+            }
+            let key = (definition.user_id(), id);
+            let mut state = self.state.lock().unwrap();
+            if state.reminders.contains_key(&key) {
+                return false;
+            }
+            state.reminders.insert(key, definition.clone());
+            true
+        }
+
+        async fn delete(&self, user_id: u64, id: i32) -> bool {
+            if self.is_unavailable() {
+                return false;
+            }
+            self.state.lock().unwrap().reminders.remove(&(user_id, id));
+            true
+        }
+
+        async fn exists(&self, user_id: u64, id: i32) -> bool {
+            self.state
+                .lock()
+                .unwrap()
+                .reminders
+                .contains_key(&(user_id, id))
+        }
+
+        async fn remove_recipient(&self, user_id: u64, id: i32, recipient: u64) -> bool {
+            self.state
+                .lock()
+                .unwrap()
+                .reminders
+                .get_mut(&(user_id, id))
+                .is_some_and(|definition| definition.remove_recipient(&recipient))
+        }
+
+        async fn consume_occurrence(&self, user_id: u64, id: i32) -> bool {
+            self.state
+                .lock()
+                .unwrap()
+                .reminders
+                .get_mut(&(user_id, id))
+                .is_some_and(|definition| definition.consume_occurrence())
+        }
+
+        async fn archive(&self, user_id: u64, id: i32) -> bool {
+            if self.is_unavailable() {
+                return false;
+            }
+            let mut state = self.state.lock().unwrap();
+            match state.reminders.remove(&(user_id, id)) {
+                Some(definition) => {
+                    state.archived.insert((user_id, id), definition);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        async fn record_firing(&self, firing: &Firing) -> bool {
+            self.state.lock().unwrap().history.push(firing.clone());
+            true
+        }
+
+        async fn acknowledge_last_firing(&self, user_id: u64, id: i32) -> bool {
+            let mut state = self.state.lock().unwrap();
+            let last = state
+                .history
+                .iter_mut()
+                .filter(|firing| firing.reminder_id() == (user_id, id))
+                .max_by_key(|firing| *firing.fired_at());
+            match last {
+                Some(firing) => {
+                    *firing = Firing::new(
+                        user_id,
+                        id,
+                        *firing.fired_at(),
+                        firing.outcome().clone(),
+                        true,
+                    );
+                    true
+                }
+                None => false,
+            }
+        }
+
+        async fn get_firings(&self, user_id: u64, limit: Option<i64>) -> Vec<Firing> {
+            let mut firings = self
+                .state
+                .lock()
+                .unwrap()
+                .history
+                .iter()
+                .filter(|firing| firing.reminder_id().0 == user_id)
+                .cloned()
+                .collect::<Vec<_>>();
+            firings.sort_by_key(|firing| std::cmp::Reverse(*firing.fired_at()));
+            firings.truncate(limit.map_or(usize::MAX, |limit| limit as usize));
+            firings
+        }
+
+        async fn get_failures(&self, limit: i64) -> Vec<Firing> {
+            let mut failures = self
+                .state
+                .lock()
+                .unwrap()
+                .history
+                .iter()
+                .filter(|firing| matches!(firing.outcome(), DeliveryOutcome::Failed(_)))
+                .cloned()
+                .collect::<Vec<_>>();
+            failures.sort_by_key(|firing| std::cmp::Reverse(*firing.fired_at()));
+            failures.truncate(limit as usize);
+            failures
+        }
+
+        async fn set_quiet_hours(&self, user_id: u64, quiet_hours: Option<&QuietHours>) -> bool {
+            let mut state = self.state.lock().unwrap();
+            match quiet_hours {
+                Some(quiet_hours) => state.quiet_hours.insert(user_id, *quiet_hours),
+                None => state.quiet_hours.remove(&user_id),
+            };
+            true
+        }
+
+        async fn get_all_quiet_hours(&self) -> Option<HashMap<u64, QuietHours>> {
+            Some(self.state.lock().unwrap().quiet_hours.clone())
+        }
+
+        async fn set_agenda_subscription(
+            &self,
+            user_id: u64,
+            subscription: Option<&AgendaSubscription>,
+        ) -> bool {
+            let mut state = self.state.lock().unwrap();
+            match subscription {
+                Some(subscription) => state
+                    .agenda_subscriptions
+                    .insert(user_id, subscription.clone()),
+                None => state.agenda_subscriptions.remove(&user_id),
+            };
+            true
+        }
+
+        async fn get_all_agenda_subscriptions(&self) -> Option<HashMap<u64, AgendaSubscription>> {
+            Some(self.state.lock().unwrap().agenda_subscriptions.clone())
+        }
+
+        async fn hold(
+            &self,
+            user_id: u64,
+            id: i32,
+            payload: &Payload,
+            until: &DateTime<Utc>,
+        ) -> bool {
+            self.state
+                .lock()
+                .unwrap()
+                .held
+                .push((user_id, id, payload.clone(), *until));
+            true
+        }
+
+        async fn release(&self, user_id: u64, until: &DateTime<Utc>) -> bool {
+            self.state
+                .lock()
+                .unwrap()
+                .held
+                .retain(|(user, _, _, held_until)| *user != user_id || held_until > until);
+            true
+        }
+
+        async fn get_held(&self, user_id: u64, until: &DateTime<Utc>) -> Option<Vec<HeldReminder>> {
+            Some(
+                self.state
+                    .lock()
+                    .unwrap()
+                    .held
+                    .iter()
+                    .filter(|(user, _, _, held_until)| *user == user_id && held_until <= until)
+                    .cloned()
+                    .collect(),
+            )
+        }
+
+        async fn get_all_held(&self) -> Option<Vec<HeldReminder>> {
+            Some(self.state.lock().unwrap().held.clone())
+        }
+
+        async fn get_all(&self) -> Option<Vec<(ReminderDefinition, i32)>> {
+            if self.is_unavailable() {
+                return None;
+            }
+            Some(
+                self.state
+                    .lock()
+                    .unwrap()
+                    .reminders
+                    .iter()
+                    .map(|((_, id), definition)| (definition.clone(), *id))
+                    .collect(),
+            )
+        }
+    }
+}
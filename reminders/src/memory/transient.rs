@@ -1,6 +1,6 @@
 use std::{
-    cmp::Ordering,
-    collections::{BinaryHeap, HashMap},
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet},
     sync::{Mutex, MutexGuard},
 };
 
@@ -8,13 +8,19 @@ use chrono::DateTime;
 use chrono::Utc;
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
 
-use crate::interface::{Reminder, ReminderDefinition};
+use crate::{
+    agenda::AgendaEntry,
+    interface::{EngineStatus, Reminder, ReminderDefinition},
+};
+
+use super::search::SearchIndex;
 
 struct ReminderState {
     id: i32,
     definition: ReminderDefinition,
     current_tick: Option<DateTime<Utc>>,
     defused: bool,
+    generation: u64,
 }
 
 impl ReminderState {
@@ -37,10 +43,6 @@ impl ReminderState {
             self.current_tick.as_ref()
         }
     }
-
-    pub fn reminder_id(&self) -> (u64, i32) {
-        (self.definition.user_id(), self.id)
-    }
 }
 
 #[derive(PartialEq, Eq)]
@@ -48,6 +50,7 @@ struct ReminderHeapref {
     user_id: u64,
     id: i32,
     next_tick: DateTime<Utc>,
+    generation: u64,
 }
 
 impl PartialOrd for ReminderHeapref {
@@ -65,7 +68,11 @@ impl Ord for ReminderHeapref {
 pub struct InMemoryStorage {
     queue: BinaryHeap<ReminderHeapref>,
     user_reminder_lookup: HashMap<u64, HashMap<i32, Mutex<ReminderState>>>,
+    shared_reminder_lookup: HashMap<u64, HashSet<(u64, i32)>>,
+    tag_lookup: HashMap<u64, HashMap<String, HashSet<i32>>>,
+    search_index: SearchIndex,
     rand: rand::rngs::SmallRng,
+    generation: u64,
 }
 
 impl Default for InMemoryStorage {
@@ -79,7 +86,25 @@ impl InMemoryStorage {
         Self {
             queue: BinaryHeap::new(),
             user_reminder_lookup: HashMap::new(),
+            shared_reminder_lookup: HashMap::new(),
+            tag_lookup: HashMap::new(),
+            search_index: SearchIndex::new(),
             rand: SmallRng::from_entropy(),
+            generation: 0,
+        }
+    }
+
+    // ids are unique across owners, so that shared reminders never clash with owned ones
+    pub fn allocate_id(&mut self) -> i32 {
+        loop {
+            let id = self.rand.next_u32() as i32;
+            let taken = self
+                .user_reminder_lookup
+                .values()
+                .any(|reminders| reminders.contains_key(&id));
+            if !taken {
+                return id;
+            }
         }
     }
 
@@ -87,32 +112,56 @@ impl InMemoryStorage {
         &mut self,
         definition: ReminderDefinition,
         now: &DateTime<Utc>,
-        id: Option<i32>,
+        id: i32,
     ) -> Option<i32> {
-        definition
-            .next_tick(now)
-            .map(|d| self.internal_insert_new(definition, d, id))
+        if self.contains(&definition.user_id(), &id) {
+            return Some(id);
+        }
+        let next_tick = definition.next_tick(now)?;
+        // a defused entry lingers until its queued tick is dequeued, the new generation supersedes it
+        self.remove_reminder(&definition.user_id(), &id);
+        Some(self.internal_insert_new(definition, next_tick, id))
+    }
+
+    pub fn contains(&self, user_id: &u64, reminder_id: &i32) -> bool {
+        self.get_reminder(user_id, reminder_id)
+            .is_some_and(|state| !state.defused)
+    }
+
+    pub fn live_ids(&self) -> HashSet<(u64, i32)> {
+        self.user_reminder_lookup
+            .iter()
+            .flat_map(|(user_id, reminders)| {
+                reminders
+                    .iter()
+                    .filter(|(_, lock)| lock.lock().is_ok_and(|state| !state.defused))
+                    .map(|(id, _)| (*user_id, *id))
+            })
+            .collect()
     }
 
     pub fn dequeue_next(&mut self) -> Option<Reminder> {
-        let x = self
-            .queue
-            .pop()
-            .and_then(|reminder| self.get_reminder(&reminder.user_id, &reminder.id))
-            .map(|rem| {
-                let (user_id, id) = rem.reminder_id();
-                Reminder::new(
-                    user_id,
-                    id,
-                    rem.current_tick().cloned(),
-                    rem.definition.message(),
-                )
-            });
-        x
+        while let Some(heap_ref) = self.queue.pop() {
+            let reminder = self
+                .get_reminder(&heap_ref.user_id, &heap_ref.id)
+                .filter(|state| state.generation == heap_ref.generation)
+                .map(Self::into_reminder);
+            if reminder.is_some() {
+                return reminder;
+            }
+        }
+        None
     }
 
-    pub fn advance(&mut self, reminder: Reminder, then: &DateTime<Utc>) {
+    pub fn advance(&mut self, reminder: Reminder, then: &DateTime<Utc>) -> bool {
         let (user_id, id) = reminder.reminder_id();
+        let generation = reminder.generation();
+        if self
+            .get_reminder(&user_id, &id)
+            .is_some_and(|state| state.generation != generation)
+        {
+            return true;
+        }
         self.get_reminder(&user_id, &id)
             .and_then(|mut state| state.fast_forward_after(then).cloned())
             .map(|next_tick| {
@@ -120,11 +169,19 @@ impl InMemoryStorage {
                     user_id,
                     id,
                     next_tick,
+                    generation,
                 });
             })
+            .map(|_| true)
             .unwrap_or_else(|| {
                 self.remove_reminder(&user_id, &id);
-            });
+                false
+            })
+    }
+
+    pub fn consume_occurrence(&self, user_id: &u64, reminder_id: &i32) -> bool {
+        self.get_reminder(user_id, reminder_id)
+            .is_some_and(|mut state| state.definition.consume_occurrence())
     }
 
     pub fn defuse(&self, user_id: &u64, reminder_id: &i32) {
@@ -137,62 +194,190 @@ impl InMemoryStorage {
         self.queue.len()
     }
 
+    pub fn status(&self, next_count: usize) -> EngineStatus {
+        let mut per_user = vec![];
+        let mut next_ticks = vec![];
+        let mut lookup_size = 0usize;
+        for (user_id, reminders) in &self.user_reminder_lookup {
+            lookup_size += reminders.len();
+            let mut active = 0usize;
+            for state in reminders.values().filter_map(|lock| lock.lock().ok()) {
+                if let Some(tick) = state.current_tick() {
+                    active += 1;
+                    next_ticks.push((*tick, *user_id, state.id));
+                }
+            }
+            if active > 0 {
+                per_user.push((*user_id, active));
+            }
+        }
+        per_user.sort_by_key(|(user_id, active)| (Reverse(*active), *user_id));
+        next_ticks.sort();
+        next_ticks.truncate(next_count);
+
+        EngineStatus {
+            active: per_user.iter().map(|(_, active)| active).sum(),
+            per_user,
+            next_ticks,
+            queue_size: self.queue.len(),
+            lookup_size,
+            failures: vec![],
+            running_since: None,
+        }
+    }
+
     pub fn get(&self, user_id: &u64, reminder_id: &i32) -> Option<Reminder> {
         self.get_reminder(user_id, reminder_id)
+            .or_else(|| {
+                self.owner_of_shared(user_id, reminder_id)
+                    .and_then(|owner| self.get_reminder(&owner, reminder_id))
+            })
+            .map(Self::into_reminder)
+    }
+
+    pub fn get_all(&self, user_id: &u64) -> Vec<Reminder> {
+        let shared = self
+            .shared_reminder_lookup
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|(owner, id)| self.get_reminder(owner, id));
+
+        self.user_reminder_lookup
+            .get(user_id)
+            .into_iter()
+            .flat_map(|lookup| lookup.values())
+            .filter_map(|lock| lock.lock().ok())
+            .chain(shared)
             .map(Self::into_reminder)
+            .collect()
     }
 
-    pub fn get_all(&self, user_id: &u64) -> HashMap<i32, Reminder> {
+    pub fn active_count(&self, user_id: &u64) -> usize {
         self.user_reminder_lookup
             .get(user_id)
-            .map(|lookup| {
-                lookup
-                    .values()
-                    .filter_map(|lock| lock.lock().ok())
-                    .map(|reminder| (reminder.id, Self::into_reminder(reminder)))
-                    .collect::<HashMap<i32, Reminder>>()
-            })
-            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|lookup| lookup.values())
+            .filter_map(|lock| lock.lock().ok())
+            .filter(|state| state.current_tick().is_some())
+            .count()
+    }
+
+    pub fn get_tagged(&self, user_id: &u64, tag: &str) -> Vec<Reminder> {
+        self.tag_lookup
+            .get(user_id)
+            .and_then(|lookup| lookup.get(tag))
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.get_reminder(user_id, id))
+            .filter(|state| !state.defused)
+            .map(Self::into_reminder)
+            .collect()
+    }
+
+    pub fn search(&self, user_id: &u64, query: &str) -> Vec<Reminder> {
+        self.search_index
+            .search(user_id, query)
+            .into_iter()
+            .filter_map(|(id, _)| self.get_reminder(user_id, &id))
+            .filter(|state| !state.defused)
+            .map(Self::into_reminder)
+            .collect()
+    }
+
+    pub fn occurrences_between(
+        &self,
+        user_id: &u64,
+        from: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Vec<AgendaEntry> {
+        let owned = self
+            .user_reminder_lookup
+            .get(user_id)
+            .into_iter()
+            .flat_map(|lookup| lookup.keys().map(|id| (*user_id, *id)));
+        let shared = self
+            .shared_reminder_lookup
+            .get(user_id)
+            .into_iter()
+            .flat_map(|lookup| lookup.iter().copied());
+
+        let mut entries = vec![];
+        for (owner, id) in owned.chain(shared) {
+            let state = match self.get_reminder(&owner, &id) {
+                Some(state) if state.current_tick().is_some() => state,
+                _ => continue,
+            };
+            for when in state.definition.schedule().occurrences_between(from, to) {
+                entries.push(AgendaEntry::new(when, id, state.definition.message()));
+            }
+        }
+        entries.sort_by_key(|entry| *entry.when());
+        entries
+    }
+
+    pub fn owner_of_shared(&self, recipient: &u64, reminder_id: &i32) -> Option<u64> {
+        self.shared_reminder_lookup
+            .get(recipient)
+            .and_then(|lookup| lookup.iter().find(|(_, id)| id == reminder_id))
+            .map(|(owner, _)| *owner)
+    }
+
+    pub fn unsubscribe(&mut self, user_id: &u64, reminder_id: &i32, recipient: &u64) {
+        if let Some(mut state) = self.get_reminder(user_id, reminder_id) {
+            state.definition.remove_recipient(recipient);
+        }
+        if let Some(lookup) = self.shared_reminder_lookup.get_mut(recipient) {
+            lookup.remove(&(*user_id, *reminder_id));
+        }
     }
 
     fn internal_insert_new(
         &mut self,
         definition: ReminderDefinition,
         now: DateTime<Utc>,
-        id: Option<i32>,
+        id: i32,
     ) -> i32 {
-        let map = self
-            .user_reminder_lookup
-            .entry(definition.user_id())
-            .or_default();
+        for recipient in definition.recipients() {
+            self.shared_reminder_lookup
+                .entry(*recipient)
+                .or_default()
+                .insert((definition.user_id(), id));
+        }
 
-        let id = id.unwrap_or_else(|| {
-            let mut id = self.rand.next_u32() as i32;
-            while map.contains_key(&id) {
-                id = self.rand.next_u32() as i32;
-            }
-            id
-        });
+        let tags = self.tag_lookup.entry(definition.user_id()).or_default();
+        for tag in definition.tags() {
+            tags.entry(tag.clone()).or_default().insert(id);
+        }
 
+        self.search_index
+            .insert(definition.user_id(), id, definition.message().as_str());
+
+        self.generation += 1;
         let heap_ref: ReminderHeapref = ReminderHeapref {
             user_id: definition.user_id(),
             id,
             next_tick: now,
+            generation: self.generation,
         };
         let state = ReminderState {
             id,
             definition,
             current_tick: Some(now),
             defused: false,
+            generation: self.generation,
         };
 
-        map.insert(id, Mutex::new(state));
+        self.user_reminder_lookup
+            .entry(state.definition.user_id())
+            .or_default()
+            .insert(id, Mutex::new(state));
         self.queue.push(heap_ref);
 
         id
     }
 
-    fn get_reminder(&self, user_id: &u64, id: &i32) -> Option<MutexGuard<ReminderState>> {
+    fn get_reminder(&self, user_id: &u64, id: &i32) -> Option<MutexGuard<'_, ReminderState>> {
         self.user_reminder_lookup
             .get(user_id)
             .and_then(|reminders| reminders.get(id))
@@ -200,9 +385,28 @@ impl InMemoryStorage {
     }
 
     fn remove_reminder(&mut self, user_id: &u64, id: &i32) -> Option<Mutex<ReminderState>> {
-        self.user_reminder_lookup
+        let removed = self
+            .user_reminder_lookup
             .get_mut(user_id)
-            .and_then(|reminders| reminders.remove(id))
+            .and_then(|reminders| reminders.remove(id));
+        if let Some(state) = removed.as_ref().and_then(|lock| lock.lock().ok()) {
+            for recipient in state.definition.recipients() {
+                if let Some(lookup) = self.shared_reminder_lookup.get_mut(recipient) {
+                    lookup.remove(&(*user_id, *id));
+                }
+            }
+            if let Some(lookup) = self.tag_lookup.get_mut(user_id) {
+                for tag in state.definition.tags() {
+                    if let Some(ids) = lookup.get_mut(tag) {
+                        ids.remove(id);
+                    }
+                }
+                lookup.retain(|_, ids| !ids.is_empty());
+            }
+            self.search_index
+                .remove(user_id, id, state.definition.message().as_str());
+        }
+        removed
     }
 
     fn into_reminder(reminder: MutexGuard<ReminderState>) -> Reminder {
@@ -210,7 +414,194 @@ impl InMemoryStorage {
             reminder.definition.user_id(),
             reminder.id,
             reminder.current_tick().cloned(),
-            reminder.definition.message(),
+            reminder.definition.payload().clone(),
+            reminder.definition.recipients().to_vec(),
+            reminder.definition.tags().to_vec(),
+            reminder.definition.is_urgent(),
+        )
+        .with_remaining(reminder.definition.schedule().remaining())
+        .with_cron(reminder.definition.schedule().cron())
+        .with_generation(reminder.generation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Utc};
+
+    use super::InMemoryStorage;
+    use crate::interface::{ReminderDefinition, Schedule};
+
+    fn now() -> DateTime<Utc> {
+        "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+    }
+
+    fn once(user_id: u64, message: &str) -> ReminderDefinition {
+        ReminderDefinition::new(
+            Schedule::Once {
+                when: now() + Duration::hours(1),
+            },
+            user_id,
+            message.to_owned(),
         )
     }
+
+    #[test]
+    fn shared_reminders_do_not_clash_with_owned_ones() {
+        let mut storage = InMemoryStorage::new();
+        storage.insert(once(1, "mio"), &now(), 5);
+        storage.insert(once(2, "condiviso").with_recipients(vec![1]), &now(), 5);
+
+        let mut all = storage
+            .get_all(&1)
+            .iter()
+            .map(|reminder| (reminder.reminder_id(), reminder.message().to_string()))
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(
+            vec![((1, 5), "mio".to_owned()), ((2, 5), "condiviso".to_owned())],
+            all
+        );
+        assert_eq!(
+            Some((1, 5)),
+            storage.get(&1, &5).map(|reminder| reminder.reminder_id())
+        );
+        assert_eq!(Some(2), storage.owner_of_shared(&1, &5));
+    }
+
+    #[test]
+    fn allocated_ids_are_unique_across_owners() {
+        let mut storage = InMemoryStorage::new();
+        for user_id in 0..50 {
+            for _ in 0..20 {
+                let id = storage.allocate_id();
+                assert!(
+                    (0..50).all(|owner| !storage.contains(&owner, &id)),
+                    "{id} is already taken"
+                );
+                storage.insert(once(user_id, "x"), &now(), id);
+            }
+        }
+    }
+
+    #[test]
+    fn unsubscribing_removes_only_the_recipient() {
+        let mut storage = InMemoryStorage::new();
+        storage.insert(once(1, "mio"), &now(), 5);
+        storage.insert(once(2, "condiviso").with_recipients(vec![1, 3]), &now(), 5);
+
+        storage.unsubscribe(&2, &5, &1);
+        assert_eq!(None, storage.owner_of_shared(&1, &5));
+        assert_eq!(
+            vec![(1, 5)],
+            storage
+                .get_all(&1)
+                .iter()
+                .map(|reminder| reminder.reminder_id())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(2), storage.owner_of_shared(&3, &5));
+        assert_eq!(
+            Some(vec![3]),
+            storage
+                .get(&2, &5)
+                .map(|reminder| reminder.recipients().to_vec())
+        );
+    }
+
+    #[test]
+    fn active_count_skips_defused_and_shared_reminders() {
+        let mut storage = InMemoryStorage::new();
+        storage.insert(once(1, "uno"), &now(), 1);
+        storage.insert(once(1, "due"), &now(), 2);
+        storage.insert(once(2, "tre").with_recipients(vec![1]), &now(), 3);
+        assert_eq!(2, storage.active_count(&1));
+
+        storage.defuse(&1, &2);
+        assert_eq!(1, storage.active_count(&1));
+        assert_eq!(1, storage.active_count(&2));
+        assert_eq!(0, storage.active_count(&3));
+    }
+
+    #[test]
+    fn tagged_reminders_skip_defused_ones() {
+        let mut storage = InMemoryStorage::new();
+        let casa = vec!["casa".to_owned()];
+        storage.insert(once(1, "uno").with_tags(casa.clone()), &now(), 1);
+        storage.insert(once(1, "due").with_tags(casa.clone()), &now(), 2);
+        storage.insert(once(1, "tre"), &now(), 3);
+        storage.insert(once(2, "quattro").with_tags(casa), &now(), 4);
+
+        let tagged = |storage: &InMemoryStorage| {
+            let mut ids = storage
+                .get_tagged(&1, "casa")
+                .iter()
+                .map(|reminder| reminder.reminder_id().1)
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(vec![1, 2], tagged(&storage));
+        storage.defuse(&1, &2);
+        assert_eq!(vec![1], tagged(&storage));
+        assert!(storage.get_tagged(&1, "ufficio").is_empty());
+    }
+
+    #[test]
+    fn search_skips_defused_reminders() {
+        let mut storage = InMemoryStorage::new();
+        storage.insert(once(1, "comprare il pane"), &now(), 1);
+        storage.insert(once(1, "comprare il latte"), &now(), 2);
+        storage.defuse(&1, &2);
+
+        assert_eq!(
+            vec![(1, 1)],
+            storage
+                .search(&1, "comprare")
+                .iter()
+                .map(|reminder| reminder.reminder_id())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn inserting_a_live_reminder_again_is_a_no_op() {
+        let mut storage = InMemoryStorage::new();
+        assert_eq!(Some(1), storage.insert(once(1, "uno"), &now(), 1));
+        assert_eq!(Some(1), storage.insert(once(1, "altro"), &now(), 1));
+        assert_eq!(1, storage.size());
+        assert_eq!("uno", storage.dequeue_next().unwrap().message().as_str());
+        assert!(storage.dequeue_next().is_none());
+    }
+
+    #[test]
+    fn inserting_over_a_defused_reminder_replaces_it() {
+        let mut storage = InMemoryStorage::new();
+        storage.insert(once(1, "uno").with_tags(vec!["casa".to_owned()]), &now(), 1);
+        storage.defuse(&1, &1);
+        assert!(!storage.contains(&1, &1));
+
+        assert_eq!(Some(1), storage.insert(once(1, "uno"), &now(), 1));
+        assert!(storage.contains(&1, &1));
+        assert!(storage.get_tagged(&1, "casa").is_empty());
+
+        let reminder = storage.dequeue_next().unwrap();
+        assert_eq!(Some(&(now() + Duration::hours(1))), reminder.current_tick());
+        assert!(storage.dequeue_next().is_none());
+    }
+
+    #[test]
+    fn advancing_a_superseded_reminder_keeps_the_new_one() {
+        let mut storage = InMemoryStorage::new();
+        storage.insert(once(1, "uno"), &now(), 1);
+        let stale = storage.dequeue_next().unwrap();
+        storage.defuse(&1, &1);
+        storage.insert(once(1, "uno"), &now(), 1);
+
+        assert!(storage.advance(stale, &now()));
+        assert!(storage.contains(&1, &1));
+        assert_eq!(1, storage.size());
+    }
 }
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Attachment {
+    Photo { file_id: String },
+    Document { file_id: String },
+    Voice { file_id: String },
+}
+
+impl Attachment {
+    pub fn file_id(&self) -> &str {
+        match self {
+            Self::Photo { file_id } | Self::Document { file_id } | Self::Voice { file_id } => {
+                file_id
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Payload {
+    message: Arc<String>,
+    attachment: Option<Attachment>,
+}
+
+impl Payload {
+    pub fn new(message: Arc<String>, attachment: Option<Attachment>) -> Self {
+        Self {
+            message,
+            attachment,
+        }
+    }
+
+    pub fn message(&self) -> Arc<String> {
+        self.message.clone()
+    }
+
+    pub fn attachment(&self) -> Option<&Attachment> {
+        self.attachment.as_ref()
+    }
+}
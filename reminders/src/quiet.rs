@@ -0,0 +1,108 @@
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Tz,
+}
+
+impl QuietHours {
+    pub fn new(start: NaiveTime, end: NaiveTime, timezone: Tz) -> Self {
+        Self {
+            start,
+            end,
+            timezone,
+        }
+    }
+
+    pub fn start(&self) -> &NaiveTime {
+        &self.start
+    }
+
+    pub fn end(&self) -> &NaiveTime {
+        &self.end
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    pub fn end_of_window(&self, now: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = now.with_timezone(&self.timezone).naive_local();
+        let (date, time) = (local.date(), local.time());
+        let end_date = match (self.start <= self.end, time) {
+            (true, t) if self.start <= t && t < self.end => date,
+            (false, t) if t >= self.start => date.succ_opt()?,
+            (false, t) if t < self.end => date,
+            _ => return None,
+        };
+        self.local_to_utc(end_date.and_time(self.end))
+    }
+
+    fn local_to_utc(&self, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                self.timezone
+                    .from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|d| d.with_timezone(&Utc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QuietHours;
+    use chrono::{DateTime, NaiveTime, Utc};
+    use chrono_tz::Europe;
+
+    fn night() -> QuietHours {
+        QuietHours::new(
+            NaiveTime::from_hms_opt(23, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            Europe::Rome,
+        )
+    }
+
+    fn end_of_window(quiet: &QuietHours, now: &str) -> Option<String> {
+        quiet
+            .end_of_window(&now.parse::<DateTime<Utc>>().unwrap())
+            .map(|d| d.with_timezone(&Europe::Rome).to_rfc3339())
+    }
+
+    #[test]
+    fn window_across_midnight_ends_next_morning() {
+        assert_eq!(
+            Some("2024-08-18T07:00:00+02:00".to_owned()),
+            end_of_window(&night(), "2024-08-17T23:30:00+02:00")
+        );
+        assert_eq!(
+            Some("2024-08-18T07:00:00+02:00".to_owned()),
+            end_of_window(&night(), "2024-08-18T02:00:00+02:00")
+        );
+    }
+
+    #[test]
+    fn outside_window_is_not_quiet() {
+        assert_eq!(None, end_of_window(&night(), "2024-08-18T07:00:00+02:00"));
+        assert_eq!(None, end_of_window(&night(), "2024-08-18T22:59:00+02:00"));
+    }
+
+    #[test]
+    fn window_within_the_day() {
+        let lunch = QuietHours::new(
+            NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(14, 30, 0).unwrap(),
+            Europe::Rome,
+        );
+        assert_eq!(
+            Some("2024-12-03T14:30:00+01:00".to_owned()),
+            end_of_window(&lunch, "2024-12-03T13:10:00+01:00")
+        );
+        assert_eq!(None, end_of_window(&lunch, "2024-12-03T15:00:00+01:00"));
+    }
+}
@@ -3,9 +3,23 @@ use crate::interface::ScheduleInspection;
 use super::bitmap::Bitmap;
 use std::num::{NonZeroU8, NonZeroUsize};
 
-use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike, Utc,
+};
 use chrono_tz::Tz;
 
+const MAX_GAP_MINUTES: i64 = 3 * 60;
+const MAX_SKIPPED_CANDIDATES: usize = 3 * 60;
+const GREGORIAN_CYCLE_YEARS: i32 = 400;
+
+fn gcd(a: i32, b: i32) -> i32 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
 #[cfg_attr(test, derive(Eq, PartialEq))]
 #[derive(Clone, Debug)]
 pub struct ScheduleGrid {
@@ -72,36 +86,69 @@ impl ScheduleGrid {
     }
 
     pub fn next_scheduled_after(&self, now: &DateTime<Utc>) -> Option<DateTime<Utc>> {
-        let next_minute = &now.checked_add_signed(Duration::minutes(1)).unwrap();
-        let now = Self::truncate_to_minute(&next_minute.with_timezone(&self.timezone));
-        let current_year = now.year();
+        let mut from = now.with_timezone(&self.timezone).naive_local();
+        for _ in 0..MAX_SKIPPED_CANDIDATES {
+            let candidate = self.next_local_after(&from)?;
+            match self.resolve(&candidate) {
+                Some(instant) if instant > *now => return Some(instant),
+                _ => from = candidate,
+            }
+        }
+
+        None
+    }
+
+    fn resolve(&self, local: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self.timezone.from_local_datetime(local) {
+            LocalResult::Single(instant) => Some(instant),
+            LocalResult::Ambiguous(earliest, _) => Some(earliest),
+            LocalResult::None => (1..=MAX_GAP_MINUTES)
+                .map(|minutes| *local + Duration::minutes(minutes))
+                .find_map(|after| self.timezone.from_local_datetime(&after).earliest()),
+        }
+        .map(|instant| instant.with_timezone(&Utc))
+    }
+
+    fn next_local_after(&self, now: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let now = Self::truncate_to_minute(&now.checked_add_signed(Duration::minutes(1))?);
         let year_start = self.year_start as i32;
         let cadence = self.year_cadence.get() as i32;
-        let year_skip = match (current_year - year_start) % cadence {
-            ..=-1 => year_start - current_year,
-            0 => 0,
-            x @ 1.. => cadence - x,
-        };
-
-        let mut current_date = match year_skip {
-            0 => now,
-            _ => Self::set_year(&now, (current_year + year_skip).min(year_start)),
+        let first_year = match now.year() - year_start {
+            ..=0 => year_start,
+            elapsed => now.year() + (cadence - elapsed % cadence) % cadence,
         };
 
-        for _ in 0..50 {
-            current_date = match self
-                .find_month(&current_date)
-                .map(|d| d.with_timezone(&Utc))
-            {
-                d @ Some(_) => return d,
-                None => Self::set_year(&current_date, current_date.year() + cadence),
+        let mut exhausted = [[false; 7]; 2];
+        let cycle = GREGORIAN_CYCLE_YEARS / gcd(cadence, GREGORIAN_CYCLE_YEARS);
+        for year in (0..=cycle).map(|k| first_year + k * cadence) {
+            let start = match year == now.year() {
+                true => now,
+                false => Self::set_year(&now, year)?,
+            };
+            let (leap, weekday) = Self::calendar_of(year)?;
+            let whole_year = start.ordinal0() == 0 && start.time() == NaiveTime::MIN;
+            if whole_year && exhausted[leap][weekday] {
+                continue;
+            }
+            match self.find_month(&start) {
+                found @ Some(_) => return found,
+                None if whole_year => exhausted[leap][weekday] = true,
+                None => (),
             }
         }
 
         None
     }
 
-    fn find_month(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    fn calendar_of(year: i32) -> Option<(usize, usize)> {
+        let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+        Some((
+            first.leap_year() as usize,
+            first.weekday().num_days_from_monday() as usize,
+        ))
+    }
+
+    fn find_month(&self, now: &NaiveDateTime) -> Option<NaiveDateTime> {
         let current_month = now.month0() as usize;
         if self.months_of_year.get(current_month) {
             if let d @ Some(_) = self.find_day(now) {
@@ -119,7 +166,7 @@ impl ScheduleGrid {
         None
     }
 
-    fn find_day(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    fn find_day(&self, now: &NaiveDateTime) -> Option<NaiveDateTime> {
         let current_day = now.day0() as usize;
         let current_weekday = now.weekday().num_days_from_monday() as usize;
         let weekday_occurrance = Self::weekday_occurrance(now);
@@ -154,7 +201,7 @@ impl ScheduleGrid {
         None
     }
 
-    fn find_hour(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    fn find_hour(&self, now: &NaiveDateTime) -> Option<NaiveDateTime> {
         let current_hour = now.hour() as usize;
         if self.hours.get(current_hour) {
             if let d @ Some(_) = self.find_minute(now) {
@@ -172,7 +219,7 @@ impl ScheduleGrid {
         None
     }
 
-    fn find_minute(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    fn find_minute(&self, now: &NaiveDateTime) -> Option<NaiveDateTime> {
         let current_minute = now.minute() as usize;
         if self.minutes.get(current_minute) {
             return Some(*now);
@@ -187,39 +234,37 @@ impl ScheduleGrid {
         None
     }
 
-    fn set_year(now: &DateTime<Tz>, years: i32) -> DateTime<Tz> {
-        Self::set_month0(now, 0)
-            .and_then(|dt| dt.with_year(years))
-            .unwrap()
+    fn set_year(now: &NaiveDateTime, years: i32) -> Option<NaiveDateTime> {
+        Self::set_month0(now, 0).and_then(|dt| dt.with_year(years))
     }
 
-    fn set_month0(now: &DateTime<Tz>, month: u32) -> Option<DateTime<Tz>> {
+    fn set_month0(now: &NaiveDateTime, month: u32) -> Option<NaiveDateTime> {
         Self::set_day0(now, 0).and_then(|dt| dt.with_month0(month))
     }
 
-    fn set_day0(now: &DateTime<Tz>, day: u32) -> Option<DateTime<Tz>> {
+    fn set_day0(now: &NaiveDateTime, day: u32) -> Option<NaiveDateTime> {
         Self::set_hour(now, 0).and_then(|dt| dt.with_day0(day))
     }
 
-    fn set_hour(now: &DateTime<Tz>, hour: u32) -> Option<DateTime<Tz>> {
+    fn set_hour(now: &NaiveDateTime, hour: u32) -> Option<NaiveDateTime> {
         Self::set_minute(now, 0).and_then(|dt| dt.with_hour(hour))
     }
 
-    fn set_minute(now: &DateTime<Tz>, minute: u32) -> Option<DateTime<Tz>> {
+    fn set_minute(now: &NaiveDateTime, minute: u32) -> Option<NaiveDateTime> {
         now.with_minute(minute)
     }
 
-    fn truncate_to_minute(now: &DateTime<Tz>) -> DateTime<Tz> {
+    fn truncate_to_minute(now: &NaiveDateTime) -> NaiveDateTime {
         now.with_nanosecond(0)
             .and_then(|dt| dt.with_second(0))
             .unwrap()
     }
 
-    fn weekday_occurrance(now: &DateTime<Tz>) -> usize {
+    fn weekday_occurrance(now: &NaiveDateTime) -> usize {
         (now.day0() / 7) as usize
     }
 
-    pub fn inspect(&self, inspect_type: ScheduleInspection) -> Vec<u8> {
+    pub fn inspect(&self, inspect_type: ScheduleInspection) -> Bitmap {
         match inspect_type {
             ScheduleInspection::Minute => &self.minutes,
             ScheduleInspection::Hour => &self.hours,
@@ -229,7 +274,6 @@ impl ScheduleGrid {
             ScheduleInspection::MonthsOfYear => &self.months_of_year,
         }
         .clone()
-        .into()
     }
 
     pub fn inspect_year_and_cadence(&self) -> (u32, NonZeroU8) {
@@ -240,3 +284,221 @@ impl ScheduleGrid {
         self.timezone
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU8;
+
+    use super::ScheduleGrid;
+    use chrono::{DateTime, Utc};
+    use chrono_tz::{America, Australia, Europe, Tz};
+    use ntest::timeout;
+
+    fn daily_at(hours: Vec<usize>, minutes: Vec<usize>, timezone: Tz) -> ScheduleGrid {
+        ScheduleGrid::explicitly_new(
+            minutes,
+            hours,
+            (0..5).collect(),
+            (0..31).collect(),
+            (0..7).collect(),
+            (0..12).collect(),
+            NonZeroU8::new(1).unwrap(),
+            1970,
+            timezone,
+        )
+    }
+
+    fn next_ticks(grid: &ScheduleGrid, now: &str, count: usize) -> Vec<String> {
+        let mut current = now.parse::<DateTime<Utc>>().unwrap();
+        (0..count)
+            .map_while(|_| {
+                current = grid.next_scheduled_after(&current)?;
+                Some(current.with_timezone(&grid.timezone).to_rfc3339())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spring_gap_fires_at_the_next_valid_instant() {
+        let cases = [
+            (
+                Europe::Rome,
+                2,
+                30,
+                "2025-03-29T12:00:00+01:00",
+                ["2025-03-30T03:00:00+02:00", "2025-03-31T02:30:00+02:00"],
+            ),
+            (
+                America::New_York,
+                2,
+                15,
+                "2025-03-08T12:00:00-05:00",
+                ["2025-03-09T03:00:00-04:00", "2025-03-10T02:15:00-04:00"],
+            ),
+            (
+                Australia::Sydney,
+                2,
+                30,
+                "2025-10-04T12:00:00+10:00",
+                ["2025-10-05T03:00:00+11:00", "2025-10-06T02:30:00+11:00"],
+            ),
+            (
+                America::Santiago,
+                0,
+                0,
+                "2025-09-06T12:00:00-04:00",
+                ["2025-09-07T01:00:00-03:00", "2025-09-08T00:00:00-03:00"],
+            ),
+        ];
+        for (timezone, hour, minute, now, expected) in cases {
+            let grid = daily_at(vec![hour], vec![minute], timezone);
+            assert_eq!(expected.to_vec(), next_ticks(&grid, now, 2), "{timezone}");
+        }
+    }
+
+    #[test]
+    fn autumn_overlap_fires_once() {
+        let cases = [
+            (
+                Europe::Rome,
+                2,
+                30,
+                "2025-10-25T12:00:00+02:00",
+                ["2025-10-26T02:30:00+02:00", "2025-10-27T02:30:00+01:00"],
+            ),
+            (
+                America::New_York,
+                1,
+                30,
+                "2025-11-01T12:00:00-04:00",
+                ["2025-11-02T01:30:00-04:00", "2025-11-03T01:30:00-05:00"],
+            ),
+            (
+                Australia::Sydney,
+                2,
+                30,
+                "2025-04-05T12:00:00+11:00",
+                ["2025-04-06T02:30:00+11:00", "2025-04-07T02:30:00+10:00"],
+            ),
+            (
+                America::Santiago,
+                23,
+                30,
+                "2025-04-05T12:00:00-03:00",
+                ["2025-04-05T23:30:00-03:00", "2025-04-06T23:30:00-04:00"],
+            ),
+        ];
+        for (timezone, hour, minute, now, expected) in cases {
+            let grid = daily_at(vec![hour], vec![minute], timezone);
+            assert_eq!(expected.to_vec(), next_ticks(&grid, now, 2), "{timezone}");
+        }
+    }
+
+    #[test]
+    fn overlap_is_skipped_when_already_in_the_repeated_hour() {
+        let grid = daily_at(vec![2], vec![30], Europe::Rome);
+        assert_eq!(
+            vec!["2025-10-27T02:30:00+01:00".to_owned()],
+            next_ticks(&grid, "2025-10-26T02:10:00+01:00", 1)
+        );
+    }
+
+    #[test]
+    fn hourly_across_transitions() {
+        let grid = daily_at((0..24).collect(), vec![0], Europe::Rome);
+        assert_eq!(
+            vec![
+                "2025-03-30T01:00:00+01:00",
+                "2025-03-30T03:00:00+02:00",
+                "2025-03-30T04:00:00+02:00",
+            ],
+            next_ticks(&grid, "2025-03-30T00:30:00+01:00", 3)
+        );
+        assert_eq!(
+            vec![
+                "2025-10-26T02:00:00+02:00",
+                "2025-10-26T03:00:00+01:00",
+                "2025-10-26T04:00:00+01:00",
+            ],
+            next_ticks(&grid, "2025-10-26T01:30:00+02:00", 3)
+        );
+    }
+
+    #[test]
+    fn every_minute_in_the_gap_collapses_into_one_firing() {
+        let grid = daily_at(vec![2, 3], (0..60).collect(), America::New_York);
+        assert_eq!(
+            vec!["2025-03-09T03:00:00-04:00", "2025-03-09T03:01:00-04:00"],
+            next_ticks(&grid, "2025-03-09T01:59:00-05:00", 2)
+        );
+    }
+
+    fn yearly(
+        months: Vec<usize>,
+        days: Vec<usize>,
+        weekdays: Vec<usize>,
+        cadence: u8,
+        year_start: u32,
+    ) -> ScheduleGrid {
+        ScheduleGrid::explicitly_new(
+            vec![0],
+            vec![9],
+            (0..5).collect(),
+            days,
+            weekdays,
+            months,
+            NonZeroU8::new(cadence).unwrap(),
+            year_start,
+            Europe::Rome,
+        )
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn cadence_never_jumps_back_to_year_start() {
+        let grid = yearly(vec![0], vec![0], (0..7).collect(), 3, 2024);
+        assert_eq!(
+            vec!["2027-01-01T09:00:00+01:00", "2030-01-01T09:00:00+01:00"],
+            next_ticks(&grid, "2026-06-01T12:00:00+02:00", 2)
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn cadence_waits_for_a_future_year_start() {
+        let grid = yearly(vec![0], vec![0], (0..7).collect(), 3, 2030);
+        assert_eq!(
+            vec!["2030-01-01T09:00:00+01:00", "2033-01-01T09:00:00+01:00"],
+            next_ticks(&grid, "2024-08-17T20:58:00+02:00", 2)
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn leap_day_with_cadences() {
+        let every_3_years = yearly(vec![1], vec![28], (0..7).collect(), 3, 2024);
+        assert_eq!(
+            vec!["2036-02-29T09:00:00+01:00", "2048-02-29T09:00:00+01:00"],
+            next_ticks(&every_3_years, "2024-08-17T20:58:00+02:00", 2)
+        );
+        let every_century = yearly(vec![1], vec![28], (0..7).collect(), 100, 2100);
+        assert_eq!(
+            vec!["2400-02-29T09:00:00+01:00", "2800-02-29T09:00:00+01:00"],
+            next_ticks(&every_century, "2024-08-17T20:58:00+02:00", 2)
+        );
+        let leap_monday = yearly(vec![1], vec![28], vec![0], 1, 2024);
+        assert_eq!(
+            vec!["2044-02-29T09:00:00+01:00"],
+            next_ticks(&leap_monday, "2024-08-17T20:58:00+02:00", 1)
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn proves_that_there_is_no_occurrence() {
+        let never_leap = yearly(vec![1], vec![28], (0..7).collect(), 4, 2025);
+        assert!(next_ticks(&never_leap, "2025-08-17T20:58:00+02:00", 1).is_empty());
+        let never_on_31st = yearly(vec![3, 5, 8, 10], vec![30], (0..7).collect(), 1, 2024);
+        assert!(next_ticks(&never_on_31st, "2025-08-17T20:58:00+02:00", 1).is_empty());
+    }
+}
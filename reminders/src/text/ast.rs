@@ -0,0 +1,160 @@
+use std::fmt;
+
+use chrono::{Duration, Month, Weekday};
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Num {
+    pub value: u32,
+    pub raw: String,
+}
+
+impl fmt::Debug for Num {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+}
+
+impl Unit {
+    pub fn duration(&self) -> Duration {
+        match self {
+            Self::Second => Duration::seconds(1),
+            Self::Minute => Duration::minutes(1),
+            Self::Hour => Duration::hours(1),
+            Self::Day => Duration::days(1),
+            Self::Week => Duration::weeks(1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartOfDay {
+    Morning,
+    Evening,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimeExpr {
+    Once(Vec<When>),
+    Recurrent(Vec<Rule>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum When {
+    After(Vec<Amount>),
+    At(At),
+    On(DateSpec),
+    In {
+        month: Month,
+        year: Option<Num>,
+    },
+    Year(Num),
+    DaysFromNow(u32),
+    PartOfDay(PartOfDay),
+    Weekday {
+        weekday: Weekday,
+        strictly_next: bool,
+    },
+    Next(Period),
+    Skipped(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Amount {
+    Units { quantity: Num, unit: Unit },
+    Half(Option<Unit>),
+    Quarters { quantity: Num, unit: Option<Unit> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum At {
+    Clock {
+        raw: String,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    },
+    Spoken {
+        hour: Num,
+        minute: Option<Num>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DateSpec {
+    Numeric {
+        raw: String,
+        day: u32,
+        month: u32,
+        year: Option<u32>,
+    },
+    Day {
+        day: Num,
+        month: Option<Month>,
+        year: Option<Num>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    Every(Vec<Every>),
+    Since(Since),
+    Until(Until),
+    At(Vec<At>),
+    Window { from: At, to: At },
+    Times(Num),
+    For(Vec<Amount>),
+    Skipped(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Since(pub Vec<Bound>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Until(pub Vec<Bound>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Every {
+    Each(Unit),
+    Weekdays {
+        positions: Vec<u32>,
+        weekdays: Vec<Weekday>,
+    },
+    Days {
+        days: Vec<Num>,
+        months: Vec<Month>,
+    },
+    DaysOfMonth(Vec<Num>),
+    Months(Vec<Month>),
+    Date {
+        raw: String,
+        day: u32,
+        month: u32,
+    },
+    Years(Num),
+    Skipped(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Bound {
+    At(At),
+    Date(DateSpec),
+    Year(Num),
+    Month { month: Month, year: Option<Num> },
+    Weekday(Weekday),
+}
@@ -0,0 +1,205 @@
+use std::{collections::HashSet, num::NonZeroU8};
+
+use chrono_tz::Tz;
+
+use crate::{bitmap::Bitmap, schedule::ScheduleGrid};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+struct FieldSpec {
+    name: &'static str,
+    min: usize,
+    max: usize,
+    names: &'static [&'static str],
+}
+
+const MINUTE: FieldSpec = FieldSpec {
+    name: "minuto",
+    min: 0,
+    max: 59,
+    names: &[],
+};
+const HOUR: FieldSpec = FieldSpec {
+    name: "ora",
+    min: 0,
+    max: 23,
+    names: &[],
+};
+const DAY_OF_MONTH: FieldSpec = FieldSpec {
+    name: "giorno del mese",
+    min: 1,
+    max: 31,
+    names: &[],
+};
+const MONTH: FieldSpec = FieldSpec {
+    name: "mese",
+    min: 1,
+    max: 12,
+    names: &MONTHS,
+};
+const DAY_OF_WEEK: FieldSpec = FieldSpec {
+    name: "giorno della settimana",
+    min: 0,
+    max: 7,
+    names: &WEEKDAYS,
+};
+
+pub fn parse(expression: &str, timezone: &Tz) -> Result<ScheduleGrid, String> {
+    let fields = expression
+        .trim_matches(['"', '“', '”', '\''])
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    let [minutes, hours, days_of_month, months, days_of_week] = fields.as_slice() else {
+        return Err(format!(
+            "servono 5 campi (minuto ora giorno mese giorno-della-settimana), ne ho trovati {}",
+            fields.len()
+        ));
+    };
+    let days_of_month = shift_down(parse_field(days_of_month, &DAY_OF_MONTH)?);
+    let days_of_week = parse_field(days_of_week, &DAY_OF_WEEK)?
+        .into_iter()
+        .map(|day| (day + 6) % 7)
+        .collect::<Vec<_>>();
+    // cron fires when either restricted day field matches, the grid needs both to match
+    if is_restricted(&days_of_month, 31) && is_restricted(&days_of_week, 7) {
+        return Err(
+            "non posso limitare insieme giorno del mese e giorno della settimana: cron li considererebbe in alternativa".to_owned(),
+        );
+    }
+
+    Ok(ScheduleGrid::explicitly_new(
+        parse_field(minutes, &MINUTE)?,
+        parse_field(hours, &HOUR)?,
+        (0..5).collect(),
+        days_of_month,
+        days_of_week,
+        shift_down(parse_field(months, &MONTH)?),
+        NonZeroU8::new(1).unwrap(),
+        1970,
+        *timezone,
+    ))
+}
+
+pub fn render(grid: &ScheduleGrid) -> Option<String> {
+    if grid.year_cadence.get() != 1 || grid.weeks_of_month.count() != grid.weeks_of_month.size() {
+        return None;
+    }
+    let days_of_month = render_field(shift_up(set_bits(&grid.days_of_month)), &DAY_OF_MONTH)?;
+    let mut days_of_week = set_bits(&grid.days_of_week)
+        .into_iter()
+        .map(|day| (day + 1) % 7)
+        .collect::<Vec<_>>();
+    days_of_week.sort();
+    let days_of_week = match days_of_week.len() {
+        7 => "*".to_owned(),
+        _ => render_field(days_of_week, &DAY_OF_WEEK)?,
+    };
+    if days_of_month != "*" && days_of_week != "*" {
+        return None;
+    }
+
+    Some(
+        [
+            render_field(set_bits(&grid.minutes), &MINUTE)?,
+            render_field(set_bits(&grid.hours), &HOUR)?,
+            days_of_month,
+            render_field(shift_up(set_bits(&grid.months_of_year)), &MONTH)?,
+            days_of_week,
+        ]
+        .join(" "),
+    )
+}
+
+fn parse_field(field: &str, spec: &FieldSpec) -> Result<Vec<usize>, String> {
+    Ok(field
+        .split(',')
+        .map(|item| parse_item(item, spec))
+        .collect::<Result<Vec<_>, _>>()?
+        .concat())
+}
+
+fn parse_item(item: &str, spec: &FieldSpec) -> Result<Vec<usize>, String> {
+    let invalid = || format!("\"{item}\" non è un {} valido", spec.name);
+    let (range, step) = match item.split_once('/') {
+        Some((range, step)) => (
+            range,
+            step.parse::<usize>()
+                .ok()
+                .filter(|step| *step > 0)
+                .ok_or_else(invalid)?,
+        ),
+        None => (item, 1),
+    };
+    let (start, end) = match range.split_once('-') {
+        _ if range == "*" => (spec.min, spec.max),
+        Some((start, end)) => (parse_value(start, spec), parse_value(end, spec)),
+        None if step > 1 => (parse_value(range, spec), spec.max),
+        None => (parse_value(range, spec), parse_value(range, spec)),
+    };
+    if start < spec.min || end > spec.max || start > end {
+        return Err(invalid());
+    }
+    Ok((start..=end).step_by(step).collect())
+}
+
+fn parse_value(value: &str, spec: &FieldSpec) -> usize {
+    spec.names
+        .iter()
+        .position(|name| *name == value)
+        .map(|position| position + spec.min)
+        .or_else(|| value.parse().ok())
+        .unwrap_or(usize::MAX)
+}
+
+fn render_field(values: Vec<usize>, spec: &FieldSpec) -> Option<String> {
+    let (first, last) = (*values.first()?, *values.last()?);
+    if values.len() == spec.max - spec.min + 1 {
+        return Some("*".to_owned());
+    }
+    let step = values.get(1).map(|second| second - first).unwrap_or(1);
+    if step > 1
+        && values.len() > 2
+        && first == spec.min
+        && last + step > spec.max
+        && values.windows(2).all(|pair| pair[1] - pair[0] == step)
+    {
+        return Some(format!("*/{step}"));
+    }
+
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for value in values {
+        match runs.last_mut() {
+            Some((_, end)) if *end + 1 == value => *end = value,
+            _ => runs.push((value, value)),
+        }
+    }
+    Some(
+        runs.into_iter()
+            .map(|(start, end)| match end - start {
+                0 => start.to_string(),
+                1 => format!("{start},{end}"),
+                _ => format!("{start}-{end}"),
+            })
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+}
+
+fn is_restricted(values: &[usize], size: usize) -> bool {
+    values.iter().collect::<HashSet<_>>().len() < size
+}
+
+fn set_bits(bitmap: &Bitmap) -> Vec<usize> {
+    (0..bitmap.size()).filter(|&i| bitmap.get(i)).collect()
+}
+
+fn shift_down(values: Vec<usize>) -> Vec<usize> {
+    values.into_iter().map(|value| value - 1).collect()
+}
+
+fn shift_up(values: Vec<usize>) -> Vec<usize> {
+    values.into_iter().map(|value| value + 1).collect()
+}
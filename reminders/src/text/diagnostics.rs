@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+
+use crate::interface::Schedule;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Hour,
+    Minute,
+    Day,
+    Month,
+    Amount,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    InThePast,
+    UntilBeforeSince,
+    NeverFires,
+    PartialHourWindow,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    Ignored { token: String },
+    OutOfRange { token: String, field: Field },
+    Conflicting(Conflict),
+    InvalidCron { reason: String },
+    FarAway { first: DateTime<Utc> },
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Self::Ignored { .. } | Self::FarAway { .. })
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseOutcome {
+    schedule: Option<Schedule>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseOutcome {
+    pub fn new(schedule: Option<Schedule>, diagnostics: Vec<Diagnostic>) -> Self {
+        Self {
+            schedule,
+            diagnostics,
+        }
+    }
+
+    pub fn schedule(&self) -> Option<&Schedule> {
+        self.schedule.as_ref()
+    }
+
+    pub fn into_schedule(self) -> Option<Schedule> {
+        self.schedule
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
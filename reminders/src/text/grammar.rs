@@ -0,0 +1,611 @@
+use std::collections::HashMap;
+
+use chrono::{Month, Weekday};
+use lazy_static::lazy_static;
+
+use super::{
+    ast::{
+        Amount, At, Bound, DateSpec, Every, Num, PartOfDay, Period, Rule, Since, TimeExpr, Unit,
+        Until, When,
+    },
+    lexer::Token,
+};
+
+lazy_static! {
+    static ref WEEKDAYS: HashMap<String, Weekday> = {
+        let mut lookup = HashMap::with_capacity(14);
+        lookup.insert("lunedì".to_owned(), Weekday::Mon);
+        lookup.insert("lunedi".to_owned(), Weekday::Mon);
+        lookup.insert("martedì".to_owned(), Weekday::Tue);
+        lookup.insert("martedi".to_owned(), Weekday::Tue);
+        lookup.insert("mercoledì".to_owned(), Weekday::Wed);
+        lookup.insert("mercoledi".to_owned(), Weekday::Wed);
+        lookup.insert("giovedì".to_owned(), Weekday::Thu);
+        lookup.insert("giovedi".to_owned(), Weekday::Thu);
+        lookup.insert("venerdì".to_owned(), Weekday::Fri);
+        lookup.insert("venerdi".to_owned(), Weekday::Fri);
+        lookup.insert("sabato".to_owned(), Weekday::Sat);
+        lookup.insert("domenica".to_owned(), Weekday::Sun);
+        lookup
+    };
+    static ref MONTHS: HashMap<String, Month> = {
+        let mut lookup = HashMap::with_capacity(12);
+        lookup.insert("gennaio".to_owned(), Month::January);
+        lookup.insert("febbraio".to_owned(), Month::February);
+        lookup.insert("marzo".to_owned(), Month::March);
+        lookup.insert("aprile".to_owned(), Month::April);
+        lookup.insert("maggio".to_owned(), Month::May);
+        lookup.insert("giugno".to_owned(), Month::June);
+        lookup.insert("luglio".to_owned(), Month::July);
+        lookup.insert("agosto".to_owned(), Month::August);
+        lookup.insert("settembre".to_owned(), Month::September);
+        lookup.insert("ottobre".to_owned(), Month::October);
+        lookup.insert("novembre".to_owned(), Month::November);
+        lookup.insert("dicembre".to_owned(), Month::December);
+        lookup
+    };
+    static ref UNITS: HashMap<String, Unit> = {
+        let mut lookup = HashMap::with_capacity(10);
+        // Plural
+        lookup.insert("secondi".to_owned(), Unit::Second);
+        lookup.insert("minuti".to_owned(), Unit::Minute);
+        lookup.insert("ore".to_owned(), Unit::Hour);
+        lookup.insert("giorni".to_owned(), Unit::Day);
+        lookup.insert("settimane".to_owned(), Unit::Week);
+        // Singular
+        lookup.insert("secondo".to_owned(), Unit::Second);
+        lookup.insert("minuto".to_owned(), Unit::Minute);
+        lookup.insert("ora".to_owned(), Unit::Hour);
+        lookup.insert("giorno".to_owned(), Unit::Day);
+        lookup.insert("settimana".to_owned(), Unit::Week);
+        lookup
+    };
+    static ref EACH_UNITS: HashMap<String, Unit> = {
+        let mut lookup = HashMap::with_capacity(4);
+        lookup.insert("minuto".to_owned(), Unit::Minute);
+        lookup.insert("ora".to_owned(), Unit::Hour);
+        lookup.insert("giorno".to_owned(), Unit::Day);
+        lookup.insert("settimana".to_owned(), Unit::Week);
+        lookup
+    };
+    static ref PERIODS: HashMap<String, Period> = {
+        let mut lookup = HashMap::with_capacity(3);
+        lookup.insert("settimana".to_owned(), Period::Week);
+        lookup.insert("mese".to_owned(), Period::Month);
+        lookup.insert("anno".to_owned(), Period::Year);
+        lookup
+    };
+    static ref NUMBERS: HashMap<String, u32> = {
+        let mut lookup = HashMap::with_capacity(24);
+        lookup.insert("un".to_owned(), 1);
+        lookup.insert("uno".to_owned(), 1);
+        lookup.insert("una".to_owned(), 1);
+        lookup.insert("due".to_owned(), 2);
+        lookup.insert("tre".to_owned(), 3);
+        lookup.insert("quattro".to_owned(), 4);
+        lookup.insert("cinque".to_owned(), 5);
+        lookup.insert("sei".to_owned(), 6);
+        lookup.insert("sette".to_owned(), 7);
+        lookup.insert("otto".to_owned(), 8);
+        lookup.insert("nove".to_owned(), 9);
+        lookup.insert("dieci".to_owned(), 10);
+        lookup.insert("undici".to_owned(), 11);
+        lookup.insert("dodici".to_owned(), 12);
+        lookup.insert("tredici".to_owned(), 13);
+        lookup.insert("quattordici".to_owned(), 14);
+        lookup.insert("quindici".to_owned(), 15);
+        lookup.insert("venti".to_owned(), 20);
+        lookup.insert("trenta".to_owned(), 30);
+        lookup.insert("quaranta".to_owned(), 40);
+        lookup.insert("cinquanta".to_owned(), 50);
+        lookup
+    };
+    static ref POSITIONS: HashMap<String, u32> = {
+        let mut lookup = HashMap::with_capacity(5);
+        // Masculine
+        lookup.insert("primo".to_owned(), 0);
+        lookup.insert("secondo".to_owned(), 1);
+        lookup.insert("terzo".to_owned(), 2);
+        lookup.insert("quarto".to_owned(), 3);
+        lookup.insert("quinto".to_owned(), 4);
+        // Feminine
+        lookup.insert("prima".to_owned(), 0);
+        lookup.insert("seconda".to_owned(), 1);
+        lookup.insert("terza".to_owned(), 2);
+        lookup.insert("quarta".to_owned(), 3);
+        lookup.insert("quinta".to_owned(), 4);
+        lookup
+    };
+}
+
+const ONCE_STARTERS: &[&str] = &[
+    "alle",
+    "a",
+    "il",
+    "lo",
+    "l",
+    "la",
+    "nel",
+    "ad",
+    "tra",
+    "fra",
+    "domani",
+    "dopodomani",
+    "stasera",
+    "stamattina",
+    "prossimo",
+    "prossima",
+    "per",
+];
+const RECURRENT_STARTERS: &[&str] = &["ogni", "fino", "dal", "dall", "da", "dalle"];
+const EVERY_STOP_WORDS: &[&str] = &[
+    "fino", "al", "all", "a", "ad", "dal", "da", "dall", "alle", "ogni", "dalle", "tra", "fra",
+    "per",
+];
+const NEXT: &[&str] = &["prossimo", "prossima"];
+
+type Input<'t> = &'t [Token];
+type Parsed<'t, O> = Option<(O, Input<'t>)>;
+
+pub fn parse(tokens: &[Token]) -> Option<TimeExpr> {
+    let expr = match tokens.first()? {
+        _ if window(tokens).or_else(|| limit(tokens)).is_some() => {
+            TimeExpr::Recurrent(many0(rule)(tokens)?.0)
+        }
+        Token::Word(word) if RECURRENT_STARTERS.contains(&word.as_str()) => {
+            TimeExpr::Recurrent(many0(rule)(tokens)?.0)
+        }
+        Token::Word(word)
+            if WEEKDAYS.contains_key(word) || ONCE_STARTERS.contains(&word.as_str()) =>
+        {
+            TimeExpr::Once(many0(when)(tokens)?.0)
+        }
+        _ => return None,
+    };
+    Some(expr)
+}
+
+// Combinators
+
+fn token<'t, O>(f: impl Fn(&'t Token) -> Option<O>) -> impl Fn(Input<'t>) -> Parsed<'t, O> {
+    move |input| {
+        input
+            .split_first()
+            .and_then(|(first, rest)| f(first).map(|o| (o, rest)))
+    }
+}
+
+fn keyword<'t>(words: &'static [&'static str]) -> impl Fn(Input<'t>) -> Parsed<'t, &'t str> {
+    token(move |t| match t {
+        Token::Word(word) if words.contains(&word.as_str()) => Some(word.as_str()),
+        _ => None,
+    })
+}
+
+fn lookup<'t, V: Copy>(table: &'static HashMap<String, V>) -> impl Fn(Input<'t>) -> Parsed<'t, V> {
+    token(move |t| match t {
+        Token::Word(word) => table.get(word).copied(),
+        _ => None,
+    })
+}
+
+fn map<'t, A, B>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+    f: impl Fn(A) -> B,
+) -> impl Fn(Input<'t>) -> Parsed<'t, B> {
+    move |input| p(input).map(|(a, rest)| (f(a), rest))
+}
+
+fn opt<'t, A>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+) -> impl Fn(Input<'t>) -> Parsed<'t, Option<A>> {
+    move |input| match p(input) {
+        Some((a, rest)) => Some((Some(a), rest)),
+        None => Some((None, input)),
+    }
+}
+
+fn pair<'t, A, B>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+    q: impl Fn(Input<'t>) -> Parsed<'t, B>,
+) -> impl Fn(Input<'t>) -> Parsed<'t, (A, B)> {
+    move |input| {
+        let (a, rest) = p(input)?;
+        let (b, rest) = q(rest)?;
+        Some(((a, b), rest))
+    }
+}
+
+fn preceded<'t, A, B>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+    q: impl Fn(Input<'t>) -> Parsed<'t, B>,
+) -> impl Fn(Input<'t>) -> Parsed<'t, B> {
+    map(pair(p, q), |(_, b)| b)
+}
+
+fn terminated<'t, A, B>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+    q: impl Fn(Input<'t>) -> Parsed<'t, B>,
+) -> impl Fn(Input<'t>) -> Parsed<'t, A> {
+    map(pair(p, q), |(a, _)| a)
+}
+
+fn alt<'t, A>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+    q: impl Fn(Input<'t>) -> Parsed<'t, A>,
+) -> impl Fn(Input<'t>) -> Parsed<'t, A> {
+    move |input| p(input).or_else(|| q(input))
+}
+
+fn many0<'t, A>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+) -> impl Fn(Input<'t>) -> Parsed<'t, Vec<A>> {
+    move |mut input| {
+        let mut items = vec![];
+        while let Some((item, rest)) = p(input).filter(|(_, rest)| rest.len() < input.len()) {
+            items.push(item);
+            input = rest;
+        }
+        Some((items, input))
+    }
+}
+
+fn many1<'t, A>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+) -> impl Fn(Input<'t>) -> Parsed<'t, Vec<A>> {
+    let items = many0(p);
+    move |input| items(input).filter(|(items, _)| !items.is_empty())
+}
+
+fn list<'t, A>(p: impl Fn(Input<'t>) -> Parsed<'t, A>) -> impl Fn(Input<'t>) -> Parsed<'t, Vec<A>> {
+    many1(terminated(p, many0(keyword(&["e"]))))
+}
+
+fn satisfies<'t, A>(
+    p: impl Fn(Input<'t>) -> Parsed<'t, A>,
+    predicate: impl Fn(&A) -> bool,
+) -> impl Fn(Input<'t>) -> Parsed<'t, A> {
+    move |input| p(input).filter(|(a, _)| predicate(a))
+}
+
+fn any(input: Input<'_>) -> Parsed<'_, String> {
+    token(|t: &Token| Some(t.raw().to_owned()))(input)
+}
+
+// Terminals
+
+fn number(input: Input<'_>) -> Parsed<'_, Num> {
+    token(|t: &Token| match t {
+        Token::Number { raw, value } => Some(Num {
+            value: *value,
+            raw: raw.clone(),
+        }),
+        _ => None,
+    })(input)
+}
+
+fn quantity(input: Input<'_>) -> Parsed<'_, Num> {
+    number(input).or_else(|| {
+        token(|t: &Token| match t {
+            Token::Word(word) => NUMBERS.get(word).map(|value| Num {
+                value: *value,
+                raw: word.clone(),
+            }),
+            _ => None,
+        })(input)
+    })
+}
+
+fn numeric_date(input: Input<'_>) -> Parsed<'_, (String, u32, u32, Option<u32>)> {
+    token(|t: &Token| match t {
+        Token::Date {
+            raw,
+            day,
+            month,
+            year,
+        } => Some((raw.clone(), *day, *month, *year)),
+        _ => None,
+    })(input)
+}
+
+fn weekday(input: Input<'_>) -> Parsed<'_, Weekday> {
+    lookup(&WEEKDAYS)(input)
+}
+
+fn month(input: Input<'_>) -> Parsed<'_, Month> {
+    lookup(&MONTHS)(input)
+}
+
+fn unit(input: Input<'_>) -> Parsed<'_, Unit> {
+    lookup(&UNITS)(input)
+}
+
+fn period(input: Input<'_>) -> Parsed<'_, Period> {
+    lookup(&PERIODS)(input)
+}
+
+fn year(input: Input<'_>) -> Parsed<'_, Num> {
+    satisfies(number, |n| n.value > 1970)(input)
+}
+
+// Shared non-terminals
+
+fn at(input: Input<'_>) -> Parsed<'_, At> {
+    token(|t: &Token| match t {
+        Token::Clock {
+            raw,
+            hour,
+            minute,
+            second,
+        } => Some(At::Clock {
+            raw: raw.clone(),
+            hour: *hour,
+            minute: *minute,
+            second: *second,
+        }),
+        _ => None,
+    })(input)
+    .or_else(|| {
+        map(
+            pair(number, opt(preceded(keyword(&["e"]), number))),
+            |(hour, minute)| At::Spoken { hour, minute },
+        )(input)
+    })
+}
+
+fn date_spec(input: Input<'_>) -> Parsed<'_, DateSpec> {
+    map(numeric_date, |(raw, day, month, year)| DateSpec::Numeric {
+        raw,
+        day,
+        month,
+        year,
+    })(input)
+    .or_else(|| {
+        map(
+            pair(
+                satisfies(number, |n| n.value <= 1970),
+                opt(pair(month, opt(number))),
+            ),
+            |(day, rest)| DateSpec::Day {
+                day,
+                month: rest.as_ref().map(|(month, _)| *month),
+                year: rest.and_then(|(_, year)| year),
+            },
+        )(input)
+    })
+}
+
+fn month_and_year(input: Input<'_>) -> Parsed<'_, (Month, Option<Num>)> {
+    pair(month, opt(number))(input)
+}
+
+// Once expressions
+
+fn when(input: Input<'_>) -> Parsed<'_, When> {
+    map(preceded(keyword(&["tra", "fra"]), amounts), When::After)(input)
+        .or_else(|| map(preceded(keyword(&["alle"]), at), When::At)(input))
+        .or_else(|| map(preceded(keyword(&["il", "lo", "l"]), date_spec), When::On)(input))
+        .or_else(|| {
+            map(
+                preceded(keyword(&["a", "ad"]), month_and_year),
+                |(month, year)| When::In { month, year },
+            )(input)
+        })
+        .or_else(|| map(preceded(keyword(&["nel"]), number), When::Year)(input))
+        .or_else(|| map(keyword(&["domani"]), |_| When::DaysFromNow(1))(input))
+        .or_else(|| map(keyword(&["dopodomani"]), |_| When::DaysFromNow(2))(input))
+        .or_else(|| {
+            map(keyword(&["stamattina"]), |_| {
+                When::PartOfDay(PartOfDay::Morning)
+            })(input)
+        })
+        .or_else(|| {
+            map(keyword(&["stasera"]), |_| {
+                When::PartOfDay(PartOfDay::Evening)
+            })(input)
+        })
+        .or_else(|| {
+            map(preceded(keyword(NEXT), weekday), |weekday| When::Weekday {
+                weekday,
+                strictly_next: true,
+            })(input)
+        })
+        .or_else(|| map(preceded(keyword(NEXT), period), When::Next)(input))
+        .or_else(|| map(terminated(period, keyword(NEXT)), When::Next)(input))
+        .or_else(|| {
+            map(pair(weekday, opt(keyword(NEXT))), |(weekday, next)| {
+                When::Weekday {
+                    weekday,
+                    strictly_next: next.is_some(),
+                }
+            })(input)
+        })
+        .or_else(|| map(any, When::Skipped)(input))
+}
+
+fn amounts(input: Input<'_>) -> Parsed<'_, Vec<Amount>> {
+    many1(terminated(amount, opt(keyword(&["e"]))))(input)
+}
+
+fn amount(input: Input<'_>) -> Parsed<'_, Amount> {
+    map(keyword(&["mezzora"]), |_| Amount::Half(Some(Unit::Hour)))(input)
+        .or_else(|| {
+            map(
+                preceded(keyword(&["mezz", "mezza", "mezzo"]), opt(unit)),
+                Amount::Half,
+            )(input)
+        })
+        .or_else(|| {
+            map(
+                pair(
+                    terminated(
+                        quantity,
+                        pair(keyword(&["quarto", "quarti"]), opt(keyword(&["d"]))),
+                    ),
+                    opt(unit),
+                ),
+                |(quantity, unit)| Amount::Quarters { quantity, unit },
+            )(input)
+        })
+        .or_else(|| {
+            map(pair(quantity, unit), |(quantity, unit)| Amount::Units {
+                quantity,
+                unit,
+            })(input)
+        })
+}
+
+// Recurrent expressions
+
+fn rule(input: Input<'_>) -> Parsed<'_, Rule> {
+    map(preceded(keyword(&["ogni"]), every), Rule::Every)(input)
+        .or_else(|| window(input))
+        .or_else(|| limit(input))
+        .or_else(|| map(until, Rule::Until)(input))
+        .or_else(|| map(since, Rule::Since)(input))
+        .or_else(|| map(preceded(keyword(&["alle"]), many0(at)), Rule::At)(input))
+        .or_else(|| map(any, Rule::Skipped)(input))
+}
+
+fn every(input: Input<'_>) -> Parsed<'_, Vec<Every>> {
+    map(many0(every_item), |items| {
+        items.into_iter().flatten().collect()
+    })(input)
+}
+
+fn every_item(input: Input<'_>) -> Parsed<'_, Vec<Every>> {
+    if keyword(EVERY_STOP_WORDS)(input).is_some() {
+        return None;
+    }
+    map(weekdays, |item| vec![item])(input)
+        .or_else(|| map(keyword(&["di"]), |_| vec![])(input))
+        .or_else(|| map(lookup(&EACH_UNITS), |unit| vec![Every::Each(unit)])(input))
+        .or_else(|| map(list(month), |months| vec![Every::Months(months)])(input))
+        .or_else(|| {
+            map(numeric_date, |(raw, day, month, _)| {
+                vec![Every::Date { raw, day, month }]
+            })(input)
+        })
+        .or_else(|| numeric_every(input))
+        .or_else(|| map(any, |raw| vec![Every::Skipped(raw)])(input))
+}
+
+fn weekdays(input: Input<'_>) -> Parsed<'_, Every> {
+    let (positions, rest) = opt(list(lookup(&POSITIONS)))(input)?;
+    let (weekdays, rest) = opt(list(weekday))(rest)?;
+    if positions.is_none() && weekdays.is_none() {
+        return None;
+    }
+    Some((
+        Every::Weekdays {
+            positions: positions.unwrap_or_default(),
+            weekdays: weekdays.unwrap_or_default(),
+        },
+        rest,
+    ))
+}
+
+fn numeric_every(input: Input<'_>) -> Parsed<'_, Vec<Every>> {
+    let (numbers, rest) = list(satisfies(number, |n| n.value <= u8::MAX as u32))(input)?;
+    let years = map(keyword(&["anni"]), |_| {
+        Every::Years(numbers[numbers.len() - 1].clone())
+    });
+    let days_of_month = map(pair(keyword(&["del"]), keyword(&["mese"])), |_| {
+        Every::DaysOfMonth(numbers.clone())
+    });
+    let days_of = map(
+        preceded(
+            keyword(&["di"]),
+            many0(terminated(month, many0(keyword(&["e"])))),
+        ),
+        |months| Every::Days {
+            days: numbers.clone(),
+            months,
+        },
+    );
+    let days = map(list(month), |months| Every::Days {
+        days: numbers.clone(),
+        months,
+    });
+    years(rest)
+        .or_else(|| days_of_month(rest))
+        .or_else(|| days_of(rest))
+        .or_else(|| days(rest))
+        .map(|(item, rest)| (vec![item], rest))
+        .or_else(|| {
+            let skipped = numbers
+                .iter()
+                .map(|n| Every::Skipped(n.raw.clone()))
+                .collect();
+            Some((skipped, rest))
+        })
+}
+
+fn until(input: Input<'_>) -> Parsed<'_, Until> {
+    let first = bound(&["al", "all", "allo"], &["a", "ad"]);
+    let rest = many0(alt(
+        map(preceded(keyword(&["alle"]), at), Bound::At),
+        bound(&["al", "all", "allo"], &["a", "ad"]),
+    ));
+    map(
+        preceded(opt(keyword(&["fino"])), pair(first, rest)),
+        |(first, rest)| Until(std::iter::once(first).chain(rest).collect()),
+    )(input)
+}
+
+fn since(input: Input<'_>) -> Parsed<'_, Since> {
+    let first = bound(&["dal", "dallo", "dall"], &["da"]);
+    let rest = many0(bound(&["dal", "dallo", "dall"], &["da"]));
+    map(pair(first, rest), |(first, rest)| {
+        Since(std::iter::once(first).chain(rest).collect())
+    })(input)
+}
+
+fn limit(input: Input<'_>) -> Parsed<'_, Rule> {
+    let times = map(
+        terminated(quantity, keyword(&["volte", "volta"])),
+        Rule::Times,
+    );
+    let next = pair(
+        keyword(&["le", "i", "gli"]),
+        keyword(&["prossime", "prossimi"]),
+    );
+    let duration = map(preceded(opt(next), amounts), Rule::For);
+    preceded(keyword(&["per"]), alt(times, duration))(input)
+}
+
+fn window(input: Input<'_>) -> Parsed<'_, Rule> {
+    let from_to = pair(
+        preceded(keyword(&["dalle"]), at),
+        preceded(keyword(&["alle"]), at),
+    );
+    let between = pair(
+        preceded(pair(keyword(&["tra", "fra"]), keyword(&["le"])), at),
+        preceded(pair(keyword(&["e"]), keyword(&["le"])), at),
+    );
+    map(alt(from_to, between), |(from, to)| Rule::Window {
+        from,
+        to,
+    })(input)
+}
+
+fn bound<'t>(
+    to_date: &'static [&'static str],
+    to_month: &'static [&'static str],
+) -> impl Fn(Input<'t>) -> Parsed<'t, Bound> {
+    move |input| {
+        preceded(
+            keyword(to_date),
+            alt(map(year, Bound::Year), map(date_spec, Bound::Date)),
+        )(input)
+        .or_else(|| {
+            preceded(
+                keyword(to_month),
+                alt(
+                    map(weekday, Bound::Weekday),
+                    map(month_and_year, |(month, year)| Bound::Month { month, year }),
+                ),
+            )(input)
+        })
+    }
+}
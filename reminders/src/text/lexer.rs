@@ -0,0 +1,92 @@
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    Word(String),
+    Number {
+        raw: String,
+        value: u32,
+    },
+    Clock {
+        raw: String,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    },
+    Date {
+        raw: String,
+        day: u32,
+        month: u32,
+        year: Option<u32>,
+    },
+}
+
+impl Token {
+    pub fn raw(&self) -> &str {
+        match self {
+            Self::Word(raw)
+            | Self::Number { raw, .. }
+            | Self::Clock { raw, .. }
+            | Self::Date { raw, .. } => raw,
+        }
+    }
+}
+
+pub fn tokenize(words: &[&str]) -> Vec<Token> {
+    words
+        .iter()
+        .filter(|word| !word.is_empty())
+        .map(|word| classify(word))
+        .collect()
+}
+
+fn classify(word: &str) -> Token {
+    let raw = word.to_owned();
+    if let Ok(value) = word.parse::<u32>() {
+        return Token::Number { raw, value };
+    }
+    match numeric_pieces(word, &[':'][..]).as_slice() {
+        [hour, minute] => {
+            return Token::Clock {
+                raw,
+                hour: *hour,
+                minute: *minute,
+                second: 0,
+            }
+        }
+        [hour, minute, second] => {
+            return Token::Clock {
+                raw,
+                hour: *hour,
+                minute: *minute,
+                second: *second,
+            }
+        }
+        _ => (),
+    }
+    match numeric_pieces(word, &['.', '/', '-'][..]).as_slice() {
+        [day, month] => Token::Date {
+            raw,
+            day: *day,
+            month: *month,
+            year: None,
+        },
+        [day, month, year] => Token::Date {
+            raw,
+            day: *day,
+            month: *month,
+            year: Some(*year),
+        },
+        _ => Token::Word(raw),
+    }
+}
+
+fn numeric_pieces(word: &str, separators: &[char]) -> Vec<u32> {
+    let pieces = word.split(separators).collect::<Vec<_>>();
+    if pieces.len() < 2 || pieces.iter().any(|piece| piece.is_empty()) {
+        return vec![];
+    }
+    pieces
+        .iter()
+        .map(|piece| piece.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default()
+}
@@ -0,0 +1,538 @@
+use std::collections::HashSet;
+
+use chrono::{
+    DateTime, Datelike, Duration, Month, NaiveDate, NaiveTime, TimeZone, Timelike, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+
+use crate::{
+    interface::{Schedule, ScheduleGridBuilder},
+    text::{
+        ast::{
+            Amount, At, Bound, DateSpec, Every, Num, PartOfDay, Period, Rule, Since, TimeExpr,
+            Unit, Until, When,
+        },
+        diagnostics::{Conflict, Diagnostic, Field},
+    },
+};
+
+lazy_static! {
+    static ref FILLERS: HashSet<&'static str> =
+        HashSet::from(["per", "la", "le", "e", "di", "del", "della", "il", "lo", "l"]);
+}
+
+pub fn lower(expr: &TimeExpr, now: &DateTime<Utc>, tz: &Tz) -> (Schedule, Vec<Diagnostic>) {
+    let mut diagnostics = vec![];
+    let context = now.with_timezone(tz);
+    let schedule = match expr {
+        TimeExpr::Once(items) => lower_once(items, &context, &mut diagnostics),
+        TimeExpr::Recurrent(rules) => lower_recurrent(rules, &context, tz, &mut diagnostics),
+    };
+    (schedule, diagnostics)
+}
+
+fn lower_once<TZ: TimeZone>(
+    items: &[When],
+    now: &DateTime<TZ>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Schedule {
+    let mut when = now.clone();
+    for item in items {
+        when = match item {
+            When::After(amounts) => {
+                advance_time(when.clone(), amounts, diagnostics).unwrap_or(when)
+            }
+            When::At(at) => try_set_time(time_of(at, diagnostics).as_ref(), when, now),
+            When::On(date) => at_date(now, when, date, diagnostics),
+            When::In { month, year } => at_month(when, month, year.as_ref()),
+            When::Year(year) => when
+                .with_month0(0)
+                .and_then(|d| d.with_day0(0))
+                .map(|d| set_year(year.value as i32, d))
+                .unwrap_or(when),
+            When::DaysFromNow(days) => when + Duration::days(*days as i64),
+            When::PartOfDay(PartOfDay::Morning) => at_part_of_day(now, when, 9),
+            When::PartOfDay(PartOfDay::Evening) => at_part_of_day(now, when, 20),
+            When::Weekday {
+                weekday,
+                strictly_next: true,
+            } => next_weekday(*weekday, when + Duration::days(1)),
+            When::Weekday { weekday, .. } => next_weekday(*weekday, when),
+            When::Next(period) => next_period(period, when),
+            When::Skipped(token) => {
+                ignore(token, diagnostics);
+                when
+            }
+        };
+    }
+
+    Schedule::Once {
+        when: when.with_timezone(&Utc),
+    }
+}
+
+fn lower_recurrent<TZ: TimeZone>(
+    rules: &[Rule],
+    now: &DateTime<TZ>,
+    tz: &Tz,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Schedule {
+    let mut since = now.clone();
+    let mut until: Option<DateTime<TZ>> = None;
+    let mut window = None;
+    let mut count = None;
+    let mut duration: Option<&[Amount]> = None;
+    let mut every_minute = false;
+    let mut builder = ScheduleGridBuilder::new(*tz);
+    builder.with_times(vec![since.time()]);
+
+    for rule in rules {
+        match rule {
+            Rule::Every(items) => items.iter().for_each(|item| {
+                every_minute |= *item == Every::Each(Unit::Minute);
+                set_every(&mut builder, item, diagnostics)
+            }),
+            Rule::Until(Until(bounds)) => {
+                let new_until = bounds.iter().fold(since.clone(), |date, bound| {
+                    at_bound(&since, date, bound, diagnostics)
+                });
+                until = Some(new_until);
+            }
+            Rule::Since(Since(bounds)) => {
+                since = bounds.iter().fold(since.clone(), |date, bound| {
+                    at_bound(now, date, bound, diagnostics)
+                });
+            }
+            Rule::At(times) => {
+                builder.with_times(
+                    times
+                        .iter()
+                        .map_while(|at| time_of(at, diagnostics))
+                        .collect(),
+                );
+            }
+            Rule::Window { from, to } => {
+                window = time_of(from, diagnostics).zip(time_of(to, diagnostics));
+            }
+            Rule::Times(times) => count = Some(times.value),
+            Rule::For(amounts) => duration = Some(amounts),
+            Rule::Skipped(token) => ignore(token, diagnostics),
+        }
+    }
+
+    if let Some((from, to)) = window {
+        set_window(&mut builder, from, to, every_minute, diagnostics);
+    }
+    if let Some(amounts) = duration {
+        if let Some(end) = advance_time(since.clone(), amounts, diagnostics) {
+            until = Some(until.map_or(end.clone(), |until| until.min(end)));
+        }
+    }
+    builder.with_year(since.year() as u32);
+
+    match (until, count) {
+        (until, Some(remaining)) => Schedule::RecurrentCount {
+            since: since.with_timezone(&Utc),
+            until: until.map(|until| until.with_timezone(&Utc)),
+            remaining,
+            schedule: builder.build(),
+        },
+        (None, None) => Schedule::Recurrent {
+            since: since.with_timezone(&Utc),
+            schedule: builder.build(),
+        },
+        (Some(until), None) => Schedule::RecurrentUntil {
+            since: since.with_timezone(&Utc),
+            until: until.with_timezone(&Utc),
+            schedule: builder.build(),
+        },
+    }
+}
+
+fn set_every(builder: &mut ScheduleGridBuilder, item: &Every, diagnostics: &mut Vec<Diagnostic>) {
+    match item {
+        Every::Each(Unit::Minute) => {
+            builder.all_minutes();
+        }
+        Every::Each(Unit::Hour) => {
+            builder.all_hours();
+        }
+        Every::Each(Unit::Day) => {
+            builder.all_days_of_month();
+        }
+        Every::Each(Unit::Week) => {
+            builder.all_weeks();
+        }
+        Every::Each(Unit::Second) => (),
+        Every::Weekdays {
+            positions,
+            weekdays,
+        } => {
+            if !positions.is_empty() {
+                builder.with_weeks_of_month(positions.clone());
+            }
+            if !weekdays.is_empty() {
+                builder.with_weekdays(weekdays.clone());
+            }
+        }
+        Every::Days { days, months } => {
+            builder.with_days_of_month(days_in_range(days, diagnostics));
+            builder.with_months(months.clone());
+        }
+        Every::DaysOfMonth(days) => {
+            builder.with_days_of_month(days_in_range(days, diagnostics));
+        }
+        Every::Months(months) => {
+            builder.with_months(months.clone());
+        }
+        Every::Date { raw, day, month } => {
+            match (day_in_range(*day), Month::try_from(*month as u8)) {
+                (None, _) => out_of_range(raw, Field::Day, diagnostics),
+                (_, Err(_)) => out_of_range(raw, Field::Month, diagnostics),
+                (Some(day), Ok(month)) => {
+                    builder.with_days_of_month(vec![day as u8]);
+                    builder.with_months(vec![month]);
+                }
+            }
+        }
+        Every::Years(cadence) => {
+            builder.with_year_cadence(cadence.value as u8);
+        }
+        Every::Skipped(token) => ignore(token, diagnostics),
+    }
+}
+
+fn set_window(
+    builder: &mut ScheduleGridBuilder,
+    from: NaiveTime,
+    to: NaiveTime,
+    every_minute: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    // the grid crosses hours and minutes, so a minute-by-minute window must either
+    // stay within one hour or start and end on whole hours
+    if every_minute && (from.minute() > 0 || to.minute() > 0) {
+        if from.hour() == to.hour() && from.minute() < to.minute() {
+            builder.with_hours(vec![from.hour()]);
+            builder.with_minutes((from.minute()..to.minute()).collect());
+        } else {
+            diagnostics.push(Diagnostic::Conflicting(Conflict::PartialHourWindow));
+        }
+        return;
+    }
+    let span = (to.hour() + 24 - from.hour()) % 24;
+    let last_hour_fires = if every_minute {
+        to.minute() > 0
+    } else {
+        from.minute() <= to.minute()
+    };
+    let span = if last_hour_fires || span == 0 {
+        span
+    } else {
+        span - 1
+    };
+    builder.with_hours((0..=span).map(|hour| (from.hour() + hour) % 24).collect());
+    if !every_minute {
+        builder.with_minutes(vec![from.minute()]);
+    }
+}
+
+fn days_in_range(days: &[Num], diagnostics: &mut Vec<Diagnostic>) -> Vec<u8> {
+    days.iter()
+        .filter_map(|day| {
+            day_in_range(day.value).map(|day| day as u8).or_else(|| {
+                out_of_range(&day.raw, Field::Day, diagnostics);
+                None
+            })
+        })
+        .collect()
+}
+
+fn at_bound<TZ: TimeZone>(
+    lower_bound: &DateTime<TZ>,
+    date: DateTime<TZ>,
+    bound: &Bound,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> DateTime<TZ> {
+    match bound {
+        Bound::At(at) => try_set_time(time_of(at, diagnostics).as_ref(), date, lower_bound),
+        Bound::Date(spec) => at_date(lower_bound, date, spec, diagnostics),
+        Bound::Year(year) => truncated_by_day(&set_year(year.value as i32 + 1, date)),
+        Bound::Month { month, year } => at_month(date, month, year.as_ref()),
+        Bound::Weekday(weekday) => next_weekday(*weekday, date),
+    }
+}
+
+fn advance_time<TZ: TimeZone>(
+    mut when: DateTime<TZ>,
+    amounts: &[Amount],
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<DateTime<TZ>> {
+    let mut last_unit = Unit::Hour;
+    for amount in amounts {
+        let (duration, unit, token) = match amount {
+            Amount::Units { quantity, unit } => (
+                i32::try_from(quantity.value)
+                    .ok()
+                    .map(|value| unit.duration() * value),
+                *unit,
+                quantity.raw.as_str(),
+            ),
+            Amount::Half(unit) => {
+                let unit = unit.unwrap_or(last_unit);
+                (Some(unit.duration() / 2), unit, "mezza")
+            }
+            Amount::Quarters { quantity, unit } => {
+                let unit = unit.unwrap_or(Unit::Hour);
+                (
+                    i32::try_from(quantity.value)
+                        .ok()
+                        .map(|value| unit.duration() / 4 * value),
+                    unit,
+                    quantity.raw.as_str(),
+                )
+            }
+        };
+        match duration.and_then(|duration| when.clone().checked_add_signed(duration)) {
+            Some(advanced) => when = advanced,
+            None => {
+                out_of_range(token, Field::Amount, diagnostics);
+                return None;
+            }
+        }
+        last_unit = unit;
+    }
+    Some(when)
+}
+
+fn time_of(at: &At, diagnostics: &mut Vec<Diagnostic>) -> Option<NaiveTime> {
+    match at {
+        At::Clock {
+            raw,
+            hour,
+            minute,
+            second,
+        } => NaiveTime::from_hms_opt(*hour, *minute, *second).or_else(|| {
+            let field = if *hour > 23 {
+                Field::Hour
+            } else {
+                Field::Minute
+            };
+            out_of_range(raw, field, diagnostics);
+            None
+        }),
+        At::Spoken { hour, .. } if hour.value > 23 => {
+            out_of_range(&hour.raw, Field::Hour, diagnostics);
+            None
+        }
+        At::Spoken {
+            hour,
+            minute: Some(minute),
+        } if minute.value > 59 => {
+            out_of_range(&minute.raw, Field::Minute, diagnostics);
+            NaiveTime::from_hms_opt(hour.value, 0, 0)
+        }
+        At::Spoken { hour, minute } => {
+            NaiveTime::from_hms_opt(hour.value, minute.as_ref().map_or(0, |m| m.value), 0)
+        }
+    }
+}
+
+fn at_date<TZ: TimeZone>(
+    lower_bound: &DateTime<TZ>,
+    when: DateTime<TZ>,
+    date: &DateSpec,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> DateTime<TZ> {
+    let (raw, day, month, year) = match date {
+        DateSpec::Numeric {
+            raw,
+            day,
+            month,
+            year,
+        } => {
+            if day_in_range(*day).is_none() {
+                out_of_range(raw, Field::Day, diagnostics);
+                return when;
+            }
+            if Month::try_from(*month as u8).is_err() {
+                out_of_range(raw, Field::Month, diagnostics);
+                return when;
+            }
+            (raw, *day, Some(*month), year.map(|year| year as i32))
+        }
+        DateSpec::Day { day, month, year } => {
+            if day_in_range(day.value).is_none() {
+                out_of_range(&day.raw, Field::Day, diagnostics);
+                return when;
+            }
+            (
+                &day.raw,
+                day.value,
+                month.map(|m| m.number_from_month()),
+                year.as_ref().map(|y| y.value as i32),
+            )
+        }
+    };
+
+    let Some(month) = month else {
+        return try_set_day(Some(day), when, lower_bound);
+    };
+    // without a year, 29 February waits for the next leap year, at most 8 years away
+    let years = match year {
+        Some(year) => year..=year,
+        None => when.year()..=when.year() + 8,
+    };
+    let dates = years
+        .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+        .collect::<Vec<_>>();
+    if dates.is_empty() {
+        out_of_range(raw, Field::Day, diagnostics);
+        return when;
+    }
+    let mut candidates = dates.iter().filter_map(|date| set_date(date, &when));
+    match year {
+        Some(_) => candidates.next(),
+        None => candidates.find(|date| *date >= *lower_bound),
+    }
+    .unwrap_or(when)
+}
+
+fn at_month<TZ: TimeZone>(when: DateTime<TZ>, month: &Month, year: Option<&Num>) -> DateTime<TZ> {
+    when.with_month(month.number_from_month())
+        .map(|date| match year {
+            Some(year) => date.with_year(year.value as i32).unwrap_or(date),
+            None => date,
+        })
+        .unwrap_or(when)
+}
+
+fn at_part_of_day<TZ: TimeZone>(
+    lower_bound: &DateTime<TZ>,
+    when: DateTime<TZ>,
+    hour: u32,
+) -> DateTime<TZ> {
+    NaiveTime::from_hms_opt(hour, 0, 0)
+        .and_then(|time| set_time(&time, &when))
+        .filter(|date| *date >= *lower_bound)
+        .unwrap_or(when)
+}
+
+fn next_period<TZ: TimeZone>(period: &Period, when: DateTime<TZ>) -> DateTime<TZ> {
+    match period {
+        Period::Week => when + Duration::weeks(1),
+        Period::Month => next_month(&when),
+        Period::Year => next_year(&when),
+    }
+}
+
+fn ignore(token: &str, diagnostics: &mut Vec<Diagnostic>) {
+    if !FILLERS.contains(token) {
+        diagnostics.push(Diagnostic::Ignored {
+            token: token.to_owned(),
+        });
+    }
+}
+
+fn out_of_range(token: &str, field: Field, diagnostics: &mut Vec<Diagnostic>) {
+    diagnostics.push(Diagnostic::OutOfRange {
+        token: token.to_owned(),
+        field,
+    });
+}
+
+fn day_in_range(day: u32) -> Option<u32> {
+    Some(day).filter(|day| (1..=31).contains(day))
+}
+
+fn try_set_time<TZ: TimeZone>(
+    time: Option<&NaiveTime>,
+    when: DateTime<TZ>,
+    lower_bound: &DateTime<TZ>,
+) -> DateTime<TZ> {
+    time.and_then(|time| {
+        set_time(time, &when)
+            .filter(|date| *date >= *lower_bound)
+            .or_else(|| set_time(time, &(when.clone() + Duration::hours(24))))
+    })
+    .unwrap_or(when)
+}
+
+fn try_set_day<TZ: TimeZone>(
+    day: Option<u32>,
+    when: DateTime<TZ>,
+    lower_bound: &DateTime<TZ>,
+) -> DateTime<TZ> {
+    day.and_then(|day| {
+        when.with_day(day)
+            .filter(|date| *date >= *lower_bound)
+            .or_else(|| next_month(&when).with_day(day))
+    })
+    .unwrap_or(when)
+}
+
+fn next_weekday<TZ: TimeZone>(weekday: Weekday, when: DateTime<TZ>) -> DateTime<TZ> {
+    let current_weekday = when.weekday().num_days_from_monday();
+    let next_weekday = weekday.num_days_from_monday();
+    let skip_days = (next_weekday + 7 - current_weekday) % 7;
+    when + Duration::days(skip_days as i64)
+}
+
+fn next_month<TZ: TimeZone>(when: &DateTime<TZ>) -> DateTime<TZ> {
+    let (y, m0) = (when.year(), when.month0());
+    when.clone() + Duration::days(days_of_month0(y, m0))
+}
+
+fn next_year<TZ: TimeZone>(when: &DateTime<TZ>) -> DateTime<TZ> {
+    let y = when.year();
+    when.clone() + Duration::days(days_of_year(y))
+}
+
+fn set_date<TZ: TimeZone>(date: &NaiveDate, when: &DateTime<TZ>) -> Option<DateTime<TZ>> {
+    when.with_day(1)
+        .and_then(|d| d.with_year(date.year()))
+        .and_then(|d| d.with_month(date.month()))
+        .and_then(|d| d.with_day(date.day()))
+}
+
+fn set_year<TZ: TimeZone>(year: i32, when: DateTime<TZ>) -> DateTime<TZ> {
+    when.with_year(year).unwrap_or(when)
+}
+
+fn set_time<TZ: TimeZone>(time: &NaiveTime, when: &DateTime<TZ>) -> Option<DateTime<TZ>> {
+    when.with_second(time.second())
+        .and_then(|d| d.with_minute(time.minute()))
+        .and_then(|d| d.with_hour(time.hour()))
+}
+
+fn days_of_month0(year: i32, month: u32) -> i64 {
+    // Create a NaiveDate for the first day of the given month
+    let first_day_of_month = NaiveDate::from_ymd_opt(year, month + 1, 1).unwrap();
+
+    let first_day_of_next_month =
+        NaiveDate::from_ymd_opt(year + ((month as i32 + 2) / 12), (month + 1) % 12 + 1, 1).unwrap();
+
+    first_day_of_next_month
+        .signed_duration_since(first_day_of_month)
+        .num_days()
+}
+
+fn days_of_year(year: i32) -> i64 {
+    // Create a NaiveDate for the first day of the given month
+    let first_day_of_year = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+
+    let first_day_of_next_year = NaiveDate::from_ymd_opt(year + 1, 1, 1).unwrap();
+
+    first_day_of_next_year
+        .signed_duration_since(first_day_of_year)
+        .num_days()
+}
+
+fn truncated_by_day<Tz: TimeZone>(date: &DateTime<Tz>) -> DateTime<Tz> {
+    date.with_hour(0)
+        .and_then(|d| d.with_minute(0))
+        .and_then(|d| d.with_second(0))
+        .and_then(|d| d.with_nanosecond(0))
+        .unwrap_or(date.clone())
+}
@@ -1 +1,7 @@
+pub mod ast;
+pub mod cron;
+pub mod diagnostics;
+mod grammar;
+mod lexer;
+mod lowering;
 pub mod parsing;
@@ -1,798 +1,76 @@
-use std::{collections::HashMap, iter::Peekable};
-
-use chrono::{
-    DateTime, Datelike, Duration, Month, NaiveDate, NaiveTime, TimeZone, Timelike, Utc, Weekday,
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Europe;
+
+use crate::{
+    interface::Schedule,
+    text::{
+        ast::TimeExpr,
+        cron,
+        diagnostics::{Conflict, Diagnostic, ParseOutcome},
+        grammar, lexer, lowering,
+    },
 };
-use chrono_tz::{Europe, Tz};
-use lazy_static::lazy_static;
-
-use crate::interface::{Schedule, ScheduleGridBuilder};
-
-lazy_static! {
-    static ref TIME_FORMATS: Vec<String> = vec![
-        "%H:%M:%S".to_owned(),
-        "%H:%M".to_owned(),
-    ];
-    static ref DATE_FORMATS: Vec<String> = vec![
-        "%d.%m.%Y".to_owned(),
-        "%d-%m-%Y".to_owned(),
-        "%d/%m/%Y".to_owned(),
-    ];
-    static ref WEEKDAYS: HashMap<String, Weekday> = {
-        let mut lookup = HashMap::with_capacity(14);
-        lookup.insert("lunedì".to_owned(), Weekday::Mon);
-        lookup.insert("lunedi".to_owned(), Weekday::Mon);
-        lookup.insert("martedì".to_owned(), Weekday::Tue);
-        lookup.insert("martedi".to_owned(), Weekday::Tue);
-        lookup.insert("mercoledì".to_owned(), Weekday::Wed);
-        lookup.insert("mercoledi".to_owned(), Weekday::Wed);
-        lookup.insert("giovedì".to_owned(), Weekday::Thu);
-        lookup.insert("giovedi".to_owned(), Weekday::Thu);
-        lookup.insert("venerdì".to_owned(), Weekday::Fri);
-        lookup.insert("venerdi".to_owned(), Weekday::Fri);
-        lookup.insert("sabato".to_owned(), Weekday::Sat);
-        lookup.insert("domenica".to_owned(), Weekday::Sun);
-        lookup
-    };
-    static ref MONTHS: HashMap<String, Month> = {
-        let mut lookup = HashMap::with_capacity(12);
-        lookup.insert("gennaio".to_owned(), Month::January);
-        lookup.insert("febbraio".to_owned(), Month::February);
-        lookup.insert("marzo".to_owned(), Month::March);
-        lookup.insert("aprile".to_owned(), Month::April);
-        lookup.insert("maggio".to_owned(), Month::May);
-        lookup.insert("giugno".to_owned(), Month::June);
-        lookup.insert("luglio".to_owned(), Month::July);
-        lookup.insert("agosto".to_owned(), Month::August);
-        lookup.insert("settembre".to_owned(), Month::September);
-        lookup.insert("ottobre".to_owned(), Month::October);
-        lookup.insert("novembre".to_owned(), Month::November);
-        lookup.insert("dicembre".to_owned(), Month::December);
-        lookup
-    };
-    static ref DURATION_UNITS: HashMap<String, Duration> = {
-        let mut lookup = HashMap::with_capacity(10);
-        // Plural
-        lookup.insert("secondi".to_owned(), Duration::seconds(1));
-        lookup.insert("minuti".to_owned(), Duration::minutes(1));
-        lookup.insert("ore".to_owned(), Duration::hours(1));
-        lookup.insert("giorni".to_owned(), Duration::days(1));
-        lookup.insert("settimane".to_owned(), Duration::weeks(1));
-        // Singular
-        lookup.insert("secondo".to_owned(), Duration::seconds(1));
-        lookup.insert("minuto".to_owned(), Duration::minutes(1));
-        lookup.insert("ora".to_owned(), Duration::hours(1));
-        lookup.insert("giorno".to_owned(), Duration::days(1));
-        lookup.insert("settimana".to_owned(), Duration::weeks(1));
-        lookup
-    };
-    static ref POSITIONS: HashMap<String, u32> = {
-        let mut lookup = HashMap::with_capacity(5);
-        // Masculine
-        lookup.insert("primo".to_owned(), 0);
-        lookup.insert("secondo".to_owned(), 1);
-        lookup.insert("terzo".to_owned(), 2);
-        lookup.insert("quarto".to_owned(), 3);
-        lookup.insert("quinto".to_owned(), 4);
-        // Feminine
-        lookup.insert("prima".to_owned(), 0);
-        lookup.insert("seconda".to_owned(), 1);
-        lookup.insert("terza".to_owned(), 2);
-        lookup.insert("quarta".to_owned(), 3);
-        lookup.insert("quinta".to_owned(), 4);
-        lookup
-    };
-}
-
-#[allow(clippy::never_loop)]
-pub fn try_parse(tokens: Vec<&str>, now: &DateTime<Utc>) -> Option<Schedule> {
-    dispatch_category(tokens.into_iter().skip(1).peekable(), now, &Europe::Rome)
-}
-
-fn dispatch_category<'a, T: Iterator<Item = &'a str>>(
-    mut tokens: Peekable<T>,
-    now: &DateTime<Utc>,
-    tz: &Tz,
-) -> Option<Schedule> {
-    let context = now.with_timezone(tz);
-    match tokens.peek().copied() {
-        Some(x) if WEEKDAYS.contains_key(x) => build_once(tokens, &context),
-        Some("ogni") | Some("fino") | Some("dal") | Some("dall") | Some("da") => {
-            build_recurrent(tokens, &context, tz)
-        }
-        Some("alle") | Some("a") | Some("il") | Some("lo") | Some("l") | Some("nel")
-        | Some("ad") | Some("tra") | Some("domani") | Some("per") => build_once(tokens, &context),
-        _ => None,
-    }
-}
-
-fn build_recurrent<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    mut tokens: Peekable<T>,
-    now: &DateTime<TZ>,
-    tz: &Tz,
-) -> Option<Schedule> {
-    let mut since = now.clone();
-    let mut until: Option<DateTime<TZ>> = None;
-    let mut builder = ScheduleGridBuilder::new(*tz);
-    builder.with_times(vec![since.time()]);
-
-    while let Some(token) = tokens.peek().copied() {
-        match token {
-            "ogni" => {
-                tokens.next();
-                set_schedule(&mut builder, &mut tokens)
-            }
-            "al" | "all" | "allo" | "a" | "ad" => set_until(&mut until, &since, &mut tokens),
-            "dal" | "da" | "dall" => set_since(&mut since, now, &mut tokens),
-            "alle" => {
-                tokens.next();
-                set_time_schedule(&mut builder, &mut tokens)
-            }
-            _ => {
-                tokens.next();
-            }
-        };
-    }
-
-    builder.with_year(since.year() as u32);
-
-    Some(match until {
-        None => Schedule::Recurrent {
-            since: since.with_timezone(&Utc),
-            schedule: builder.build(),
-        },
-        Some(until) => Schedule::RecurrentUntil {
-            since: since.with_timezone(&Utc),
-            until: until.with_timezone(&Utc),
-            schedule: builder.build(),
-        },
-    })
-}
-
-fn set_time_schedule<'a, T: Iterator<Item = &'a str>>(
-    builder: &mut ScheduleGridBuilder,
-    tokens: &mut Peekable<T>,
-) {
-    builder.with_times(std::iter::from_fn(|| try_parse_time(tokens)).collect());
-}
-
-fn set_schedule<'a, T: Iterator<Item = &'a str>>(
-    builder: &mut ScheduleGridBuilder,
-    tokens: &mut Peekable<T>,
-) {
-    while let Some(token) = tokens.peek().copied() {
-        match token {
-            "fino" | "al" | "all" | "a" | "ad" | "dal" | "da" | "dall" | "alle" | "ogni" => return,
-            x if (POSITIONS.contains_key(x) || WEEKDAYS.contains_key(x)) => {
-                set_weekday_schedule(builder, tokens);
-            }
-            "di" => {
-                tokens.next();
-            }
-            x if (DURATION_UNITS.contains_key(x)) => {
-                set_every_unit_schedule(builder, tokens);
-            }
-            _ => {
-                set_fittest_schedule(builder, tokens);
-            }
-        };
-    }
-}
-
-fn set_every_unit_schedule<'a, T: Iterator<Item = &'a str>>(
-    builder: &mut ScheduleGridBuilder,
-    tokens: &mut Peekable<T>,
-) {
-    match tokens.peek().copied() {
-        Some("minuto") => {
-            builder.all_minutes();
-            tokens.next();
-        }
-        Some("ora") => {
-            builder.all_hours();
-            tokens.next();
-        }
-        Some("giorno") => {
-            builder.all_days_of_month();
-            tokens.next();
-        }
-        Some("settimana") => {
-            builder.all_weeks();
-            tokens.next();
-        }
-        Some("mese") => {
-            builder.all_months();
-            tokens.next();
-        }
-        Some("anno") => {
-            builder.with_year_cadence(1);
-            tokens.next();
-        }
-        Some(_) => {
-            tokens.next();
-        }
-        None => (),
-    }
-}
-
-fn set_weekday_schedule<'a, T: Iterator<Item = &'a str>>(
-    builder: &mut ScheduleGridBuilder,
-    tokens: &mut Peekable<T>,
-) {
-    let weekday_positions = std::iter::from_fn(|| {
-        let position = try_parse_position(tokens);
-        while let Some("e") = tokens.peek().copied() {
-            tokens.next();
-        }
-        position
-    })
-    .collect::<Vec<_>>();
-
-    if !weekday_positions.is_empty() {
-        builder.with_weeks_of_month(weekday_positions);
-    }
-
-    let weekdays = std::iter::from_fn(|| {
-        let weekday = try_parse_weekday(tokens).copied();
-        while let Some("e") = tokens.peek().copied() {
-            tokens.next();
-        }
-        weekday
-    })
-    .collect::<Vec<_>>();
-
-    if !weekdays.is_empty() {
-        builder.with_weekdays(weekdays);
-    }
-}
-
-fn set_fittest_schedule<'a, T: Iterator<Item = &'a str>>(
-    builder: &mut ScheduleGridBuilder,
-    tokens: &mut Peekable<T>,
-) {
-    match tokens.peek().copied() {
-        Some(x) if MONTHS.contains_key(x) => set_month_schedule(builder, tokens),
-        Some(x) if x.contains(&['.', '/', '-'][..]) => set_date_schedule(builder, tokens),
-        Some(x) if x.parse::<u32>().is_ok() => set_numeric_schedule(builder, tokens),
-        Some(_) => {
-            tokens.next();
-        }
-        None => (),
-    }
-}
-fn set_numeric_schedule<'a, T: Iterator<Item = &'a str>>(
-    builder: &mut ScheduleGridBuilder,
-    tokens: &mut Peekable<T>,
-) {
-    let numbers = std::iter::from_fn(|| {
-        let num = tokens
-            .peek()
-            .copied()
-            .and_then(|s| s.parse::<u8>().ok())
-            .inspect(|_| {
-                tokens.next();
-            });
-        while let Some("e") = tokens.peek().copied() {
-            tokens.next();
-        }
-        num
-    })
-    .collect::<Vec<_>>();
-
-    match tokens.peek().copied() {
-        Some("anni") => {
-            tokens.next();
-            builder.with_year_cadence(numbers.last().copied().unwrap_or(1u8));
-        }
-        Some("del") => {
-            tokens.next();
-            if let Some("mese") = tokens.peek().copied() {
-                tokens.next();
-                builder.with_days_of_month(numbers);
-            }
-        }
-        Some("di") => {
-            tokens.next();
-            builder.with_days_of_month(numbers);
-            set_month_schedule(builder, tokens);
-        }
-        Some(x) if MONTHS.contains_key(x) => {
-            builder.with_days_of_month(numbers);
-            set_month_schedule(builder, tokens);
-        }
-        _ => (),
-    }
-}
 
-fn set_date_schedule<'a, T: Iterator<Item = &'a str>>(
-    builder: &mut ScheduleGridBuilder,
-    tokens: &mut Peekable<T>,
-) {
-    let pair = tokens
-        .peek()
-        .copied()
-        .map(|s| {
-            s.split(&['.', '/', '-'][..])
-                .map(|piece| piece.trim_start_matches('0'))
-                .filter_map(|piece| piece.parse::<u8>().ok())
-                .collect::<Vec<_>>()
-        })
-        .inspect(|_| {
-            tokens.next();
-        })
-        .filter(|v| v.len() >= 2)
-        .map(|v| (v[0], v[1]));
-
-    if let Some((day, month)) = pair {
-        builder.with_days_of_month(vec![day]);
-        Month::try_from(month)
-            .ok()
-            .map(|m| builder.with_months(vec![m]));
-    }
-}
-
-fn set_month_schedule<'a, T: Iterator<Item = &'a str>>(
-    builder: &mut ScheduleGridBuilder,
-    tokens: &mut Peekable<T>,
-) {
-    let months = std::iter::from_fn(|| {
-        let month = try_parse_month(tokens).copied();
-        while let Some("e") = tokens.peek().copied() {
-            tokens.next();
-        }
-        month
-    })
-    .collect::<Vec<_>>();
-
-    builder.with_months(months);
-}
-
-fn set_until<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    until: &mut Option<DateTime<TZ>>,
-    since: &DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) {
-    let mut new_until = since.clone();
-    while let Some(token) = tokens.peek().copied() {
-        new_until = match token {
-            "alle" => {
-                tokens.next();
-                at_time(since, new_until, tokens)
-            }
-            "al" | "allo" | "all" => {
-                tokens.next();
-                at_date_or_year(new_until, since, tokens)
-            }
-            "a" | "ad" => {
-                tokens.next();
-                at_month_or_weekday(new_until, tokens)
-            }
-            _ => break,
-        };
-    }
-    *until = Some(new_until)
-}
-
-fn set_since<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    since: &mut DateTime<TZ>,
-    now: &DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) {
-    let mut new_since = since.clone();
-    while let Some(token) = tokens.peek().copied() {
-        new_since = match token {
-            "dalle" => {
-                tokens.next();
-                at_time(now, new_since, tokens)
-            }
-            "dal" | "dallo" | "dall" => {
-                tokens.next();
-                at_date_or_year(new_since, now, tokens)
-            }
-            "da" => {
-                tokens.next();
-                at_month_or_weekday(new_since, tokens)
-            }
-            _ => break,
-        };
-    }
-
-    *since = new_since;
-}
-
-fn at_date_or_year<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    date: DateTime<TZ>,
-    lower_bound: &DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    tokens
-        .peek()
-        .copied()
-        .and_then(|n| n.parse::<i32>().ok().filter(|year| *year > 1970))
-        .inspect(|_| {
-            tokens.next();
-        })
-        .map(|year| set_year(year + 1, date.clone()))
-        .map(|d| truncated_by_day(&d))
-        .unwrap_or_else(|| at_date(lower_bound, date, tokens))
-}
+const FAR_AWAY_DAYS: i64 = 366;
 
-fn at_month_or_weekday<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    date: DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    tokens
-        .peek()
-        .copied()
-        .and_then(|d| WEEKDAYS.get(d).copied())
-        .inspect(|_| {
-            tokens.next();
-        })
-        .map(|weekday| next_weekday(weekday, date.clone()))
-        .unwrap_or_else(|| at_month(date, tokens))
-}
-
-fn build_once<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    mut tokens: Peekable<T>,
-    now: &DateTime<TZ>,
-) -> Option<Schedule> {
-    let mut when = now.clone();
-    while let Some(token) = tokens.peek().copied() {
-        when = match token {
-            "tra" => {
-                tokens.next();
-                advance_time(when, &mut tokens)
-            }
-            "alle" => {
-                tokens.next();
-                at_time(now, when, &mut tokens)
-            }
-            "il" | "lo" | "l" => {
-                tokens.next();
-                at_date(now, when, &mut tokens)
-            }
-            "a" | "ad" => {
-                tokens.next();
-                at_month(when, &mut tokens)
-            }
-            "nel" => {
-                tokens.next();
-                at_beginning_of_year(when, &mut tokens)
-            }
-            "domani" => {
-                tokens.next();
-                when + Duration::days(1i64)
-            }
-            x if WEEKDAYS.contains_key(x) => configure_weekday(when, &mut tokens),
-            _ => {
-                tokens.next();
-                when
-            }
-        };
+pub fn try_parse(tokens: Vec<&str>, now: &DateTime<Utc>) -> ParseOutcome {
+    if tokens.get(1) == Some(&"cron") {
+        return try_parse_cron(&tokens[2..].join(" "), now);
     }
-    let schedule = Schedule::Once {
-        when: when.with_timezone(&Utc),
+    let expr = match explain(tokens) {
+        Some(expr) => expr,
+        None => return ParseOutcome::new(None, vec![]),
     };
-
-    Some(schedule)
+    let (schedule, mut diagnostics) = lowering::lower(&expr, now, &Europe::Rome);
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        diagnostics.extend(find_conflict(&schedule, now).or_else(|| find_far_away(&schedule, now)));
+    }
+    ParseOutcome::new(Some(schedule), diagnostics)
 }
 
-fn advance_time<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    when: DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    tokens
-        .peek()
-        .and_then(|s| s.parse::<i32>().ok())
-        .and_then(|quantity| {
-            tokens.next();
-            tokens
-                .peek()
-                .and_then(|unit| DURATION_UNITS.get(*unit))
-                .copied()
-                .map(|dur| {
-                    tokens.next();
-                    dur * quantity
-                })
-        })
-        .map(|duration| when.clone() + duration)
-        .map(|d| {
-            if let Some("e") = tokens.peek().copied() {
-                tokens.next();
+fn try_parse_cron(expression: &str, now: &DateTime<Utc>) -> ParseOutcome {
+    match cron::parse(expression, &Europe::Rome) {
+        Ok(schedule) => {
+            let schedule = Schedule::Recurrent {
+                since: *now,
+                schedule,
             };
-            advance_time(d, tokens)
-        })
-        .unwrap_or(when)
-}
-
-fn configure_weekday<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    when: DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    try_parse_weekday(tokens)
-        .map(|weekday| next_weekday(*weekday, when.clone()))
-        .unwrap_or(when)
-}
-
-fn at_date<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    lower_bound: &DateTime<TZ>,
-    when: DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    if let Some(d) = parse_date(tokens).and_then(|date| {
-        when.with_year(date.year_ce().1 as i32)
-            .and_then(|d| d.with_month0(date.month0()))
-            .and_then(|d| d.with_day0(date.day0()))
-    }) {
-        return d;
-    }
-
-    let day = match try_parse_day(tokens) {
-        None => return when,
-        day => day,
-    };
-
-    let month = match try_parse_month(tokens).map(|m| m.number_from_month()) {
-        None => return try_set_day(day, when, lower_bound),
-        month => month,
-    };
-
-    match try_parse_year(tokens) {
-        None => try_set_month(month, when.clone(), lower_bound)
-            .with_day(1)
-            .map(|d| try_set_day(day, d, lower_bound))
-            .unwrap_or(when),
-        Some(year) => set_year(year, when.clone())
-            .with_month(1)
-            .map(|d| try_set_month(month, d, lower_bound))
-            .and_then(|d| d.with_day(1))
-            .map(|d| try_set_day(day, d, lower_bound))
-            .unwrap_or(when.clone()),
+            let diagnostics = find_conflict(&schedule, now)
+                .or_else(|| find_far_away(&schedule, now))
+                .into_iter()
+                .collect();
+            ParseOutcome::new(Some(schedule), diagnostics)
+        }
+        Err(reason) => ParseOutcome::new(None, vec![Diagnostic::InvalidCron { reason }]),
     }
 }
 
-fn at_month<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    when: DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    try_parse_month(tokens)
-        .and_then(|month| when.with_month(month.number_from_month()))
-        .map(|date| at_year(date, tokens))
-        .unwrap_or(when)
-}
-
-fn at_beginning_of_year<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    when: DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    try_parse_year(tokens)
-        .and_then(|year| {
-            when.with_month0(0)
-                .and_then(|d| d.with_day0(0))
-                .map(|d| set_year(year, d))
-        })
-        .unwrap_or(when)
-}
-
-fn at_time<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    lower_bound: &DateTime<TZ>,
-    when: DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    try_set_time(try_parse_time(tokens).as_ref(), when, lower_bound)
-}
-
-fn at_year<'a, TZ: TimeZone, T: Iterator<Item = &'a str>>(
-    when: DateTime<TZ>,
-    tokens: &mut Peekable<T>,
-) -> DateTime<TZ> {
-    try_parse_year(tokens)
-        .and_then(|year| when.with_year(year))
-        .unwrap_or(when)
-}
-
-fn try_set_time<TZ: TimeZone>(
-    time: Option<&NaiveTime>,
-    when: DateTime<TZ>,
-    lower_bound: &DateTime<TZ>,
-) -> DateTime<TZ> {
-    time.and_then(|time| {
-        set_time(time, &when)
-            .filter(|date| *date >= *lower_bound)
-            .or_else(|| set_time(time, &(when.clone() + Duration::hours(24))))
-    })
-    .unwrap_or(when)
-}
-
-fn try_set_day<TZ: TimeZone>(
-    day: Option<u32>,
-    when: DateTime<TZ>,
-    lower_bound: &DateTime<TZ>,
-) -> DateTime<TZ> {
-    day.and_then(|day| {
-        when.with_day(day)
-            .filter(|date| *date >= *lower_bound)
-            .or_else(|| next_month(&when).with_day(day))
-    })
-    .unwrap_or(when)
-}
-
-fn try_set_month<TZ: TimeZone>(
-    month: Option<u32>,
-    when: DateTime<TZ>,
-    lower_bound: &DateTime<TZ>,
-) -> DateTime<TZ> {
-    month
-        .and_then(|month| {
-            when.with_month(month)
-                .filter(|date| *date >= *lower_bound)
-                .or_else(|| next_year(&when).with_month(month))
-        })
-        .unwrap_or(when)
-}
-
-fn next_weekday<TZ: TimeZone>(weekday: Weekday, when: DateTime<TZ>) -> DateTime<TZ> {
-    let current_weekday = when.weekday().num_days_from_monday();
-    let next_weekday = weekday.num_days_from_monday();
-    let skip_days = (next_weekday - current_weekday + 7) % 7;
-    when + Duration::days(skip_days as i64)
-}
-
-fn next_month<TZ: TimeZone>(when: &DateTime<TZ>) -> DateTime<TZ> {
-    let (y, m0) = (when.year(), when.month0());
-    when.clone() + Duration::days(days_of_month0(y, m0))
-}
-
-fn next_year<TZ: TimeZone>(when: &DateTime<TZ>) -> DateTime<TZ> {
-    let y = when.year();
-    when.clone() + Duration::days(days_of_year(y))
-}
-
-fn set_year<TZ: TimeZone>(year: i32, when: DateTime<TZ>) -> DateTime<TZ> {
-    when.with_year(year).unwrap_or(when)
-}
-
-fn try_parse_year<'a, T: Iterator<Item = &'a str>>(tokens: &mut Peekable<T>) -> Option<i32> {
-    tokens
-        .peek()
-        .and_then(|s| s.parse::<i32>().ok())
-        .inspect(|_| {
-            tokens.next();
-        })
-}
-
-fn try_parse_month<'a, T: Iterator<Item = &'a str>>(tokens: &mut Peekable<T>) -> Option<&Month> {
-    tokens.peek().and_then(|s| MONTHS.get(*s)).inspect(|_| {
-        tokens.next();
-    })
-}
-
-fn try_parse_weekday<'a, T: Iterator<Item = &'a str>>(
-    tokens: &mut Peekable<T>,
-) -> Option<&Weekday> {
-    tokens.peek().and_then(|s| WEEKDAYS.get(*s)).inspect(|_| {
-        tokens.next();
-    })
-}
-
-fn try_parse_position<'a, T: Iterator<Item = &'a str>>(tokens: &mut Peekable<T>) -> Option<u32> {
-    tokens
-        .peek()
-        .and_then(|s| POSITIONS.get(*s))
-        .inspect(|_| {
-            tokens.next();
-        })
-        .copied()
-}
-
-fn try_parse_day<'a, T: Iterator<Item = &'a str>>(tokens: &mut Peekable<T>) -> Option<u32> {
-    tokens
-        .peek()
-        .and_then(|s| s.parse::<u32>().ok())
-        .filter(|day| *day < 32)
-        .inspect(|_| {
-            tokens.next();
-        })
-}
-
-fn set_time<TZ: TimeZone>(time: &NaiveTime, when: &DateTime<TZ>) -> Option<DateTime<TZ>> {
-    when.with_second(time.second())
-        .and_then(|d| d.with_minute(time.minute()))
-        .and_then(|d| d.with_hour(time.hour()))
+pub fn explain(tokens: Vec<&str>) -> Option<TimeExpr> {
+    let tokens = tokens.into_iter().skip(1).collect::<Vec<_>>();
+    grammar::parse(&lexer::tokenize(&tokens))
 }
 
-fn try_parse_time<'a, T: Iterator<Item = &'a str>>(tokens: &mut Peekable<T>) -> Option<NaiveTime> {
-    parse_time(tokens).or_else(|| custom_parse_time(tokens))
+fn find_far_away(schedule: &Schedule, now: &DateTime<Utc>) -> Option<Diagnostic> {
+    schedule
+        .next_tick(now)
+        .filter(|first| *first - *now > Duration::days(FAR_AWAY_DAYS))
+        .map(|first| Diagnostic::FarAway { first })
 }
 
-fn custom_parse_time<'a, T: Iterator<Item = &'a str>>(
-    tokens: &mut Peekable<T>,
-) -> Option<NaiveTime> {
-    let parsed_token = tokens
-        .peek()
-        .and_then(|h| h.parse::<u32>().ok())
-        .inspect(|_| {
-            tokens.next();
-        });
-
-    let hour = match parsed_token {
-        None => return None,
-        Some(h) => h,
+fn find_conflict(schedule: &Schedule, now: &DateTime<Utc>) -> Option<Diagnostic> {
+    let conflict = match schedule {
+        Schedule::Once { when } if when < now => Conflict::InThePast,
+        Schedule::Once { .. } => return None,
+        Schedule::RecurrentUntil { since, until, .. }
+        | Schedule::RecurrentCount {
+            since,
+            until: Some(until),
+            ..
+        } if until <= since => Conflict::UntilBeforeSince,
+        schedule if schedule.next_tick(now).is_none() => Conflict::NeverFires,
+        _ => return None,
     };
-
-    tokens
-        .peek()
-        .copied()
-        .filter(|e| *e == "e")
-        .and_then(|_| {
-            tokens.next();
-            tokens.peek().copied()
-        })
-        .and_then(|m| m.parse::<u32>().ok())
-        .inspect(|_| {
-            tokens.next();
-        })
-        .and_then(|minute| NaiveTime::from_hms_opt(hour, minute, 0))
-        .or_else(|| NaiveTime::from_hms_opt(hour, 0, 0))
-}
-
-fn parse_date<'a, T: Iterator<Item = &'a str>>(tokens: &mut Peekable<T>) -> Option<NaiveDate> {
-    tokens
-        .peek()
-        .and_then(|date| {
-            DATE_FORMATS
-                .iter()
-                .filter_map(|fmt| NaiveDate::parse_from_str(date, fmt).ok())
-                .next()
-        })
-        .inspect(|_| {
-            tokens.next();
-        })
-}
-
-fn parse_time<'a, T: Iterator<Item = &'a str>>(tokens: &mut Peekable<T>) -> Option<NaiveTime> {
-    tokens
-        .peek()
-        .and_then(|time| {
-            TIME_FORMATS
-                .iter()
-                .filter_map(|fmt| NaiveTime::parse_from_str(time, fmt).ok())
-                .next()
-        })
-        .inspect(|_| {
-            tokens.next();
-        })
-}
-
-fn days_of_month0(year: i32, month: u32) -> i64 {
-    // Create a NaiveDate for the first day of the given month
-    let first_day_of_month = NaiveDate::from_ymd_opt(year, month + 1, 1).unwrap();
-
-    let first_day_of_next_month =
-        NaiveDate::from_ymd_opt(year + ((month as i32 + 2) / 12), (month + 1) % 12 + 1, 1).unwrap();
-
-    first_day_of_next_month
-        .signed_duration_since(first_day_of_month)
-        .num_days()
-}
-
-fn days_of_year(year: i32) -> i64 {
-    // Create a NaiveDate for the first day of the given month
-    let first_day_of_year = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
-
-    let first_day_of_next_year = NaiveDate::from_ymd_opt(year + 1, 1, 1).unwrap();
-
-    first_day_of_next_year
-        .signed_duration_since(first_day_of_year)
-        .num_days()
-}
-
-fn truncated_by_day<Tz: TimeZone>(date: &DateTime<Tz>) -> DateTime<Tz> {
-    date.with_hour(0)
-        .and_then(|d| d.with_minute(0))
-        .and_then(|d| d.with_second(0))
-        .and_then(|d| d.with_nanosecond(0))
-        .unwrap_or(date.clone())
+    Some(Diagnostic::Conflicting(conflict))
 }
 
 #[cfg(test)]
@@ -802,6 +80,16 @@ mod once_tests {
     use chrono::{DateTime, Utc};
     use ntest::timeout;
 
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_il_29_febbraio() {
+        assert_schedule_once(
+            "Ricordami il 29 febbraio",
+            "2025-03-10T10:00:00+01:00",
+            "2028-02-29T10:00:00+01:00",
+        );
+    }
+
     #[test]
     #[timeout(50)]
     fn test_ricordami_il_18() {
@@ -942,82 +230,243 @@ mod once_tests {
         );
     }
 
-    fn assert_schedule_once(msg: &str, date_str: &str, expected_when_str: &str) {
-        assert_eq_schedule(
-            msg,
-            try_parse(
-                msg.split(' ').collect(),
-                &date_str.parse::<DateTime<Utc>>().unwrap(),
-            ),
-            Some(Schedule::Once {
-                when: expected_when_str.parse::<DateTime<Utc>>().unwrap(),
-            }),
-        )
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_dopodomani() {
+        assert_schedule_once(
+            "Ricordami dopodomani",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-19T20:58:00+02:00",
+        );
     }
 
-    fn assert_eq_schedule(msg: &str, result: Option<Schedule>, expected: Option<Schedule>) {
-        assert_eq!(expected, result, "When parsing \"{msg}\"");
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_fra_2_ore() {
+        assert_schedule_once(
+            "Ricordami fra 2 ore",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T22:58:00+02:00",
+        );
     }
-}
-
-#[cfg(test)]
-mod recurrent_tests {
-    use super::try_parse;
-    use crate::{interface::Schedule, schedule::ScheduleGrid};
-    use chrono::{DateTime, Duration, Utc};
-    use chrono_tz::Europe;
-    use ntest::timeout;
 
     #[test]
     #[timeout(50)]
-    fn ricordami_ogni_2_anni() {
-        assert_schedule_recurrent(
-            "Ricordami ogni 2 anni",
-            "2024-08-17T20:58:00+02:00",
+    fn test_ricordami_tra_due_ore_e_mezza() {
+        assert_schedule_once(
+            "Ricordami tra due ore e mezza",
             "2024-08-17T20:58:00+02:00",
-            |_, grid, _| {
-                assert_eq!(2u8, grid.year_cadence.get());
-            },
+            "2024-08-17T23:28:00+02:00",
         );
     }
 
     #[test]
     #[timeout(50)]
-    fn ricordami_ogni_2_anni_ogni_primo_sabato_di_gennaio_dal_1_1_2025() {
-        assert_schedule_recurrent(
-            "Ricordami ogni 2 anni ogni primo sabato di gennaio dal 1/1/2025",
+    fn test_ricordami_tra_mezz_ora() {
+        assert_schedule_once(
+            "Ricordami tra mezz ora",
             "2024-08-17T20:58:00+02:00",
-            "2025-01-01T20:58:00+01:00",
-            |schedule, grid, now| {
-                let result = schedule
-                    .next_tick(now)
-                    .unwrap()
-                    .with_timezone(&Europe::Rome)
-                    .to_rfc3339();
-
-                assert_eq!(2u8, grid.year_cadence.get());
-                assert_eq!(2025u32, grid.year_start);
-                assert_eq!(
-                    "2025-01-04T20:58:00+01:00", result,
-                    "Next schedule available"
-                );
-            },
+            "2024-08-17T21:28:00+02:00",
         );
     }
 
     #[test]
     #[timeout(50)]
-    fn ricordami_ogni_primo_e_terzo_giovedì() {
-        assert_schedule_recurrent(
-            "Ricordami ogni primo e terzo giovedì alle 00:00",
-            "2024-08-17T20:58:00+02:00",
+    fn test_ricordami_tra_un_quarto_d_ora() {
+        assert_schedule_once(
+            "Ricordami tra un quarto d ora",
             "2024-08-17T20:58:00+02:00",
-            |schedule, grid, now| {
-                let result = schedule
-                    .next_tick(now)
-                    .unwrap()
-                    .with_timezone(&Europe::Rome)
-                    .to_rfc3339();
+            "2024-08-17T21:13:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_fra_tre_quarti_d_ora() {
+        assert_schedule_once(
+            "Ricordami fra tre quarti d ora",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T21:43:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_fra_una_settimana() {
+        assert_schedule_once(
+            "Ricordami fra una settimana",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-24T20:58:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_tra_due_giorni_e_mezzo() {
+        assert_schedule_once(
+            "Ricordami tra due giorni e mezzo",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-20T08:58:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_stasera() {
+        assert_schedule_once(
+            "Ricordami stasera",
+            "2024-08-17T15:30:00+02:00",
+            "2024-08-17T20:00:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_stasera_alle_22() {
+        assert_schedule_once(
+            "Ricordami stasera alle 22",
+            "2024-08-17T15:30:00+02:00",
+            "2024-08-17T22:00:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_stamattina() {
+        assert_schedule_once(
+            "Ricordami stamattina",
+            "2024-08-17T07:30:00+02:00",
+            "2024-08-17T09:00:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_lunedì_prossimo() {
+        assert_schedule_once(
+            "Ricordami lunedì prossimo",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-19T20:58:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_lunedì_prossimo_di_lunedì() {
+        assert_schedule_once(
+            "Ricordami lunedì prossimo",
+            "2024-08-19T20:58:00+02:00",
+            "2024-08-26T20:58:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_prossimo_venerdì_alle_10() {
+        assert_schedule_once(
+            "Ricordami prossimo venerdì alle 10",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-23T10:00:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_la_settimana_prossima() {
+        assert_schedule_once(
+            "Ricordami la settimana prossima",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-24T20:58:00+02:00",
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn test_ricordami_il_mese_prossimo() {
+        assert_schedule_once(
+            "Ricordami il mese prossimo",
+            "2024-08-17T20:58:00+02:00",
+            "2024-09-17T20:58:00+02:00",
+        );
+    }
+
+    fn assert_schedule_once(msg: &str, date_str: &str, expected_when_str: &str) {
+        assert_eq_schedule(
+            msg,
+            try_parse(
+                msg.split(' ').collect(),
+                &date_str.parse::<DateTime<Utc>>().unwrap(),
+            )
+            .into_schedule(),
+            Some(Schedule::Once {
+                when: expected_when_str.parse::<DateTime<Utc>>().unwrap(),
+            }),
+        )
+    }
+
+    fn assert_eq_schedule(msg: &str, result: Option<Schedule>, expected: Option<Schedule>) {
+        assert_eq!(expected, result, "When parsing \"{msg}\"");
+    }
+}
+
+#[cfg(test)]
+mod recurrent_tests {
+    use super::try_parse;
+    use crate::{interface::Schedule, schedule::ScheduleGrid};
+    use chrono::{DateTime, Duration, Utc};
+    use chrono_tz::Europe;
+    use ntest::timeout;
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_2_anni() {
+        assert_schedule_recurrent(
+            "Ricordami ogni 2 anni",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T20:58:00+02:00",
+            |_, grid, _| {
+                assert_eq!(2u8, grid.year_cadence.get());
+            },
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_2_anni_ogni_primo_sabato_di_gennaio_dal_1_1_2025() {
+        assert_schedule_recurrent(
+            "Ricordami ogni 2 anni ogni primo sabato di gennaio dal 1/1/2025",
+            "2024-08-17T20:58:00+02:00",
+            "2025-01-01T20:58:00+01:00",
+            |schedule, grid, now| {
+                let result = schedule
+                    .next_tick(now)
+                    .unwrap()
+                    .with_timezone(&Europe::Rome)
+                    .to_rfc3339();
+
+                assert_eq!(2u8, grid.year_cadence.get());
+                assert_eq!(2025u32, grid.year_start);
+                assert_eq!(
+                    "2025-01-04T20:58:00+01:00", result,
+                    "Next schedule available"
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_primo_e_terzo_giovedì() {
+        assert_schedule_recurrent(
+            "Ricordami ogni primo e terzo giovedì alle 00:00",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T20:58:00+02:00",
+            |schedule, grid, now| {
+                let result = schedule
+                    .next_tick(now)
+                    .unwrap()
+                    .with_timezone(&Europe::Rome)
+                    .to_rfc3339();
 
                 assert!(grid.weeks_of_month.get(0usize), "Expecting first week set");
                 assert!(grid.weeks_of_month.get(2usize), "Expecting third week set");
@@ -1269,6 +718,367 @@ mod recurrent_tests {
         );
     }
 
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_lunedi_dalle_9_alle_12_ogni_ora() {
+        assert_schedule_recurrent(
+            "Ricordami ogni lunedì dalle 9 alle 12 ogni ora",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T20:58:00+02:00",
+            |schedule, grid, now| {
+                for hour in 9..=12 {
+                    assert!(grid.hours.get(hour as usize), "Expecting hour {hour}");
+                }
+                assert!(!grid.hours.get(8usize), "Not expecting hour 8");
+                assert!(!grid.hours.get(13usize), "Not expecting hour 13");
+                assert!(grid.minutes.get(0usize), "Expecting minute 0");
+                assert!(!grid.minutes.get(58usize), "Not expecting minute 58");
+
+                let first = schedule.next_tick(now).unwrap();
+                let second = schedule.next_tick(&first).unwrap();
+                assert_eq!(
+                    "2024-08-19T09:00:00+02:00",
+                    first.with_timezone(&Europe::Rome).to_rfc3339(),
+                    "First schedule available"
+                );
+                assert_eq!(
+                    "2024-08-19T10:00:00+02:00",
+                    second.with_timezone(&Europe::Rome).to_rfc3339(),
+                    "Second schedule available"
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_tra_le_8_e_le_10_ogni_sabato_di_dicembre() {
+        assert_schedule_recurrent(
+            "Ricordami tra le 8 e le 10 ogni sabato di dicembre",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T20:58:00+02:00",
+            |schedule, grid, now| {
+                let result = schedule
+                    .next_tick(now)
+                    .unwrap()
+                    .with_timezone(&Europe::Rome)
+                    .to_rfc3339();
+
+                assert!(grid.hours.get(8usize), "Expecting hour 8");
+                assert!(grid.hours.get(10usize), "Expecting hour 10");
+                assert!(!grid.hours.get(11usize), "Not expecting hour 11");
+                assert!(grid.months_of_year.get(11usize), "Expecting December");
+                assert!(!grid.months_of_year.get(7usize), "Not expecting August");
+                assert_eq!(
+                    "2024-12-07T08:00:00+01:00", result,
+                    "Next schedule available"
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_ora_dalle_22_alle_2_wraps_midnight() {
+        assert_schedule_recurrent(
+            "Ricordami ogni ora dalle 22 alle 2",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T20:58:00+02:00",
+            |_, grid, _| {
+                for hour in [22, 23, 0, 1, 2] {
+                    assert!(grid.hours.get(hour as usize), "Expecting hour {hour}");
+                }
+                for hour in 3..22 {
+                    assert!(!grid.hours.get(hour as usize), "Not expecting hour {hour}");
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_minuto_dalle_9_alle_10_stops_at_10() {
+        assert_schedule_recurrent(
+            "Ricordami ogni minuto dalle 9 alle 10",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T20:58:00+02:00",
+            |_, grid, _| {
+                assert!(grid.hours.get(9usize), "Expecting hour 9");
+                assert!(!grid.hours.get(10usize), "Not expecting hour 10");
+                assert!(grid.minutes.get(30usize), "Expecting minute 30");
+            },
+        );
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn ricordami_dal_19_al_20_agosto_ogni_minuto_dalle_9_e_15_alle_9_e_20() {
+        assert_schedule_recurrent_until_sequence(
+            "Ricordami dal 19 agosto al 20 agosto ogni minuto dalle 9 e 15 alle 9 e 20",
+            "2024-08-17T20:58:00+02:00",
+            &[
+                "2024-08-20T09:15:00+02:00",
+                "2024-08-20T09:16:00+02:00",
+                "2024-08-20T09:17:00+02:00",
+                "2024-08-20T09:18:00+02:00",
+                "2024-08-20T09:19:00+02:00",
+            ],
+        );
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn ricordami_dal_13_novembre_al_20_novembre_ogni_venerdi_dalle_9_e_30_alle_11_e_30() {
+        assert_schedule_recurrent_until_sequence(
+            "Ricordami dal 13 novembre al 20 novembre ogni venerdì dalle 9 e 30 alle 11 e 30",
+            "2024-08-17T20:58:00+02:00",
+            &[
+                "2024-11-15T09:30:00+01:00",
+                "2024-11-15T10:30:00+01:00",
+                "2024-11-15T11:30:00+01:00",
+            ],
+        );
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn ricordami_ogni_giorno_alle_9_per_3_volte() {
+        let now = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        let mut schedule = try_parse(
+            "Ricordami ogni giorno alle 9 per 3 volte"
+                .split(' ')
+                .collect(),
+            &now,
+        )
+        .into_schedule()
+        .unwrap();
+        assert_eq!(Some(3), schedule.remaining(), "Expecting 3 occurrences");
+
+        let mut current = now;
+        let dates = std::iter::from_fn(|| {
+            let next = schedule.next_tick(&current)?;
+            schedule.consume_occurrence();
+            current = next;
+            Some(next.with_timezone(&Europe::Rome).to_rfc3339())
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "2024-08-18T09:00:00+02:00",
+                "2024-08-19T09:00:00+02:00",
+                "2024-08-20T09:00:00+02:00",
+            ],
+            dates
+        );
+        assert_eq!(Some(0), schedule.remaining(), "Expecting no occurrences");
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn occurrences_after_honour_remaining_count() {
+        let now = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        let schedule = try_parse(
+            "Ricordami ogni giorno alle 9 per 3 volte"
+                .split(' ')
+                .collect(),
+            &now,
+        )
+        .into_schedule()
+        .unwrap();
+        assert_eq!(3, schedule.occurrences_after(&now).count());
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn occurrences_after_stop_at_until() {
+        let now = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        let schedule = try_parse(
+            "Ricordami ogni giorno alle 9 fino al 21/8/2024"
+                .split(' ')
+                .collect(),
+            &now,
+        )
+        .into_schedule()
+        .unwrap();
+        let mut current = now;
+        let chained =
+            std::iter::from_fn(|| schedule.next_tick(&current).inspect(|next| current = *next))
+                .collect::<Vec<_>>();
+        assert_eq!(
+            chained,
+            schedule.occurrences_after(&now).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                "2024-08-18T09:00:00+02:00",
+                "2024-08-19T09:00:00+02:00",
+                "2024-08-20T09:00:00+02:00",
+                "2024-08-21T09:00:00+02:00",
+            ],
+            chained
+                .iter()
+                .map(|d| d.with_timezone(&Europe::Rome).to_rfc3339())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn occurrences_between_include_start_and_exclude_end() {
+        let now = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        let schedule = try_parse("Ricordami ogni giorno alle 9".split(' ').collect(), &now)
+            .into_schedule()
+            .unwrap();
+        let from = "2024-08-20T09:00:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        let to = "2024-08-23T09:00:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                "2024-08-20T09:00:00+02:00",
+                "2024-08-21T09:00:00+02:00",
+                "2024-08-22T09:00:00+02:00",
+            ],
+            schedule
+                .occurrences_between(&from, &to)
+                .map(|d| d.with_timezone(&Europe::Rome).to_rfc3339())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    #[timeout(2000)]
+    fn occurrences_between_every_minute_across_dst_days() {
+        let now = "2025-01-01T00:00:00+01:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        let schedule = try_parse("Ricordami cron * * * * *".split(' ').collect(), &now)
+            .into_schedule()
+            .unwrap();
+        let day = |from: &str, to: &str| {
+            schedule
+                .occurrences_between(
+                    &from.parse::<DateTime<Utc>>().unwrap(),
+                    &to.parse::<DateTime<Utc>>().unwrap(),
+                )
+                .count()
+        };
+        assert_eq!(
+            1440 - 60,
+            day("2025-03-30T00:00:00+01:00", "2025-03-31T00:00:00+02:00")
+        );
+        assert_eq!(
+            1440,
+            day("2025-10-26T00:00:00+02:00", "2025-10-27T00:00:00+01:00")
+        );
+    }
+
+    #[test]
+    #[timeout(1000)]
+    fn shortest_interval_between_occurrences() {
+        let now = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        let interval = |text: &str| {
+            try_parse(text.split(' ').collect(), &now)
+                .into_schedule()
+                .unwrap()
+                .shortest_interval(&now)
+        };
+        // the day DST starts is 23 hours long
+        assert_eq!(
+            Some(Duration::hours(23)),
+            interval("Ricordami ogni giorno alle 9")
+        );
+        assert_eq!(
+            Some(Duration::minutes(15)),
+            interval("Ricordami cron */15 * * * *")
+        );
+        assert_eq!(
+            Some(Duration::minutes(1)),
+            interval("Ricordami cron 0,1 9 * * *")
+        );
+        assert_eq!(None, interval("Ricordami domani alle 9"));
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn ricordami_per_2_volte_ogni_venerdi_alle_18_fino_al_25_agosto() {
+        let now = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        match try_parse(
+            "Ricordami per 2 volte ogni venerdì alle 18 fino al 25 agosto"
+                .split(' ')
+                .collect(),
+            &now,
+        )
+        .schedule()
+        {
+            Some(schedule @ Schedule::RecurrentCount { until, .. }) => {
+                assert_eq!(Some(2), schedule.remaining(), "Expecting 2 occurrences");
+                assert_eq!(
+                    Some(
+                        "2024-08-25T20:58:00+02:00"
+                            .parse::<DateTime<Utc>>()
+                            .unwrap()
+                    ),
+                    *until,
+                    "Expecting until"
+                );
+                let first = schedule.next_tick(&now).unwrap();
+                assert_eq!(None, schedule.next_tick(&first), "Until comes first");
+            }
+            _ => panic!("Expecting RecurrentCount"),
+        }
+    }
+
+    #[test]
+    #[timeout(100)]
+    fn ricordami_ogni_lunedi_alle_8_per_le_prossime_3_settimane() {
+        assert_schedule_recurrent_until_sequence(
+            "Ricordami ogni lunedì alle 8 per le prossime 3 settimane",
+            "2024-08-17T20:58:00+02:00",
+            &[
+                "2024-08-19T08:00:00+02:00",
+                "2024-08-26T08:00:00+02:00",
+                "2024-09-02T08:00:00+02:00",
+            ],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_per_i_prossimi_10_giorni_alle_21() {
+        assert_schedule_recurrent_until(
+            "Ricordami per i prossimi 10 giorni alle 21",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-17T20:58:00+02:00",
+            "2024-08-27T20:58:00+02:00",
+            |schedule, _, now| {
+                let result = schedule
+                    .next_tick(now)
+                    .unwrap()
+                    .with_timezone(&Europe::Rome)
+                    .to_rfc3339();
+                assert_eq!(
+                    "2024-08-17T21:00:00+02:00", result,
+                    "Next schedule available"
+                );
+            },
+        );
+    }
+
     fn assert_schedule_recurrent<F>(
         msg: &str,
         date_str: &str,
@@ -1278,7 +1088,7 @@ mod recurrent_tests {
         F: FnOnce(&Schedule, &ScheduleGrid, &DateTime<Utc>),
     {
         let now = &date_str.parse::<DateTime<Utc>>().unwrap();
-        match try_parse(msg.split(' ').collect(), now).as_ref() {
+        match try_parse(msg.split(' ').collect(), now).schedule() {
             Some(
                 schedule @ Schedule::Recurrent {
                     since,
@@ -1310,7 +1120,7 @@ mod recurrent_tests {
         F: FnOnce(&Schedule, &ScheduleGrid, &DateTime<Utc>),
     {
         let now = &date_str.parse::<DateTime<Utc>>().unwrap();
-        match try_parse(msg.split(' ').collect(), now).as_ref() {
+        match try_parse(msg.split(' ').collect(), now).schedule() {
             Some(
                 schedule @ Schedule::RecurrentUntil {
                     since,
@@ -1346,7 +1156,7 @@ mod recurrent_tests {
         expected_sequence: &[&str],
     ) {
         let now = &date_str.parse::<DateTime<Utc>>().unwrap();
-        match try_parse(msg.split(' ').collect(), now) {
+        match try_parse(msg.split(' ').collect(), now).into_schedule() {
             Some(schedule @ Schedule::RecurrentUntil { .. }) => {
                 let mut current = *now;
                 let dates = std::iter::from_fn(|| {
@@ -1368,3 +1178,491 @@ mod recurrent_tests {
         }
     }
 }
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use super::try_parse;
+    use crate::text::diagnostics::{Conflict, Diagnostic, Field};
+    use chrono::{DateTime, Utc};
+    use ntest::timeout;
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_lunedi_alle_25_is_out_of_range() {
+        assert_diagnostics(
+            "Ricordami ogni lunedi alle 25",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::OutOfRange {
+                token: "25".to_owned(),
+                field: Field::Hour,
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_alle_10_e_75_is_out_of_range() {
+        assert_diagnostics(
+            "Ricordami alle 10:75",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::OutOfRange {
+                token: "10:75".to_owned(),
+                field: Field::Minute,
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_il_32_is_out_of_range() {
+        assert_diagnostics(
+            "Ricordami il 32",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::OutOfRange {
+                token: "32".to_owned(),
+                field: Field::Day,
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_il_31_febbraio_is_out_of_range() {
+        assert_diagnostics(
+            "Ricordami il 31 febbraio",
+            "2025-01-10T10:00:00+01:00",
+            &[Diagnostic::OutOfRange {
+                token: "31".to_owned(),
+                field: Field::Day,
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_10_13_is_out_of_range() {
+        assert_diagnostics(
+            "Ricordami ogni 10/13",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::OutOfRange {
+                token: "10/13".to_owned(),
+                field: Field::Month,
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_tra_2000000000_settimane_is_out_of_range() {
+        assert_diagnostics(
+            "Ricordami tra 2000000000 settimane",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::OutOfRange {
+                token: "2000000000".to_owned(),
+                field: Field::Amount,
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_giorno_per_2000000000_settimane_is_out_of_range() {
+        assert_diagnostics(
+            "Ricordami ogni giorno per 2000000000 settimane",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::OutOfRange {
+                token: "2000000000".to_owned(),
+                field: Field::Amount,
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_tra_4294967295_minuti_is_out_of_range() {
+        assert_diagnostics(
+            "Ricordami tra 4294967295 minuti",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::OutOfRange {
+                token: "4294967295".to_owned(),
+                field: Field::Amount,
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_domani_pomeriggio_ignores_unknown_words() {
+        assert_diagnostics(
+            "Ricordami per domani pomeriggio",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::Ignored {
+                token: "pomeriggio".to_owned(),
+            }],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_il_12_05_2020_is_in_the_past() {
+        assert_diagnostics(
+            "Ricordami il 12/05/2020",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::Conflicting(Conflict::InThePast)],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_30_di_febbraio_never_fires() {
+        assert_diagnostics(
+            "Ricordami ogni 30 di febbraio",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::Conflicting(Conflict::NeverFires)],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_minuto_across_partial_hours_is_rejected() {
+        for msg in [
+            "Ricordami ogni minuto dalle 9 e 50 alle 10 e 5",
+            "Ricordami ogni minuto dalle 9 e 30 alle 11",
+            "Ricordami ogni minuto dalle 9 alle 10 e 30",
+            "Ricordami ogni minuto dalle 9 e 20 alle 9 e 15",
+        ] {
+            assert_diagnostics(
+                msg,
+                "2024-08-17T20:58:00+02:00",
+                &[Diagnostic::Conflicting(Conflict::PartialHourWindow)],
+            );
+        }
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_giorno_alle_13_is_clean() {
+        assert_diagnostics(
+            "Ricordami ogni giorno alle 13",
+            "2024-08-17T20:58:00+02:00",
+            &[],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_4_anni_ogni_29_febbraio_never_fires() {
+        assert_diagnostics(
+            "Ricordami ogni 4 anni ogni 29 febbraio",
+            "2025-08-17T20:58:00+02:00",
+            &[Diagnostic::Conflicting(Conflict::NeverFires)],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_ogni_3_anni_ogni_29_febbraio_fires() {
+        assert_diagnostics(
+            "Ricordami ogni 3 anni ogni 29 febbraio alle 9",
+            "2024-08-17T20:58:00+02:00",
+            &[Diagnostic::FarAway {
+                first: "2036-02-29T08:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+            }],
+        );
+        let now = "2024-08-17T20:58:00+02:00"
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        let next = try_parse(
+            "Ricordami ogni 3 anni ogni 29 febbraio alle 9"
+                .split(' ')
+                .collect(),
+            &now,
+        )
+        .into_schedule()
+        .and_then(|schedule| schedule.next_tick(&now));
+        assert_eq!(
+            Some("2036-02-29T08:00:00Z".parse::<DateTime<Utc>>().unwrap()),
+            next
+        );
+    }
+
+    fn assert_diagnostics(msg: &str, date_str: &str, expected: &[Diagnostic]) {
+        let outcome = try_parse(
+            msg.split(' ').collect(),
+            &date_str.parse::<DateTime<Utc>>().unwrap(),
+        );
+        assert_eq!(expected, outcome.diagnostics(), "Diagnostics for \"{msg}\"");
+    }
+}
+
+#[cfg(test)]
+mod ast_tests {
+    use super::explain;
+    use crate::text::ast::{
+        Amount, At, Bound, DateSpec, Every, Num, Rule, Since, TimeExpr, Unit, Until, When,
+    };
+    use chrono::{Month, Weekday};
+    use ntest::timeout;
+
+    #[test]
+    #[timeout(50)]
+    fn explain_tra_due_ore_e_mezza() {
+        assert_eq!(
+            Some(TimeExpr::Once(vec![When::After(vec![
+                Amount::Units {
+                    quantity: num(2, "due"),
+                    unit: Unit::Hour,
+                },
+                Amount::Half(None),
+            ])])),
+            explain("Ricordami tra due ore e mezza".split(' ').collect())
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn explain_il_3_dicembre_alle_13_e_20() {
+        assert_eq!(
+            Some(TimeExpr::Once(vec![
+                When::On(DateSpec::Day {
+                    day: num(3, "3"),
+                    month: Some(Month::December),
+                    year: None,
+                }),
+                When::At(At::Spoken {
+                    hour: num(13, "13"),
+                    minute: Some(num(20, "20")),
+                }),
+            ])),
+            explain("Ricordami il 3 dicembre alle 13 e 20".split(' ').collect())
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn explain_ogni_venerdi_alle_18_dal_29_agosto_al_2030() {
+        assert_eq!(
+            Some(TimeExpr::Recurrent(vec![
+                Rule::Every(vec![Every::Weekdays {
+                    positions: vec![],
+                    weekdays: vec![Weekday::Fri],
+                }]),
+                Rule::At(vec![At::Spoken {
+                    hour: num(18, "18"),
+                    minute: None,
+                }]),
+                Rule::Since(Since(vec![Bound::Date(DateSpec::Day {
+                    day: num(29, "29"),
+                    month: Some(Month::August),
+                    year: None,
+                })])),
+                Rule::Until(Until(vec![Bound::Year(num(2030, "2030"))])),
+            ])),
+            explain(
+                "Ricordami ogni venerdì alle 18 dal 29 agosto al 2030"
+                    .split(' ')
+                    .collect()
+            )
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn explain_unknown_expression() {
+        assert_eq!(None, explain("Ricordami qualcosa".split(' ').collect()));
+    }
+
+    fn num(value: u32, raw: &str) -> Num {
+        Num {
+            value,
+            raw: raw.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod cron_tests {
+    use super::try_parse;
+    use crate::{
+        interface::Schedule,
+        text::diagnostics::{Conflict, Diagnostic},
+    };
+    use chrono::{DateTime, Utc};
+    use chrono_tz::Europe;
+    use ntest::timeout;
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_cron_feriali_alle_9() {
+        assert_next_ticks(
+            r#"ricordami cron "0 9 * * 1-5""#,
+            "2024-08-16T20:58:00+02:00",
+            &[
+                "2024-08-19T09:00:00+02:00",
+                "2024-08-20T09:00:00+02:00",
+                "2024-08-21T09:00:00+02:00",
+            ],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_cron_with_steps_lists_and_names() {
+        assert_next_ticks(
+            "ricordami cron */20 8,12-13 * jan-mar,dec sat",
+            "2024-08-17T20:58:00+02:00",
+            &[
+                "2024-12-07T08:00:00+01:00",
+                "2024-12-07T08:20:00+01:00",
+                "2024-12-07T08:40:00+01:00",
+                "2024-12-07T12:00:00+01:00",
+            ],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_cron_sunday_is_0_and_7() {
+        assert_next_ticks(
+            "ricordami cron 30 18 * * 7",
+            "2024-08-17T20:58:00+02:00",
+            &["2024-08-18T18:30:00+02:00", "2024-08-25T18:30:00+02:00"],
+        );
+        assert_next_ticks(
+            "ricordami cron 30 18 * * 0",
+            "2024-08-17T20:58:00+02:00",
+            &["2024-08-18T18:30:00+02:00", "2024-08-25T18:30:00+02:00"],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_cron_rejects_invalid_expressions() {
+        for expression in [
+            "0 9 * *",
+            "60 9 * * *",
+            "0 9 0 * *",
+            "0 9 * 13 *",
+            "0 9 * * 8",
+            "0 9 * * 5-1",
+            "*/0 9 * * *",
+            "0 9 * * lun",
+            "0 9 1 * 1",
+            "0 9 */2 * mon",
+        ] {
+            let outcome = try_parse(
+                format!("ricordami cron {expression}").split(' ').collect(),
+                &Utc::now(),
+            );
+            assert!(outcome.schedule().is_none(), "\"{expression}\"");
+            assert!(
+                matches!(outcome.diagnostics(), [Diagnostic::InvalidCron { .. }]),
+                "\"{expression}\""
+            );
+        }
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_cron_restricts_one_day_field_at_a_time() {
+        assert_next_ticks(
+            "ricordami cron 0 9 */2 * *",
+            "2024-08-17T20:58:00+02:00",
+            &["2024-08-19T09:00:00+02:00", "2024-08-21T09:00:00+02:00"],
+        );
+        assert_next_ticks(
+            "ricordami cron 0 9 1-31 * mon",
+            "2024-08-17T20:58:00+02:00",
+            &["2024-08-19T09:00:00+02:00", "2024-08-26T09:00:00+02:00"],
+        );
+        assert_next_ticks(
+            "ricordami cron 0 9 15 * 0-6",
+            "2024-08-17T20:58:00+02:00",
+            &["2024-09-15T09:00:00+02:00", "2024-10-15T09:00:00+02:00"],
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn ricordami_cron_never_firing() {
+        let outcome = try_parse(
+            "ricordami cron 0 9 31 2 *".split(' ').collect(),
+            &"2024-08-17T20:58:00+02:00"
+                .parse::<DateTime<Utc>>()
+                .unwrap(),
+        );
+        assert_eq!(
+            &[Diagnostic::Conflicting(Conflict::NeverFires)],
+            outcome.diagnostics()
+        );
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn cron_round_trips() {
+        for expression in [
+            "0 9 * * 1-5",
+            "*/15 * * * *",
+            "0 8,20 1,15 * *",
+            "30 6 * 1-3,12 0,6",
+            "0 0 * */3 *",
+            "5 4 * * 0",
+            "0 12 31 12 *",
+        ] {
+            assert_eq!(
+                Some(expression.to_owned()),
+                cron_of(&format!("ricordami cron {expression}")),
+                "\"{expression}\""
+            );
+        }
+    }
+
+    #[test]
+    #[timeout(50)]
+    fn cron_of_parsed_expressions() {
+        assert_eq!(
+            Some("0 9 * * 1".to_owned()),
+            cron_of("ricordami ogni lunedì alle 9")
+        );
+        assert_eq!(
+            Some("0 9-18 * * *".to_owned()),
+            cron_of("ricordami ogni giorno dalle 9 alle 18")
+        );
+        assert_eq!(
+            Some("0 20 4,10 7,8 *".to_owned()),
+            cron_of("ricordami ogni 4 e 10 di luglio e agosto alle 20")
+        );
+        assert_eq!(None, cron_of("ricordami ogni primo lunedì alle 9"));
+        assert_eq!(None, cron_of("ricordami ogni 2 anni alle 9"));
+        assert_eq!(None, cron_of("ricordami domani alle 9"));
+    }
+
+    fn cron_of(msg: &str) -> Option<String> {
+        try_parse(
+            msg.split(' ').collect(),
+            &"2024-08-17T20:58:00+02:00"
+                .parse::<DateTime<Utc>>()
+                .unwrap(),
+        )
+        .schedule()
+        .and_then(Schedule::cron)
+    }
+
+    fn assert_next_ticks(msg: &str, date_str: &str, expected: &[&str]) {
+        let outcome = try_parse(
+            msg.split(' ').collect(),
+            &date_str.parse::<DateTime<Utc>>().unwrap(),
+        );
+        assert_eq!(&[] as &[Diagnostic], outcome.diagnostics(), "\"{msg}\"");
+        let schedule = outcome.schedule().unwrap();
+        let mut current = date_str.parse::<DateTime<Utc>>().unwrap();
+        let ticks = expected
+            .iter()
+            .map_while(|_| {
+                current = schedule.next_tick(&current)?;
+                Some(current.with_timezone(&Europe::Rome).to_rfc3339())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(expected, ticks, "Next ticks for \"{msg}\"");
+    }
+}
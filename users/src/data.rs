@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
@@ -38,3 +40,168 @@ impl User {
         matches!(self, User::SuperUser { .. })
     }
 }
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Member,
+    Guest,
+}
+
+impl Role {
+    pub fn capabilities(&self) -> &'static [Capability] {
+        match self {
+            Role::Admin => &Capability::ALL,
+            Role::Member => &[
+                Capability::Chat,
+                Capability::Ferrero,
+                Capability::Forecast,
+                Capability::Reminders,
+                Capability::Downloads,
+            ],
+            Role::Guest => &[Capability::Chat, Capability::Ferrero, Capability::Forecast],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Member => "member",
+            Role::Guest => "guest",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Role::Admin, Role::Member, Role::Guest]
+            .into_iter()
+            .find(|role| role.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    Chat,
+    Ferrero,
+    Forecast,
+    Reminders,
+    Downloads,
+    RemindersAdmin,
+    Users,
+    Shutdown,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 8] = [
+        Capability::Chat,
+        Capability::Ferrero,
+        Capability::Forecast,
+        Capability::Reminders,
+        Capability::Downloads,
+        Capability::RemindersAdmin,
+        Capability::Users,
+        Capability::Shutdown,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Chat => "chiacchiere",
+            Capability::Ferrero => "ferrero",
+            Capability::Forecast => "meteo",
+            Capability::Reminders => "promemoria",
+            Capability::Downloads => "download",
+            Capability::RemindersAdmin => "gestione-promemoria",
+            Capability::Users => "utenti",
+            Capability::Shutdown => "spegnimento",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|capability| capability.name() == name)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Permissions {
+    pub role: Role,
+    #[serde(default)]
+    pub granted: BTreeSet<Capability>,
+    #[serde(default)]
+    pub revoked: BTreeSet<Capability>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self::new(Role::Member)
+    }
+}
+
+impl Permissions {
+    pub fn new(role: Role) -> Self {
+        Self {
+            role,
+            granted: BTreeSet::new(),
+            revoked: BTreeSet::new(),
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        !self.revoked.contains(&capability)
+            && (self.granted.contains(&capability)
+                || self.role.capabilities().contains(&capability))
+    }
+
+    pub fn grant(&mut self, capability: Capability) {
+        self.revoked.remove(&capability);
+        if !self.role.capabilities().contains(&capability) {
+            self.granted.insert(capability);
+        }
+    }
+
+    pub fn revoke(&mut self, capability: Capability) {
+        self.granted.remove(&capability);
+        if self.role.capabilities().contains(&capability) {
+            self.revoked.insert(capability);
+        }
+    }
+
+    pub fn capabilities(&self) -> Vec<Capability> {
+        Capability::ALL
+            .into_iter()
+            .filter(|capability| self.allows(*capability))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Capability, Permissions, Role};
+
+    #[test]
+    fn roles_grant_their_default_capabilities() {
+        assert!(Permissions::new(Role::Admin).allows(Capability::Shutdown));
+        assert!(Permissions::new(Role::Member).allows(Capability::Downloads));
+        assert!(!Permissions::new(Role::Member).allows(Capability::Users));
+        assert!(!Permissions::new(Role::Guest).allows(Capability::Reminders));
+    }
+
+    #[test]
+    fn grants_and_revokes_single_capabilities() {
+        let mut permissions = Permissions::new(Role::Guest);
+        permissions.grant(Capability::Downloads);
+        assert!(permissions.allows(Capability::Downloads));
+        assert!(!permissions.allows(Capability::Users));
+
+        permissions.revoke(Capability::Forecast);
+        permissions.revoke(Capability::Downloads);
+        assert!(!permissions.allows(Capability::Forecast));
+        assert!(!permissions.allows(Capability::Downloads));
+        assert!(permissions.granted.is_empty());
+
+        permissions.grant(Capability::Forecast);
+        assert_eq!(Permissions::new(Role::Guest), permissions);
+    }
+}
@@ -1,7 +1,7 @@
 pub mod data;
 
 use async_trait::async_trait;
-use data::{User, UserId};
+use data::{Permissions, User, UserId};
 use redis::AsyncCommands;
 
 #[async_trait]
@@ -9,6 +9,10 @@ pub trait UserRepository {
     async fn get(&self, id: UserId) -> Result<Option<User>, String>;
     async fn set(&self, user: User) -> Result<bool, String>;
     async fn remove(&self, id: UserId) -> Result<bool, String>;
+    async fn rename(&self, user: User) -> Result<bool, String>;
+    async fn find_by_name(&self, name: &str) -> Result<Option<User>, String>;
+    async fn get_permissions(&self, id: UserId) -> Result<Option<Permissions>, String>;
+    async fn set_permissions(&self, id: UserId, permissions: &Permissions) -> Result<bool, String>;
 }
 
 #[derive(Clone)]
@@ -40,19 +44,95 @@ impl UserRepository for RedisUserRepository {
         let mut conn = self.connection.clone();
         let UserId(id) = user.id();
         let payload = serde_json::to_string(&user).map_err(|e| e.to_string())?;
-        let _ = conn
+        let _: () = conn
             .set(format!("user:{id}"), payload)
             .await
             .map_err(|e| format!("{e}"))?;
+        if let User::NamedUser { name, .. } = &user {
+            let _: () = conn
+                .set(username_key(name), id)
+                .await
+                .map_err(|e| format!("{e}"))?;
+        }
         Ok(true)
     }
 
     async fn remove(&self, UserId(id): UserId) -> Result<bool, String> {
         let mut conn = self.connection.clone();
-        let _ = conn
+        if let Some(User::NamedUser { name, .. }) = self.get(UserId(id)).await? {
+            let _: () = conn
+                .del(username_key(&name))
+                .await
+                .map_err(|e| format!("{e}"))?;
+        }
+        let _: () = conn
             .del(format!("user:{id}"))
             .await
             .map_err(|e| format!("{e}"))?;
+        let _: () = conn
+            .del(permissions_key(id))
+            .await
+            .map_err(|e| format!("{e}"))?;
         Ok(true)
     }
+
+    // swaps the username lookup only, so that permissions survive a rename
+    async fn rename(&self, user: User) -> Result<bool, String> {
+        let mut conn = self.connection.clone();
+        if let Some(User::NamedUser { name, .. }) = self.get(user.id()).await? {
+            let _: () = conn
+                .del(username_key(&name))
+                .await
+                .map_err(|e| format!("{e}"))?;
+        }
+        self.set(user).await
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Option<User>, String> {
+        let mut conn = self.connection.clone();
+        let id: Option<u64> = conn
+            .get(username_key(name))
+            .await
+            .map_err(|e| format!("{e}"))?;
+
+        match id {
+            Some(id) => self.get(UserId(id)).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_permissions(&self, UserId(id): UserId) -> Result<Option<Permissions>, String> {
+        let mut conn = self.connection.clone();
+        let permissions: Option<String> = conn
+            .get(permissions_key(id))
+            .await
+            .map_err(|e| format!("{e}"))?;
+
+        permissions
+            .map(|p| serde_json::from_str::<Permissions>(&p))
+            .transpose()
+            .map_err(|e| e.to_string())
+    }
+
+    async fn set_permissions(
+        &self,
+        UserId(id): UserId,
+        permissions: &Permissions,
+    ) -> Result<bool, String> {
+        let mut conn = self.connection.clone();
+        let payload = serde_json::to_string(permissions).map_err(|e| e.to_string())?;
+        let _: () = conn
+            .set(permissions_key(id), payload)
+            .await
+            .map_err(|e| format!("{e}"))?;
+        Ok(true)
+    }
+}
+
+fn username_key(name: &str) -> String {
+    format!("username:{}", name.trim_start_matches('@').to_lowercase())
+}
+
+fn permissions_key(id: u64) -> String {
+    format!("permissions:{id}")
 }